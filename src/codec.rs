@@ -0,0 +1,639 @@
+use crate::{
+    data_type::RespDataType,
+    util::{self, GenericError},
+};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::ops::Range;
+
+/// Decodes frames out of a read buffer, same shape as `tokio_util::codec::Decoder`.
+///
+/// Returns `Ok(None)` when the buffer does not hold a complete frame yet, in which case
+/// nothing is consumed and the caller should read more bytes into `src` and retry.
+pub trait Decoder {
+    type Item;
+    type Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error>;
+}
+
+/// Encodes frames into a write buffer, same shape as `tokio_util::codec::Encoder`.
+pub trait Encoder<Item> {
+    type Error;
+
+    fn encode(&mut self, item: Item, dst: &mut BytesMut) -> Result<(), Self::Error>;
+}
+
+/// RESP2/RESP3 framing, including inline commands.
+///
+/// Decoding is done in two iterative passes over the buffer: the first one only walks the
+/// headers to find out whether a whole frame is buffered (and how long it is) without
+/// allocating anything, the second one builds the frame using an explicit stack instead
+/// of recursion. The first pass remembers how far it got into an incomplete frame, so
+/// that a frame arriving over many reads is not scanned again from its start each time.
+#[derive(Debug, Clone, Default)]
+pub struct RespCodec {
+    limits: ProtocolLimits,
+    scan: ScanState,
+}
+
+/// Bounds on what a peer may send, checked as soon as the offending header is seen so
+/// that nothing gets allocated (or buffered) on the sole basis of a declared length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtocolLimits {
+    /// maximum length of a bulk string, like `proto-max-bulk-len`
+    pub max_bulk_len: usize,
+    /// maximum number of elements of an aggregate
+    pub max_multibulk_len: usize,
+    /// maximum number of aggregates nested into each other
+    pub max_nesting_depth: usize,
+    /// maximum length of an inline command or of any header line
+    pub max_inline_len: usize,
+}
+
+impl Default for ProtocolLimits {
+    fn default() -> Self {
+        ProtocolLimits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_multibulk_len: 1024 * 1024,
+            max_nesting_depth: 32,
+            max_inline_len: 64 * 1024,
+        }
+    }
+}
+
+impl RespCodec {
+    pub fn with_limits(limits: ProtocolLimits) -> RespCodec {
+        RespCodec {
+            limits,
+            scan: ScanState::default(),
+        }
+    }
+}
+
+/// Where [`scan`] stopped in the frame at the start of the buffer.
+#[derive(Debug, Clone, Default)]
+struct ScanState {
+    /// start of the first header that is not complete yet
+    pos: usize,
+    /// number of frames each open aggregate is still waiting for
+    pending: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Header {
+    Line(u8, Range<usize>),
+    Integer(i64),
+    Double(f64),
+    Boolean(bool),
+    Null,
+    Blob(u8, Range<usize>),
+    NullBulk,
+    /// aggregate tag and the number of frames it is made of (twice the size for maps)
+    Aggregate(u8, usize),
+    NullArray,
+    Inline(Range<usize>),
+}
+
+fn protocol_error<S: AsRef<str>>(message: S) -> GenericError {
+    format!("ERR Protocol error: {}", message.as_ref()).into()
+}
+
+/// Finds the line starting at `pos`, returning its content and where the next one starts.
+fn read_line(
+    buf: &[u8],
+    pos: usize,
+    limits: &ProtocolLimits,
+) -> util::Result<Option<(Range<usize>, usize)>> {
+    let line = buf[pos..].iter().position(|&x| x == b'\n');
+    if line.unwrap_or(buf.len() - pos) > limits.max_inline_len {
+        return Err(protocol_error("too big header line"));
+    }
+    match line {
+        None => Ok(None),
+        Some(i) if i == 0 || buf[pos + i - 1] != b'\r' => Err(protocol_error("expected <CR> <LF>")),
+        Some(i) => Ok(Some((pos..pos + i - 1, pos + i + 1))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(buf: &[u8], what: &str) -> util::Result<T> {
+    std::str::from_utf8(buf)
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| protocol_error(format!("invalid {}", what)))
+}
+
+/// Parses the header of the frame starting at `pos`, returning `None` if it is incomplete.
+///
+/// For blobs the header includes the payload, for aggregates only the element count.
+fn parse_header(
+    buf: &[u8],
+    pos: usize,
+    top_level: bool,
+    limits: &ProtocolLimits,
+) -> util::Result<Option<(Header, usize)>> {
+    let tag = match buf.get(pos) {
+        Some(&tag) => tag,
+        None => return Ok(None),
+    };
+
+    if top_level && !b"+-:$*_,#(=!~>%|".contains(&tag) {
+        // anything that is not a type tag is an inline command, e.g. typed via telnet
+        let line = buf[pos..].iter().position(|&x| x == b'\n');
+        if line.unwrap_or(buf.len() - pos) > limits.max_inline_len {
+            return Err(protocol_error("too big inline request"));
+        }
+        return Ok(line.map(|i| {
+            let end = pos + i;
+            let line_end = if end > pos && buf[end - 1] == b'\r' {
+                end - 1
+            } else {
+                end
+            };
+            (Header::Inline(pos..line_end), end + 1)
+        }));
+    }
+
+    let (line, next) = match read_line(buf, pos + 1, limits)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let content = &buf[line.clone()];
+
+    let header = match tag {
+        b'+' | b'-' | b'(' => Header::Line(tag, line),
+        b':' => Header::Integer(parse_number(content, "integer")?),
+        b',' => Header::Double(parse_number(content, "double")?),
+        b'#' => match content {
+            b"t" => Header::Boolean(true),
+            b"f" => Header::Boolean(false),
+            _ => return Err(protocol_error("invalid boolean")),
+        },
+        b'_' => Header::Null,
+        b'$' | b'!' | b'=' => {
+            let n = parse_number::<i64>(content, "bulk length")?;
+            if n < 0 {
+                if tag != b'$' {
+                    return Err(protocol_error("invalid bulk length"));
+                }
+                return Ok(Some((Header::NullBulk, next)));
+            }
+            if n as u64 > limits.max_bulk_len as u64 {
+                return Err(protocol_error("invalid bulk length"));
+            }
+            let n = n as usize;
+            if buf.len() < next + n + 2 {
+                return Ok(None);
+            }
+            if &buf[next + n..next + n + 2] != b"\r\n" {
+                return Err(protocol_error("expected <CR> <LF>"));
+            }
+            if tag == b'=' && (n < 4 || buf[next + 3] != b':') {
+                return Err(protocol_error("verbatim string is missing its format"));
+            }
+            return Ok(Some((Header::Blob(tag, next..next + n), next + n + 2)));
+        }
+        b'*' | b'~' | b'>' | b'%' | b'|' => {
+            let n = parse_number::<i64>(content, "multibulk length")?;
+            if n < 0 {
+                if tag != b'*' {
+                    return Err(protocol_error("invalid multibulk length"));
+                }
+                Header::NullArray
+            } else if n as u64 > limits.max_multibulk_len as u64 {
+                return Err(protocol_error("invalid multibulk length"));
+            } else if tag == b'%' || tag == b'|' {
+                Header::Aggregate(tag, n as usize * 2)
+            } else {
+                Header::Aggregate(tag, n as usize)
+            }
+        }
+        x => return Err(protocol_error(format!("unknown message tag '{}'", x))),
+    };
+    Ok(Some((header, next)))
+}
+
+/// Returns the length of the frame at the start of `buf`, or `None` if it is incomplete.
+///
+/// Resumes from `state`, which is left where the frame stops being complete for the next
+/// call, after more bytes were appended to `buf`.
+fn scan(buf: &[u8], state: &mut ScanState, limits: &ProtocolLimits) -> util::Result<Option<usize>> {
+    let ScanState { pos, pending } = state;
+    loop {
+        let top_level = pending.is_empty() && *pos == 0;
+        let (header, next) = match parse_header(buf, *pos, top_level, limits)? {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+        *pos = next;
+
+        if let Header::Aggregate(_, n) = header {
+            if n > 0 {
+                if pending.len() >= limits.max_nesting_depth {
+                    return Err(protocol_error("too deeply nested aggregate"));
+                }
+                pending.push(n);
+                continue;
+            }
+        }
+
+        // a complete frame, which might in turn complete its parents
+        loop {
+            match pending.last_mut() {
+                None => return Ok(Some(std::mem::take(pos))),
+                Some(n) => {
+                    *n -= 1;
+                    if *n > 0 {
+                        break;
+                    }
+                    pending.pop();
+                }
+            }
+        }
+    }
+}
+
+struct PartialAggregate {
+    tag: u8,
+    items: Vec<RespDataType>,
+    remaining: usize,
+}
+
+fn aggregate(tag: u8, items: Vec<RespDataType>) -> RespDataType {
+    fn pairs(items: Vec<RespDataType>) -> Vec<(RespDataType, RespDataType)> {
+        let mut pairs = Vec::with_capacity(items.len() / 2);
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            pairs.push((key, value));
+        }
+        pairs
+    }
+
+    match tag {
+        b'~' => RespDataType::Sets(items),
+        b'>' => RespDataType::Pushes(items),
+        b'%' => RespDataType::Maps(pairs(items)),
+        b'|' => RespDataType::Attributes(pairs(items)),
+        _ => RespDataType::Arrays(Some(items)),
+    }
+}
+
+/// Builds the frame held by `buf`, which must have been validated by [`scan`] first.
+///
+/// Bulk strings are slices of `buf` rather than copies. Returns `None` for blank inline
+/// lines, which are simply skipped.
+fn build(buf: &Bytes, limits: &ProtocolLimits) -> util::Result<Option<RespDataType>> {
+    let mut pos = 0;
+    let mut stack: Vec<PartialAggregate> = vec![];
+    loop {
+        let top_level = stack.is_empty() && pos == 0;
+        let (header, next) = parse_header(buf, pos, top_level, limits)?
+            .ok_or_else(|| protocol_error("incomplete frame"))?;
+        pos = next;
+
+        let mut value = match header {
+            Header::Line(b'+', range) => RespDataType::SimpleStrings(buf[range].to_owned()),
+            Header::Line(b'-', range) => RespDataType::Errors(buf[range].to_owned()),
+            Header::Line(_, range) => RespDataType::BigNumbers(buf[range].to_owned()),
+            Header::Integer(n) => RespDataType::Integers(n),
+            Header::Double(n) => RespDataType::Doubles(n),
+            Header::Boolean(x) => RespDataType::Booleans(x),
+            Header::Null => RespDataType::Null,
+            Header::Blob(b'$', range) => RespDataType::BulkStrings(Some(buf.slice(range))),
+            Header::Blob(b'!', range) => RespDataType::BlobErrors(buf[range].to_owned()),
+            Header::Blob(_, range) => {
+                let (format, str) = buf[range].split_at(4);
+                RespDataType::VerbatimStrings([format[0], format[1], format[2]], str.to_owned())
+            }
+            Header::NullBulk => RespDataType::BulkStrings(None),
+            Header::NullArray => RespDataType::Arrays(None),
+            Header::Aggregate(tag, 0) => aggregate(tag, vec![]),
+            Header::Aggregate(tag, n) => {
+                stack.push(PartialAggregate {
+                    tag,
+                    items: Vec::with_capacity(n),
+                    remaining: n,
+                });
+                continue;
+            }
+            Header::Inline(range) => {
+                let args =
+                    util::split_args(&buf[range]).map_err(|e| protocol_error(e.to_string()))?;
+                if args.is_empty() {
+                    // blank lines are ignored, just like redis does
+                    return Ok(None);
+                }
+                RespDataType::Arrays(Some(
+                    args.into_iter()
+                        .map(|x| RespDataType::BulkStrings(Some(x.into())))
+                        .collect(),
+                ))
+            }
+        };
+
+        loop {
+            match stack.last_mut() {
+                None => return Ok(Some(value)),
+                Some(top) => {
+                    top.items.push(value);
+                    top.remaining -= 1;
+                    if top.remaining > 0 {
+                        break;
+                    }
+                    let top = stack.pop().expect("stack is not empty");
+                    value = aggregate(top.tag, top.items);
+                }
+            }
+        }
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = RespDataType;
+    type Error = GenericError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let len = match scan(src, &mut self.scan, &self.limits) {
+                Ok(Some(len)) => len,
+                Ok(None) => return Ok(None),
+                Err(e) => {
+                    self.scan = ScanState::default();
+                    return Err(e);
+                }
+            };
+            let frame = src.split_to(len).freeze();
+            if let Some(value) = build(&frame, &self.limits)? {
+                return Ok(Some(value));
+            }
+        }
+    }
+}
+
+fn write_header(dst: &mut BytesMut, tag: u8, len: usize) {
+    dst.put_u8(tag);
+    dst.put_slice(len.to_string().as_bytes());
+    dst.put_slice(b"\r\n");
+}
+
+fn write_line(dst: &mut BytesMut, tag: u8, line: &[u8]) {
+    dst.reserve(line.len() + 3);
+    dst.put_u8(tag);
+    dst.put_slice(line);
+    dst.put_slice(b"\r\n");
+}
+
+fn write_blob(dst: &mut BytesMut, tag: u8, blob: &[u8]) {
+    write_header(dst, tag, blob.len());
+    dst.reserve(blob.len() + 2);
+    dst.put_slice(blob);
+    dst.put_slice(b"\r\n");
+}
+
+impl<'a> Encoder<&'a RespDataType> for RespCodec {
+    type Error = GenericError;
+
+    fn encode(&mut self, item: &'a RespDataType, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let tag = item.tag();
+        match item {
+            RespDataType::SimpleStrings(str)
+            | RespDataType::Errors(str)
+            | RespDataType::BigNumbers(str) => write_line(dst, tag, str),
+            RespDataType::Integers(num) => write_line(dst, tag, num.to_string().as_bytes()),
+            RespDataType::Doubles(num) => {
+                write_line(dst, tag, util::format_double(*num).as_bytes())
+            }
+            RespDataType::Booleans(value) => write_line(dst, tag, if *value { b"t" } else { b"f" }),
+            RespDataType::Null | RespDataType::BulkStrings(None) | RespDataType::Arrays(None) => {
+                write_line(dst, tag, if tag == b'_' { b"" } else { b"-1" })
+            }
+            RespDataType::BulkStrings(Some(str)) => write_blob(dst, tag, str),
+            RespDataType::BlobErrors(err) => write_blob(dst, tag, err),
+            RespDataType::VerbatimStrings(format, str) => {
+                write_header(dst, tag, str.len() + 4);
+                dst.reserve(str.len() + 6);
+                dst.put_slice(format);
+                dst.put_u8(b':');
+                dst.put_slice(str);
+                dst.put_slice(b"\r\n");
+            }
+            RespDataType::Arrays(Some(arr))
+            | RespDataType::Sets(arr)
+            | RespDataType::Pushes(arr) => {
+                write_header(dst, tag, arr.len());
+                for elem in arr {
+                    self.encode(elem, dst)?;
+                }
+            }
+            RespDataType::Maps(map) | RespDataType::Attributes(map) => {
+                write_header(dst, tag, map.len());
+                for (key, value) in map {
+                    self.encode(key, dst)?;
+                    self.encode(value, dst)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Encoder<RespDataType> for RespCodec {
+    type Error = GenericError;
+
+    fn encode(&mut self, item: RespDataType, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encode(&item, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, ProtocolLimits, RespCodec};
+    use crate::{data_type::RespDataType, util::Result};
+    use bytes::BytesMut;
+
+    #[test]
+    fn test_incomplete_frames() -> Result<()> {
+        let frame = b"*2\r\n$5\r\nhello\r\n*1\r\n:1\r\n";
+        for i in 0..frame.len() {
+            let mut buf = BytesMut::from(&frame[..i]);
+            assert_eq!(RespCodec::default().decode(&mut buf)?, None);
+            // nothing must be consumed until the whole frame is there
+            assert_eq!(buf.len(), i);
+        }
+
+        let mut buf = BytesMut::from(&frame[..]);
+        assert_eq!(
+            RespCodec::default().decode(&mut buf)?,
+            Some(RespDataType::arrays(vec![
+                RespDataType::bulk_strings("hello"),
+                RespDataType::arrays(vec![RespDataType::integers(1)]),
+            ]))
+        );
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_frames_split_over_many_reads() -> Result<()> {
+        let frames = b"*2\r\n$5\r\nhello\r\n*1\r\n:1\r\n+OK\r\n";
+        let mut codec = RespCodec::default();
+        let mut buf = BytesMut::new();
+        let mut decoded = vec![];
+        for byte in frames.iter() {
+            buf.extend_from_slice(&[*byte]);
+            if let Some(value) = codec.decode(&mut buf)? {
+                decoded.push(value);
+            }
+        }
+        assert_eq!(
+            decoded,
+            vec![
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("hello"),
+                    RespDataType::arrays(vec![RespDataType::integers(1)]),
+                ]),
+                RespDataType::simple_strings("OK"),
+            ]
+        );
+        assert!(buf.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bulk_strings_are_not_copied() -> Result<()> {
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n"[..]);
+        let start = buf.as_ptr() as usize;
+        let end = start + buf.len();
+
+        let args = match RespCodec::default().decode(&mut buf)? {
+            Some(RespDataType::Arrays(Some(args))) => args,
+            x => panic!("unexpected {:?}", x),
+        };
+        for arg in args {
+            let str = arg.into_bulk_strings()?.expect("not null");
+            let ptr = str.as_ptr() as usize;
+            assert!(start <= ptr && ptr + str.len() <= end);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_frames() -> Result<()> {
+        let mut buf = BytesMut::from(&b"+OK\r\n\r\nPING\r\n:1\r\n$3\r\nfo"[..]);
+        assert_eq!(
+            RespCodec::default().decode(&mut buf)?,
+            Some(RespDataType::simple_strings("OK"))
+        );
+        assert_eq!(
+            RespCodec::default().decode(&mut buf)?,
+            Some(RespDataType::arrays(vec![RespDataType::bulk_strings(
+                "PING"
+            )]))
+        );
+        assert_eq!(
+            RespCodec::default().decode(&mut buf)?,
+            Some(RespDataType::integers(1))
+        );
+        assert_eq!(RespCodec::default().decode(&mut buf)?, None);
+        assert_eq!(&buf[..], b"$3\r\nfo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_deeply_nested_frame() -> Result<()> {
+        let depth = 100_000;
+        let mut buf = BytesMut::new();
+        for _ in 0..depth {
+            buf.extend_from_slice(b"*1\r\n");
+        }
+        buf.extend_from_slice(b":1\r\n");
+
+        let mut codec = RespCodec::with_limits(ProtocolLimits {
+            max_nesting_depth: usize::MAX,
+            ..Default::default()
+        });
+        let mut value = codec.decode(&mut buf)?.expect("frame is complete");
+        for _ in 0..depth {
+            value = match value {
+                RespDataType::Arrays(Some(mut x)) => x.pop().expect("one element"),
+                x => panic!("unexpected {:?}", x),
+            };
+        }
+        assert_eq!(value, RespDataType::integers(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        for frame in &[&b":abc\r\n"[..], b"$3\r\nfoobar\r\n", b"+OK\n", b"#x\r\n"] {
+            let mut buf = BytesMut::from(*frame);
+            assert!(RespCodec::default().decode(&mut buf).is_err());
+        }
+    }
+
+    fn assert_rejected(limits: ProtocolLimits, frame: &[u8]) {
+        let mut buf = BytesMut::from(frame);
+        let capacity = buf.capacity();
+        let err = RespCodec::with_limits(limits)
+            .decode(&mut buf)
+            .expect_err("frame must be rejected");
+        assert!(err.to_string().starts_with("ERR Protocol error"));
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_oversized_bulk_strings() -> Result<()> {
+        let limits = ProtocolLimits {
+            max_bulk_len: 16,
+            ..Default::default()
+        };
+        // only the header is buffered, the declared payload is never waited for
+        assert_rejected(limits, b"$17\r\n");
+        assert_rejected(limits, b"*1\r\n$9223372036854775807\r\n");
+        assert_rejected(ProtocolLimits::default(), b"$536870913\r\n");
+
+        let mut buf = BytesMut::from(&b"$16\r\n0123456789abcdef\r\n"[..]);
+        assert_eq!(
+            RespCodec::with_limits(limits).decode(&mut buf)?,
+            Some(RespDataType::bulk_strings("0123456789abcdef"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_oversized_aggregates() {
+        let limits = ProtocolLimits {
+            max_multibulk_len: 4,
+            ..Default::default()
+        };
+        assert_rejected(limits, b"*5\r\n");
+        assert_rejected(limits, b"%5\r\n");
+        assert_rejected(limits, b"*1\r\n~1000000\r\n");
+        assert_rejected(ProtocolLimits::default(), b"*2147483647\r\n");
+    }
+
+    #[test]
+    fn test_too_deeply_nested() {
+        let limits = ProtocolLimits {
+            max_nesting_depth: 3,
+            ..Default::default()
+        };
+        assert_rejected(limits, b"*1\r\n*1\r\n*1\r\n*1\r\n");
+
+        let mut buf = BytesMut::from(&b"*1\r\n*1\r\n*1\r\n:1\r\n"[..]);
+        assert!(matches!(
+            RespCodec::with_limits(limits).decode(&mut buf),
+            Ok(Some(_))
+        ));
+    }
+
+    #[test]
+    fn test_oversized_lines() {
+        let limits = ProtocolLimits {
+            max_inline_len: 8,
+            ..Default::default()
+        };
+        assert_rejected(limits, b"SET foo bar");
+        assert_rejected(limits, b"*100000000000000000000");
+        assert_rejected(limits, b"+a very long status line");
+    }
+}
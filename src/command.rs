@@ -1,14 +1,34 @@
-use crate::data_type::RedisDataTypeWithTTL;
 use crate::{
-    data_type::{RedisDataType, RespDataType},
+    data_type::{Key, ProtocolVersion, RespDataType},
+    db::Db,
+    server::Connection,
     util::{self, BoxFuture, GenericError},
 };
-use std::{
-    collections::HashMap,
-    convert::{TryFrom, TryInto},
-    time::Duration,
-};
-use tokio::time::Instant;
+use bytes::Bytes;
+use std::{convert::TryFrom, time::Duration};
+
+mod bitmap;
+mod expire;
+mod geo;
+mod hash;
+mod hyperloglog;
+mod keyspace;
+mod list;
+mod set;
+mod stream;
+mod string;
+mod zset;
+pub use bitmap::*;
+pub use expire::*;
+pub use geo::*;
+pub use hash::*;
+pub use hyperloglog::*;
+pub use keyspace::*;
+pub use list::*;
+pub use set::*;
+pub use stream::*;
+pub use string::*;
+pub use zset::*;
 
 #[derive(Debug, Clone)]
 pub struct Ping {
@@ -21,23 +41,377 @@ pub struct Echo {
 }
 
 #[derive(Debug, Clone)]
-pub struct Set {
-    pub key: RespDataType,
-    pub value: RespDataType,
-    pub expiry: Option<Duration>,
+pub struct Hello {
+    pub protocol: Option<ProtocolVersion>,
+    pub auth: Option<(Bytes, Bytes)>,
+    pub client_name: Option<Bytes>,
 }
 
 #[derive(Debug, Clone)]
-pub struct Get {
-    pub key: RespDataType,
+pub struct Watch {
+    pub keys: Vec<Key>,
 }
 
 #[derive(Debug, Clone)]
 pub enum RespCommand {
     Ping(Ping),
     Echo(Echo),
+    Hello(Hello),
+    Multi,
+    Exec,
+    Discard,
+    Watch(Watch),
+    Unwatch,
     Set(Set),
+    SetNx(SetNx),
     Get(Get),
+    IncrBy(IncrBy),
+    IncrByFloat(IncrByFloat),
+    Append(Append),
+    Strlen(Strlen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    MGet(MGet),
+    MSet(MSet),
+    GetDel(GetDel),
+    GetEx(GetEx),
+    Del(Del),
+    Exists(Exists),
+    Type(Type),
+    Rename(Rename),
+    Copy(Copy),
+    RandomKey(RandomKey),
+    DbSize(DbSize),
+    Flush(Flush),
+    Info(Info),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Push(Push),
+    Pop(Pop),
+    LRange(LRange),
+    LIndex(LIndex),
+    LSet(LSet),
+    LInsert(LInsert),
+    LRem(LRem),
+    LTrim(LTrim),
+    LLen(LLen),
+    LPos(LPos),
+    LMove(LMove),
+    LMPop(LMPop),
+    BPop(BPop),
+    BLMove(BLMove),
+    BLMPop(BLMPop),
+    HSet(HSet),
+    HSetNx(HSetNx),
+    HGet(HGet),
+    HMGet(HMGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    HLen(HLen),
+    HExists(HExists),
+    HStrlen(HStrlen),
+    HIncrBy(HIncrBy),
+    HIncrByFloat(HIncrByFloat),
+    HRandField(HRandField),
+    HScan(HScan),
+    HExpire(HExpire),
+    HTtl(HTtl),
+    HPersist(HPersist),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    SMIsMember(SMIsMember),
+    SCard(SCard),
+    SPop(SPop),
+    SRandMember(SRandMember),
+    SMove(SMove),
+    SetOp(SetOp),
+    SInterCard(SInterCard),
+    SScan(SScan),
+    ZAdd(ZAdd),
+    ZRem(ZRem),
+    ZCard(ZCard),
+    ZScore(ZScore),
+    ZRank(ZRank),
+    ZCount(ZCount),
+    ZRange(ZRange),
+    ZPop(ZPop),
+    BZPop(BZPop),
+    ZStore(ZStore),
+    XAdd(XAdd),
+    XRange(XRange),
+    XLen(XLen),
+    XTrim(XTrim),
+    XDel(XDel),
+    XRead(XRead),
+    XGroup(XGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    XAutoClaim(XAutoClaim),
+    XInfo(XInfo),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitPos(BitPos),
+    BitOp(BitOp),
+    Bitfield(Bitfield),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    GeoDist(GeoDist),
+    GeoHash(GeoHash),
+    GeoPos(GeoPos),
+    GeoSearch(GeoSearch),
+}
+
+impl RespCommand {
+    /// The command as one running against the keyspace, `None` for the commands that
+    /// only touch the connection.
+    pub fn as_keyspace_command(&self) -> Option<&(dyn for<'a> Command<'a, Db> + Send + Sync)> {
+        Some(match self {
+            RespCommand::Ping(_)
+            | RespCommand::Echo(_)
+            | RespCommand::Hello(_)
+            | RespCommand::Multi
+            | RespCommand::Exec
+            | RespCommand::Discard
+            | RespCommand::Watch(_)
+            | RespCommand::Unwatch => return None,
+            RespCommand::Set(x) => x,
+            RespCommand::SetNx(x) => x,
+            RespCommand::Get(x) => x,
+            RespCommand::IncrBy(x) => x,
+            RespCommand::IncrByFloat(x) => x,
+            RespCommand::Append(x) => x,
+            RespCommand::Strlen(x) => x,
+            RespCommand::GetRange(x) => x,
+            RespCommand::SetRange(x) => x,
+            RespCommand::MGet(x) => x,
+            RespCommand::MSet(x) => x,
+            RespCommand::GetDel(x) => x,
+            RespCommand::GetEx(x) => x,
+            RespCommand::Del(x) => x,
+            RespCommand::Exists(x) => x,
+            RespCommand::Type(x) => x,
+            RespCommand::Rename(x) => x,
+            RespCommand::Copy(x) => x,
+            RespCommand::RandomKey(x) => x,
+            RespCommand::DbSize(x) => x,
+            RespCommand::Flush(x) => x,
+            RespCommand::Info(x) => x,
+            RespCommand::Expire(x) => x,
+            RespCommand::Ttl(x) => x,
+            RespCommand::Persist(x) => x,
+            RespCommand::Push(x) => x,
+            RespCommand::Pop(x) => x,
+            RespCommand::LRange(x) => x,
+            RespCommand::LIndex(x) => x,
+            RespCommand::LSet(x) => x,
+            RespCommand::LInsert(x) => x,
+            RespCommand::LRem(x) => x,
+            RespCommand::LTrim(x) => x,
+            RespCommand::LLen(x) => x,
+            RespCommand::LPos(x) => x,
+            RespCommand::LMove(x) => x,
+            RespCommand::LMPop(x) => x,
+            RespCommand::BPop(x) => x,
+            RespCommand::BLMove(x) => x,
+            RespCommand::BLMPop(x) => x,
+            RespCommand::HSet(x) => x,
+            RespCommand::HSetNx(x) => x,
+            RespCommand::HGet(x) => x,
+            RespCommand::HMGet(x) => x,
+            RespCommand::HDel(x) => x,
+            RespCommand::HGetAll(x) => x,
+            RespCommand::HLen(x) => x,
+            RespCommand::HExists(x) => x,
+            RespCommand::HStrlen(x) => x,
+            RespCommand::HIncrBy(x) => x,
+            RespCommand::HIncrByFloat(x) => x,
+            RespCommand::HRandField(x) => x,
+            RespCommand::HScan(x) => x,
+            RespCommand::HExpire(x) => x,
+            RespCommand::HTtl(x) => x,
+            RespCommand::HPersist(x) => x,
+            RespCommand::SAdd(x) => x,
+            RespCommand::SRem(x) => x,
+            RespCommand::SMembers(x) => x,
+            RespCommand::SIsMember(x) => x,
+            RespCommand::SMIsMember(x) => x,
+            RespCommand::SCard(x) => x,
+            RespCommand::SPop(x) => x,
+            RespCommand::SRandMember(x) => x,
+            RespCommand::SMove(x) => x,
+            RespCommand::SetOp(x) => x,
+            RespCommand::SInterCard(x) => x,
+            RespCommand::SScan(x) => x,
+            RespCommand::ZAdd(x) => x,
+            RespCommand::ZRem(x) => x,
+            RespCommand::ZCard(x) => x,
+            RespCommand::ZScore(x) => x,
+            RespCommand::ZRank(x) => x,
+            RespCommand::ZCount(x) => x,
+            RespCommand::ZRange(x) => x,
+            RespCommand::ZPop(x) => x,
+            RespCommand::BZPop(x) => x,
+            RespCommand::ZStore(x) => x,
+            RespCommand::XAdd(x) => x,
+            RespCommand::XRange(x) => x,
+            RespCommand::XLen(x) => x,
+            RespCommand::XTrim(x) => x,
+            RespCommand::XDel(x) => x,
+            RespCommand::XRead(x) => x,
+            RespCommand::XGroup(x) => x,
+            RespCommand::XAck(x) => x,
+            RespCommand::XPending(x) => x,
+            RespCommand::XClaim(x) => x,
+            RespCommand::XAutoClaim(x) => x,
+            RespCommand::XInfo(x) => x,
+            RespCommand::SetBit(x) => x,
+            RespCommand::GetBit(x) => x,
+            RespCommand::BitCount(x) => x,
+            RespCommand::BitPos(x) => x,
+            RespCommand::BitOp(x) => x,
+            RespCommand::Bitfield(x) => x,
+            RespCommand::PfAdd(x) => x,
+            RespCommand::PfCount(x) => x,
+            RespCommand::PfMerge(x) => x,
+            RespCommand::GeoDist(x) => x,
+            RespCommand::GeoHash(x) => x,
+            RespCommand::GeoPos(x) => x,
+            RespCommand::GeoSearch(x) => x,
+        })
+    }
+
+    /// The command as one that blocks when it cannot be served right away.
+    pub fn as_blocking_command(&self) -> Option<&(dyn BlockingCommand + Send + Sync)> {
+        match self {
+            RespCommand::BPop(x) => Some(x),
+            RespCommand::BLMove(x) => Some(x),
+            RespCommand::BLMPop(x) => Some(x),
+            RespCommand::BZPop(x) => Some(x),
+            RespCommand::XRead(x) if x.block.is_some() => Some(x),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) const WRONG_TYPE: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+pub(crate) const SYNTAX_ERROR: &str = "ERR syntax error";
+
+/// Cursor over the arguments of a command, turning missing or malformed arguments into
+/// the same errors redis replies with.
+pub(crate) struct Arguments<'a> {
+    command: &'a str,
+    args: &'a [RespDataType],
+}
+
+impl<'a> Arguments<'a> {
+    pub fn new(command: &'a str, args: &'a [RespDataType]) -> Arguments<'a> {
+        Arguments { command, args }
+    }
+
+    pub fn wrong_arity(&self) -> GenericError {
+        format!(
+            "ERR wrong number of arguments for '{}' command",
+            self.command
+        )
+        .into()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    /// Fails with the arity error unless exactly `len` arguments are left.
+    pub fn expect_len(&self, len: usize) -> util::Result<()> {
+        if self.args.len() == len {
+            Ok(())
+        } else {
+            Err(self.wrong_arity())
+        }
+    }
+
+    pub fn next_bytes(&mut self) -> util::Result<Bytes> {
+        let (first, rest) = self.args.split_first().ok_or_else(|| self.wrong_arity())?;
+        self.args = rest;
+        match first {
+            RespDataType::BulkStrings(Some(x)) => Ok(x.clone()),
+            RespDataType::SimpleStrings(x) => Ok(Bytes::copy_from_slice(x)),
+            RespDataType::Integers(n) => Ok(n.to_string().into()),
+            _ => Err("ERR Protocol error: expected bulk string".into()),
+        }
+    }
+
+    pub fn next_key(&mut self) -> util::Result<Key> {
+        self.next_bytes().map(Key::from)
+    }
+
+    pub fn next_integer(&mut self) -> util::Result<i64> {
+        let arg = self.next_bytes()?;
+        util::parse_integer(&arg)
+            .ok_or_else(|| "ERR value is not an integer or out of range".into())
+    }
+
+    /// Returns the next argument as the count of a random pick, negative when the same
+    /// element may be picked more than once.
+    pub fn next_random_count(&mut self) -> util::Result<i64> {
+        let n = self.next_integer()?;
+        if !(-(i64::MAX / 2)..=i64::MAX / 2).contains(&n) {
+            return Err("ERR value is out of range".into());
+        }
+        Ok(n)
+    }
+
+    /// Returns the next argument as a count, which cannot be negative.
+    pub fn next_positive(&mut self) -> util::Result<usize> {
+        let n = self.next_integer()?;
+        if n < 0 {
+            return Err("ERR value is out of range, must be positive".into());
+        }
+        Ok(n as usize)
+    }
+
+    /// Returns the next argument as a timeout in seconds, `None` for 0 which is waiting
+    /// forever.
+    pub fn next_timeout(&mut self) -> util::Result<Option<Duration>> {
+        let arg = self.next_bytes()?;
+        let timeout = util::parse_float(&arg)
+            .filter(|x| x.is_finite())
+            .ok_or("ERR timeout is not a float or out of range")?;
+        if timeout < 0.0 {
+            return Err("ERR timeout is negative".into());
+        }
+        Ok(if timeout == 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(timeout))
+        })
+    }
+
+    pub fn next_float(&mut self) -> util::Result<f64> {
+        let arg = self.next_bytes()?;
+        util::parse_float(&arg).ok_or_else(|| "ERR value is not a valid float".into())
+    }
+
+    /// Returns the next argument lowercased, for matching against option names.
+    pub fn next_keyword(&mut self) -> util::Result<String> {
+        self.next_bytes()
+            .map(|x| String::from_utf8_lossy(&x).to_ascii_lowercase())
+    }
+
+    /// Returns the next argument lowercased, without moving past it.
+    pub fn peek_keyword(&self) -> Option<String> {
+        Arguments::new(self.command, self.args).next_keyword().ok()
+    }
 }
 
 impl TryFrom<RespDataType> for RespCommand {
@@ -72,72 +446,453 @@ impl TryFrom<RespDataType> for RespCommand {
 
                         Ok(RespCommand::Echo(Echo { message }))
                     }
-                    "set" => {
-                        let key = args
-                            .get(0)
-                            .ok_or::<GenericError>("missing argument key".into())?
-                            .expect_bulk_strings()
-                            .map_err::<GenericError, _>(|_| "key is not bulk string".into())?
-                            .clone();
-                        let value = args
-                            .get(1)
-                            .ok_or::<GenericError>("missing argument value".into())?
-                            .expect_bulk_strings()
-                            .map_err::<GenericError, _>(|_| "value is not bulk string".into())?
-                            .clone();
-                        let expiry_value = args.get(3);
-                        match args.get(2) {
-                            None if expiry_value.is_none() => Ok(RespCommand::Set(Set {
-                                key,
-                                value,
-                                expiry: None,
-                            })),
-                            Some(expiry_type) if expiry_value.is_some() => {
-                                let ttl_type = expiry_type
-                                    .clone()
+                    "set" => Set::parse(Arguments::new("set", args)).map(RespCommand::Set),
+                    "setnx" => SetNx::parse(Arguments::new("setnx", args)).map(RespCommand::SetNx),
+                    "setex" => Set::parse_with_expiry(Arguments::new("setex", args), "ex")
+                        .map(RespCommand::Set),
+                    "psetex" => Set::parse_with_expiry(Arguments::new("psetex", args), "px")
+                        .map(RespCommand::Set),
+                    "get" => Get::parse(Arguments::new("get", args)).map(RespCommand::Get),
+                    "incr" => IncrBy::parse(Arguments::new("incr", args), Some(1))
+                        .map(RespCommand::IncrBy),
+                    "decr" => IncrBy::parse(Arguments::new("decr", args), Some(-1))
+                        .map(RespCommand::IncrBy),
+                    "incrby" => {
+                        IncrBy::parse(Arguments::new("incrby", args), None).map(RespCommand::IncrBy)
+                    }
+                    "decrby" => IncrBy::parse_decrement(Arguments::new("decrby", args))
+                        .map(RespCommand::IncrBy),
+                    "incrbyfloat" => IncrByFloat::parse(Arguments::new("incrbyfloat", args))
+                        .map(RespCommand::IncrByFloat),
+                    "append" => {
+                        Append::parse(Arguments::new("append", args)).map(RespCommand::Append)
+                    }
+                    "strlen" => {
+                        Strlen::parse(Arguments::new("strlen", args)).map(RespCommand::Strlen)
+                    }
+                    "getrange" | "substr" => {
+                        GetRange::parse(Arguments::new(&command, args)).map(RespCommand::GetRange)
+                    }
+                    "setrange" => {
+                        SetRange::parse(Arguments::new("setrange", args)).map(RespCommand::SetRange)
+                    }
+                    "mget" => MGet::parse(Arguments::new("mget", args)).map(RespCommand::MGet),
+                    "mset" => {
+                        MSet::parse(Arguments::new("mset", args), false).map(RespCommand::MSet)
+                    }
+                    "msetnx" => {
+                        MSet::parse(Arguments::new("msetnx", args), true).map(RespCommand::MSet)
+                    }
+                    "getdel" => {
+                        GetDel::parse(Arguments::new("getdel", args)).map(RespCommand::GetDel)
+                    }
+                    "getex" => GetEx::parse(Arguments::new("getex", args)).map(RespCommand::GetEx),
+                    "del" | "unlink" => {
+                        Del::parse(Arguments::new(&command, args)).map(RespCommand::Del)
+                    }
+                    "exists" | "touch" => {
+                        Exists::parse(Arguments::new(&command, args)).map(RespCommand::Exists)
+                    }
+                    "type" => Type::parse(Arguments::new("type", args)).map(RespCommand::Type),
+                    "rename" => Rename::parse(Arguments::new("rename", args), false)
+                        .map(RespCommand::Rename),
+                    "renamenx" => Rename::parse(Arguments::new("renamenx", args), true)
+                        .map(RespCommand::Rename),
+                    "copy" => Copy::parse(Arguments::new("copy", args)).map(RespCommand::Copy),
+                    "randomkey" => RandomKey::parse(Arguments::new("randomkey", args))
+                        .map(RespCommand::RandomKey),
+                    "dbsize" => {
+                        DbSize::parse(Arguments::new("dbsize", args)).map(RespCommand::DbSize)
+                    }
+                    "flushdb" | "flushall" => {
+                        Flush::parse(Arguments::new(&command, args)).map(RespCommand::Flush)
+                    }
+                    "info" => Info::parse(Arguments::new("info", args)).map(RespCommand::Info),
+                    "expire" => Expire::parse(Arguments::new("expire", args), 1000, false)
+                        .map(RespCommand::Expire),
+                    "pexpire" => Expire::parse(Arguments::new("pexpire", args), 1, false)
+                        .map(RespCommand::Expire),
+                    "expireat" => Expire::parse(Arguments::new("expireat", args), 1000, true)
+                        .map(RespCommand::Expire),
+                    "pexpireat" => Expire::parse(Arguments::new("pexpireat", args), 1, true)
+                        .map(RespCommand::Expire),
+                    "ttl" => {
+                        Ttl::parse(Arguments::new("ttl", args), false, false).map(RespCommand::Ttl)
+                    }
+                    "pttl" => {
+                        Ttl::parse(Arguments::new("pttl", args), true, false).map(RespCommand::Ttl)
+                    }
+                    "expiretime" => Ttl::parse(Arguments::new("expiretime", args), false, true)
+                        .map(RespCommand::Ttl),
+                    "pexpiretime" => Ttl::parse(Arguments::new("pexpiretime", args), true, true)
+                        .map(RespCommand::Ttl),
+                    "persist" => {
+                        Persist::parse(Arguments::new("persist", args)).map(RespCommand::Persist)
+                    }
+                    "lpush" => Push::parse(Arguments::new("lpush", args), End::Left, false)
+                        .map(RespCommand::Push),
+                    "rpush" => Push::parse(Arguments::new("rpush", args), End::Right, false)
+                        .map(RespCommand::Push),
+                    "lpushx" => Push::parse(Arguments::new("lpushx", args), End::Left, true)
+                        .map(RespCommand::Push),
+                    "rpushx" => Push::parse(Arguments::new("rpushx", args), End::Right, true)
+                        .map(RespCommand::Push),
+                    "lpop" => {
+                        Pop::parse(Arguments::new("lpop", args), End::Left).map(RespCommand::Pop)
+                    }
+                    "rpop" => {
+                        Pop::parse(Arguments::new("rpop", args), End::Right).map(RespCommand::Pop)
+                    }
+                    "lrange" => {
+                        LRange::parse(Arguments::new("lrange", args)).map(RespCommand::LRange)
+                    }
+                    "lindex" => {
+                        LIndex::parse(Arguments::new("lindex", args)).map(RespCommand::LIndex)
+                    }
+                    "lset" => LSet::parse(Arguments::new("lset", args)).map(RespCommand::LSet),
+                    "linsert" => {
+                        LInsert::parse(Arguments::new("linsert", args)).map(RespCommand::LInsert)
+                    }
+                    "lrem" => LRem::parse(Arguments::new("lrem", args)).map(RespCommand::LRem),
+                    "ltrim" => LTrim::parse(Arguments::new("ltrim", args)).map(RespCommand::LTrim),
+                    "llen" => LLen::parse(Arguments::new("llen", args)).map(RespCommand::LLen),
+                    "lpos" => LPos::parse(Arguments::new("lpos", args)).map(RespCommand::LPos),
+                    "lmove" => LMove::parse(Arguments::new("lmove", args)).map(RespCommand::LMove),
+                    "rpoplpush" => LMove::parse_rpoplpush(Arguments::new("rpoplpush", args))
+                        .map(RespCommand::LMove),
+                    "lmpop" => LMPop::parse(Arguments::new("lmpop", args)).map(RespCommand::LMPop),
+                    "blpop" => {
+                        BPop::parse(Arguments::new("blpop", args), End::Left).map(RespCommand::BPop)
+                    }
+                    "brpop" => BPop::parse(Arguments::new("brpop", args), End::Right)
+                        .map(RespCommand::BPop),
+                    "blmove" => {
+                        BLMove::parse(Arguments::new("blmove", args)).map(RespCommand::BLMove)
+                    }
+                    "brpoplpush" => BLMove::parse_brpoplpush(Arguments::new("brpoplpush", args))
+                        .map(RespCommand::BLMove),
+                    "blmpop" => {
+                        BLMPop::parse(Arguments::new("blmpop", args)).map(RespCommand::BLMPop)
+                    }
+                    "hset" => {
+                        HSet::parse(Arguments::new("hset", args), false).map(RespCommand::HSet)
+                    }
+                    "hmset" => {
+                        HSet::parse(Arguments::new("hmset", args), true).map(RespCommand::HSet)
+                    }
+                    "hsetnx" => {
+                        HSetNx::parse(Arguments::new("hsetnx", args)).map(RespCommand::HSetNx)
+                    }
+                    "hget" => HGet::parse(Arguments::new("hget", args)).map(RespCommand::HGet),
+                    "hmget" => HMGet::parse(Arguments::new("hmget", args)).map(RespCommand::HMGet),
+                    "hdel" => HDel::parse(Arguments::new("hdel", args)).map(RespCommand::HDel),
+                    "hgetall" => HGetAll::parse(Arguments::new("hgetall", args), true, true)
+                        .map(RespCommand::HGetAll),
+                    "hkeys" => HGetAll::parse(Arguments::new("hkeys", args), true, false)
+                        .map(RespCommand::HGetAll),
+                    "hvals" => HGetAll::parse(Arguments::new("hvals", args), false, true)
+                        .map(RespCommand::HGetAll),
+                    "hlen" => HLen::parse(Arguments::new("hlen", args)).map(RespCommand::HLen),
+                    "hexists" => {
+                        HExists::parse(Arguments::new("hexists", args)).map(RespCommand::HExists)
+                    }
+                    "hstrlen" => {
+                        HStrlen::parse(Arguments::new("hstrlen", args)).map(RespCommand::HStrlen)
+                    }
+                    "hincrby" => {
+                        HIncrBy::parse(Arguments::new("hincrby", args)).map(RespCommand::HIncrBy)
+                    }
+                    "hincrbyfloat" => HIncrByFloat::parse(Arguments::new("hincrbyfloat", args))
+                        .map(RespCommand::HIncrByFloat),
+                    "hrandfield" => HRandField::parse(Arguments::new("hrandfield", args))
+                        .map(RespCommand::HRandField),
+                    "hscan" => HScan::parse(Arguments::new("hscan", args)).map(RespCommand::HScan),
+                    "hexpire" => HExpire::parse(Arguments::new("hexpire", args), 1000, false)
+                        .map(RespCommand::HExpire),
+                    "hpexpire" => HExpire::parse(Arguments::new("hpexpire", args), 1, false)
+                        .map(RespCommand::HExpire),
+                    "hexpireat" => HExpire::parse(Arguments::new("hexpireat", args), 1000, true)
+                        .map(RespCommand::HExpire),
+                    "hpexpireat" => HExpire::parse(Arguments::new("hpexpireat", args), 1, true)
+                        .map(RespCommand::HExpire),
+                    "httl" => HTtl::parse(Arguments::new("httl", args), false, false)
+                        .map(RespCommand::HTtl),
+                    "hpttl" => HTtl::parse(Arguments::new("hpttl", args), true, false)
+                        .map(RespCommand::HTtl),
+                    "hexpiretime" => HTtl::parse(Arguments::new("hexpiretime", args), false, true)
+                        .map(RespCommand::HTtl),
+                    "hpexpiretime" => HTtl::parse(Arguments::new("hpexpiretime", args), true, true)
+                        .map(RespCommand::HTtl),
+                    "hpersist" => {
+                        HPersist::parse(Arguments::new("hpersist", args)).map(RespCommand::HPersist)
+                    }
+                    "sadd" => SAdd::parse(Arguments::new("sadd", args)).map(RespCommand::SAdd),
+                    "srem" => SRem::parse(Arguments::new("srem", args)).map(RespCommand::SRem),
+                    "smembers" => {
+                        SMembers::parse(Arguments::new("smembers", args)).map(RespCommand::SMembers)
+                    }
+                    "sismember" => SIsMember::parse(Arguments::new("sismember", args))
+                        .map(RespCommand::SIsMember),
+                    "smismember" => SMIsMember::parse(Arguments::new("smismember", args))
+                        .map(RespCommand::SMIsMember),
+                    "scard" => SCard::parse(Arguments::new("scard", args)).map(RespCommand::SCard),
+                    "spop" => SPop::parse(Arguments::new("spop", args)).map(RespCommand::SPop),
+                    "srandmember" => SRandMember::parse(Arguments::new("srandmember", args))
+                        .map(RespCommand::SRandMember),
+                    "smove" => SMove::parse(Arguments::new("smove", args)).map(RespCommand::SMove),
+                    "sintercard" => SInterCard::parse(Arguments::new("sintercard", args))
+                        .map(RespCommand::SInterCard),
+                    "sscan" => SScan::parse(Arguments::new("sscan", args)).map(RespCommand::SScan),
+                    "sinter" => {
+                        SetOp::parse(Arguments::new("sinter", args), SetOperation::Inter, false)
+                            .map(RespCommand::SetOp)
+                    }
+                    "sinterstore" => SetOp::parse(
+                        Arguments::new("sinterstore", args),
+                        SetOperation::Inter,
+                        true,
+                    )
+                    .map(RespCommand::SetOp),
+                    "sunion" => {
+                        SetOp::parse(Arguments::new("sunion", args), SetOperation::Union, false)
+                            .map(RespCommand::SetOp)
+                    }
+                    "sunionstore" => SetOp::parse(
+                        Arguments::new("sunionstore", args),
+                        SetOperation::Union,
+                        true,
+                    )
+                    .map(RespCommand::SetOp),
+                    "sdiff" => {
+                        SetOp::parse(Arguments::new("sdiff", args), SetOperation::Diff, false)
+                            .map(RespCommand::SetOp)
+                    }
+                    "sdiffstore" => {
+                        SetOp::parse(Arguments::new("sdiffstore", args), SetOperation::Diff, true)
+                            .map(RespCommand::SetOp)
+                    }
+                    "zadd" => ZAdd::parse(Arguments::new("zadd", args)).map(RespCommand::ZAdd),
+                    "zincrby" => {
+                        ZAdd::parse_zincrby(Arguments::new("zincrby", args)).map(RespCommand::ZAdd)
+                    }
+                    "zrem" => ZRem::parse(Arguments::new("zrem", args)).map(RespCommand::ZRem),
+                    "zcard" => ZCard::parse(Arguments::new("zcard", args)).map(RespCommand::ZCard),
+                    "zscore" => ZScore::parse(Arguments::new("zscore", args), false)
+                        .map(RespCommand::ZScore),
+                    "zmscore" => ZScore::parse(Arguments::new("zmscore", args), true)
+                        .map(RespCommand::ZScore),
+                    "zrank" => {
+                        ZRank::parse(Arguments::new("zrank", args), false).map(RespCommand::ZRank)
+                    }
+                    "zrevrank" => {
+                        ZRank::parse(Arguments::new("zrevrank", args), true).map(RespCommand::ZRank)
+                    }
+                    "zcount" => ZCount::parse(Arguments::new("zcount", args), false)
+                        .map(RespCommand::ZCount),
+                    "zlexcount" => ZCount::parse(Arguments::new("zlexcount", args), true)
+                        .map(RespCommand::ZCount),
+                    "zrange" => ZRange::parse(Arguments::new("zrange", args), false)
+                        .map(RespCommand::ZRange),
+                    "zrangestore" => ZRange::parse(Arguments::new("zrangestore", args), true)
+                        .map(RespCommand::ZRange),
+                    "zrevrange" => ZRange::parse_legacy(
+                        Arguments::new("zrevrange", args),
+                        RangeKind::Rank,
+                        true,
+                    )
+                    .map(RespCommand::ZRange),
+                    "zrangebyscore" => ZRange::parse_legacy(
+                        Arguments::new("zrangebyscore", args),
+                        RangeKind::Score,
+                        false,
+                    )
+                    .map(RespCommand::ZRange),
+                    "zrevrangebyscore" => ZRange::parse_legacy(
+                        Arguments::new("zrevrangebyscore", args),
+                        RangeKind::Score,
+                        true,
+                    )
+                    .map(RespCommand::ZRange),
+                    "zrangebylex" => ZRange::parse_legacy(
+                        Arguments::new("zrangebylex", args),
+                        RangeKind::Lex,
+                        false,
+                    )
+                    .map(RespCommand::ZRange),
+                    "zrevrangebylex" => ZRange::parse_legacy(
+                        Arguments::new("zrevrangebylex", args),
+                        RangeKind::Lex,
+                        true,
+                    )
+                    .map(RespCommand::ZRange),
+                    "zpopmin" => {
+                        ZPop::parse(Arguments::new("zpopmin", args), true).map(RespCommand::ZPop)
+                    }
+                    "zpopmax" => {
+                        ZPop::parse(Arguments::new("zpopmax", args), false).map(RespCommand::ZPop)
+                    }
+                    "bzpopmin" => {
+                        BZPop::parse(Arguments::new("bzpopmin", args), true).map(RespCommand::BZPop)
+                    }
+                    "bzpopmax" => BZPop::parse(Arguments::new("bzpopmax", args), false)
+                        .map(RespCommand::BZPop),
+                    "zunionstore" => {
+                        ZStore::parse(Arguments::new("zunionstore", args), ZSetOperation::Union)
+                            .map(RespCommand::ZStore)
+                    }
+                    "zinterstore" => {
+                        ZStore::parse(Arguments::new("zinterstore", args), ZSetOperation::Inter)
+                            .map(RespCommand::ZStore)
+                    }
+                    "xadd" => XAdd::parse(Arguments::new("xadd", args)).map(RespCommand::XAdd),
+                    "xrange" => XRange::parse(Arguments::new("xrange", args), false)
+                        .map(RespCommand::XRange),
+                    "xrevrange" => XRange::parse(Arguments::new("xrevrange", args), true)
+                        .map(RespCommand::XRange),
+                    "xlen" => XLen::parse(Arguments::new("xlen", args)).map(RespCommand::XLen),
+                    "xtrim" => XTrim::parse(Arguments::new("xtrim", args)).map(RespCommand::XTrim),
+                    "xdel" => XDel::parse(Arguments::new("xdel", args)).map(RespCommand::XDel),
+                    "xread" => {
+                        XRead::parse(Arguments::new("xread", args), false).map(RespCommand::XRead)
+                    }
+                    "xreadgroup" => XRead::parse(Arguments::new("xreadgroup", args), true)
+                        .map(RespCommand::XRead),
+                    "xgroup" => {
+                        XGroup::parse(Arguments::new("xgroup", args)).map(RespCommand::XGroup)
+                    }
+                    "xack" => XAck::parse(Arguments::new("xack", args)).map(RespCommand::XAck),
+                    "xpending" => {
+                        XPending::parse(Arguments::new("xpending", args)).map(RespCommand::XPending)
+                    }
+                    "xclaim" => {
+                        XClaim::parse(Arguments::new("xclaim", args)).map(RespCommand::XClaim)
+                    }
+                    "xautoclaim" => XAutoClaim::parse(Arguments::new("xautoclaim", args))
+                        .map(RespCommand::XAutoClaim),
+                    "xinfo" => XInfo::parse(Arguments::new("xinfo", args)).map(RespCommand::XInfo),
+                    "setbit" => {
+                        SetBit::parse(Arguments::new("setbit", args)).map(RespCommand::SetBit)
+                    }
+                    "getbit" => {
+                        GetBit::parse(Arguments::new("getbit", args)).map(RespCommand::GetBit)
+                    }
+                    "bitcount" => {
+                        BitCount::parse(Arguments::new("bitcount", args)).map(RespCommand::BitCount)
+                    }
+                    "bitpos" => {
+                        BitPos::parse(Arguments::new("bitpos", args)).map(RespCommand::BitPos)
+                    }
+                    "bitop" => BitOp::parse(Arguments::new("bitop", args)).map(RespCommand::BitOp),
+                    "bitfield" => Bitfield::parse(Arguments::new("bitfield", args), false)
+                        .map(RespCommand::Bitfield),
+                    "bitfield_ro" => Bitfield::parse(Arguments::new("bitfield_ro", args), true)
+                        .map(RespCommand::Bitfield),
+                    "pfadd" => PfAdd::parse(Arguments::new("pfadd", args)).map(RespCommand::PfAdd),
+                    "pfcount" => {
+                        PfCount::parse(Arguments::new("pfcount", args)).map(RespCommand::PfCount)
+                    }
+                    "pfmerge" => {
+                        PfMerge::parse(Arguments::new("pfmerge", args)).map(RespCommand::PfMerge)
+                    }
+                    "geoadd" => {
+                        ZAdd::parse_geoadd(Arguments::new("geoadd", args)).map(RespCommand::ZAdd)
+                    }
+                    "geodist" => {
+                        GeoDist::parse(Arguments::new("geodist", args)).map(RespCommand::GeoDist)
+                    }
+                    "geohash" => {
+                        GeoHash::parse(Arguments::new("geohash", args)).map(RespCommand::GeoHash)
+                    }
+                    "geopos" => {
+                        GeoPos::parse(Arguments::new("geopos", args)).map(RespCommand::GeoPos)
+                    }
+                    "geosearch" => GeoSearch::parse(Arguments::new("geosearch", args), false)
+                        .map(RespCommand::GeoSearch),
+                    "geosearchstore" => {
+                        GeoSearch::parse(Arguments::new("geosearchstore", args), true)
+                            .map(RespCommand::GeoSearch)
+                    }
+                    "multi" => Arguments::new("multi", args)
+                        .expect_len(0)
+                        .map(|_| RespCommand::Multi),
+                    "exec" => Arguments::new("exec", args)
+                        .expect_len(0)
+                        .map(|_| RespCommand::Exec),
+                    "discard" => Arguments::new("discard", args)
+                        .expect_len(0)
+                        .map(|_| RespCommand::Discard),
+                    "watch" => {
+                        let mut args = Arguments::new("watch", args);
+                        if args.is_empty() {
+                            return Err(args.wrong_arity());
+                        }
+                        let mut keys = vec![];
+                        while !args.is_empty() {
+                            keys.push(args.next_key()?);
+                        }
+                        Ok(RespCommand::Watch(Watch { keys }))
+                    }
+                    "unwatch" => Arguments::new("unwatch", args)
+                        .expect_len(0)
+                        .map(|_| RespCommand::Unwatch),
+                    "hello" => {
+                        let mut args = args
+                            .iter()
+                            .map(|x| {
+                                x.clone()
                                     .into_bulk_strings()?
-                                    .map(String::from_utf8)
-                                    .transpose()?
-                                    .map(|s| s.to_ascii_lowercase())
-                                    .ok_or::<GenericError>("expected PX".into())?;
-
-                                let expiry = expiry_value
-                                    .cloned()
-                                    .map(|x| match x {
-                                        RespDataType::BulkStrings(Some(s)) => {
-                                            Ok(String::from_utf8(s)?.parse::<u64>()?)
-                                        }
-                                        RespDataType::Integers(n) => Ok(n.try_into()?),
-                                        _ => Err::<_, GenericError>(
-                                            "expected bulk string or int".into(),
-                                        ),
-                                    })
-                                    .transpose()?
-                                    .ok_or("expected time")?
-                                    .try_into()
-                                    .map(Duration::from_millis)?;
-
-                                match ttl_type.as_str() {
-                                    "px" => Ok(RespCommand::Set(Set {
-                                        key,
-                                        value,
-                                        expiry: Some(expiry),
-                                    })),
-                                    _ => Err("expected PX".into()),
+                                    .ok_or::<GenericError>("expected bulk string".into())
+                            })
+                            .collect::<util::Result<Vec<_>>>()?
+                            .into_iter();
+
+                        let protocol = args
+                            .next()
+                            .map(|x| {
+                                std::str::from_utf8(&x)
+                                    .ok()
+                                    .and_then(|x| x.parse::<i64>().ok())
+                                    .ok_or::<GenericError>(
+                                        "ERR Protocol version is not an integer or out of range"
+                                            .into(),
+                                    )
+                                    .and_then(ProtocolVersion::try_from)
+                            })
+                            .transpose()?;
+
+                        let mut hello = Hello {
+                            protocol,
+                            auth: None,
+                            client_name: None,
+                        };
+                        while let Some(option) = args.next() {
+                            match &option.to_ascii_lowercase()[..] {
+                                b"auth" => {
+                                    let username = args.next();
+                                    let password = args.next();
+                                    hello.auth = Some(
+                                        username
+                                            .zip(password)
+                                            .ok_or("ERR Syntax error in HELLO option 'auth'")?,
+                                    );
+                                }
+                                b"setname" => {
+                                    hello.client_name = Some(
+                                        args.next()
+                                            .ok_or("ERR Syntax error in HELLO option 'setname'")?,
+                                    );
+                                }
+                                _ => {
+                                    return Err(format!(
+                                        "ERR Syntax error in HELLO option '{}'",
+                                        String::from_utf8_lossy(&option)
+                                    )
+                                    .into())
                                 }
                             }
-                            _ => Err("unexpected argument".into()),
                         }
-                    }
-                    "get" => {
-                        let key = args
-                            .get(0)
-                            .ok_or::<GenericError>("missing argument key".into())?
-                            .expect_bulk_strings()
-                            .map_err::<GenericError, _>(|_| "key is not bulk string".into())?
-                            .clone();
 
-                        Ok(RespCommand::Get(Get { key }))
+                        Ok(RespCommand::Hello(hello))
                     }
                     _ => Err("unknown command".into()),
                 }
@@ -151,6 +906,46 @@ pub trait Command<'a, C> {
     fn execute(&'a self, context: &'a mut C) -> BoxFuture<'a, util::Result<RespDataType>>;
 }
 
+/// A command that makes the client wait when it cannot be served right away, until one
+/// of its keys is written to or it times out.
+///
+/// As a [`Command`] it never waits, replying as if it timed out right away.
+pub trait BlockingCommand {
+    fn keys(&self) -> Vec<Key>;
+
+    /// How long to wait, `None` for as long as it takes.
+    fn timeout(&self) -> Option<Duration>;
+
+    /// Serves the command, `Ok(None)` when there is nothing to serve it with yet.
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>>;
+
+    fn timeout_reply(&self) -> RespDataType {
+        RespDataType::empty_arrays()
+    }
+
+    /// Whether the clients blocked on a key with this command all wait for the same
+    /// thing, so that when one cannot be served, neither can those after it.
+    fn in_line(&self) -> bool {
+        true
+    }
+
+    /// The command to block with instead, for commands whose arguments are relative to
+    /// the keyspace at the time they block, such as the `$` ID of `XREAD`.
+    fn resolve(&self, _db: &mut Db) -> util::Result<Option<RespCommand>> {
+        Ok(None)
+    }
+}
+
+impl<'a, T: BlockingCommand + Sync> Command<'a, Db> for T {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(self
+                .try_serve(context)?
+                .unwrap_or_else(|| self.timeout_reply()))
+        })
+    }
+}
+
 impl<'a> Command<'a, ()> for Ping {
     fn execute(&'a self, _: &'a mut ()) -> BoxFuture<'a, util::Result<RespDataType>> {
         Box::pin(async move {
@@ -168,57 +963,84 @@ impl<'a> Command<'a, ()> for Echo {
     }
 }
 
-impl<'a> Command<'a, HashMap<String, RedisDataTypeWithTTL>> for Set {
-    fn execute(
-        &'a self,
-        context: &'a mut HashMap<String, RedisDataTypeWithTTL>,
-    ) -> BoxFuture<'a, util::Result<RespDataType>> {
+impl<'a> Command<'a, Connection> for Hello {
+    fn execute(&'a self, context: &'a mut Connection) -> BoxFuture<'a, util::Result<RespDataType>> {
         Box::pin(async move {
-            let key = self
-                .key
-                .clone()
-                .into_bulk_strings()?
-                .ok_or::<GenericError>("empty key".into())?;
-            let value: RedisDataType = self.value.clone().try_into()?;
-            context.insert(
-                String::from_utf8(key)?,
-                match self.expiry {
-                    Some(time) => RedisDataTypeWithTTL::Finite(value, Instant::now() + time),
-                    None => RedisDataTypeWithTTL::Infinite(value),
-                },
-            );
-            Ok(RespDataType::simple_strings("OK"))
+            if let Some((username, _)) = &self.auth {
+                // there is no ACL support, the default user accepts any password
+                if &username[..] != b"default" {
+                    return Err(
+                        "WRONGPASS invalid username-password pair or user is disabled.".into(),
+                    );
+                }
+            }
+            if let Some(protocol) = self.protocol {
+                context.protocol = protocol;
+            }
+            if let Some(name) = &self.client_name {
+                context.name = Some(name.clone());
+            }
+
+            Ok(RespDataType::maps(vec![
+                (
+                    RespDataType::bulk_strings("server"),
+                    RespDataType::bulk_strings("redis"),
+                ),
+                (
+                    RespDataType::bulk_strings("version"),
+                    RespDataType::bulk_strings(env!("CARGO_PKG_VERSION")),
+                ),
+                (
+                    RespDataType::bulk_strings("proto"),
+                    RespDataType::integers(context.protocol.into()),
+                ),
+                (
+                    RespDataType::bulk_strings("id"),
+                    RespDataType::integers(context.id as i64),
+                ),
+                (
+                    RespDataType::bulk_strings("mode"),
+                    RespDataType::bulk_strings("standalone"),
+                ),
+                (
+                    RespDataType::bulk_strings("role"),
+                    RespDataType::bulk_strings("master"),
+                ),
+                (
+                    RespDataType::bulk_strings("modules"),
+                    RespDataType::arrays(vec![]),
+                ),
+            ]))
         })
     }
 }
 
-impl<'a> Command<'a, HashMap<String, RedisDataTypeWithTTL>> for Get {
-    fn execute(
-        &'a self,
-        context: &'a mut HashMap<String, RedisDataTypeWithTTL>,
-    ) -> BoxFuture<'a, util::Result<RespDataType>> {
-        Box::pin(async move {
-            let key = self
-                .key
-                .clone()
-                .into_bulk_strings()?
-                .ok_or::<GenericError>("empty key".into())?;
-            let key = String::from_utf8(key).map_err::<GenericError, _>(|x| x.into())?;
-            match context.get(&key).cloned() {
-                Some(RedisDataTypeWithTTL::Infinite(RedisDataType::Strings(s))) => {
-                    Ok(RespDataType::bulk_strings(s))
-                }
-                Some(RedisDataTypeWithTTL::Finite(RedisDataType::Strings(s), ttl)) => {
-                    if Instant::now() > ttl {
-                        context.remove(&key);
-                        Ok(RespDataType::empty_bulk_strings())
-                    } else {
-                        Ok(RespDataType::bulk_strings(s))
-                    }
-                }
-                Some(_) => Err("entry is not a bulk string".into()),
-                None => Ok(RespDataType::empty_bulk_strings()),
-            }
-        })
+/// Running commands against a keyspace from their inline form, for the tests of every
+/// command module.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::RespCommand;
+    use crate::{data_type::RespDataType, db::Db, util};
+    use std::convert::TryFrom;
+
+    pub async fn run(db: &mut Db, line: &str) -> util::Result<RespDataType> {
+        let args = util::split_args(line.as_bytes())?
+            .into_iter()
+            .map(RespDataType::bulk_strings)
+            .collect();
+        let command = RespCommand::try_from(RespDataType::arrays(args))?;
+        command
+            .as_keyspace_command()
+            .expect("a keyspace command")
+            .execute(db)
+            .await
+    }
+
+    pub async fn run_ok(db: &mut Db, line: &str) -> RespDataType {
+        run(db, line).await.expect("command succeeds")
+    }
+
+    pub async fn run_err(db: &mut Db, line: &str) -> String {
+        run(db, line).await.expect_err("command fails").to_string()
     }
 }
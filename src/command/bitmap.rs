@@ -0,0 +1,857 @@
+use super::{
+    string::{get_string, put_string, MAX_STRING_LEN},
+    Arguments, Command, SYNTAX_ERROR,
+};
+use crate::{
+    data_type::{Key, RedisDataType, RespDataType},
+    db::Db,
+    util::{self, BoxFuture},
+};
+
+const INVALID_OFFSET: &str = "ERR bit offset is not an integer or out of range";
+
+#[derive(Debug, Clone)]
+pub struct SetBit {
+    pub key: Key,
+    pub offset: usize,
+    pub bit: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetBit {
+    pub key: Key,
+    pub offset: usize,
+}
+
+/// A range of a string in `BITCOUNT` and `BITPOS`, with indexes that may count from the
+/// end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitRange {
+    pub start: i64,
+    pub end: Option<i64>,
+    /// `BIT`, indexes of bits rather than bytes
+    pub bits: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BitCount {
+    pub key: Key,
+    pub range: Option<BitRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BitPos {
+    pub key: Key,
+    pub bit: bool,
+    pub range: Option<BitRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+#[derive(Debug, Clone)]
+pub struct BitOp {
+    pub operation: BitOperation,
+    pub destination: Key,
+    pub keys: Vec<Key>,
+}
+
+/// The type of a `BITFIELD` integer, such as `i16` or `u8`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+/// What `BITFIELD` does when a write does not fit its type.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Overflow {
+    Wrap,
+    /// Saturate to the smallest or the greatest value
+    Sat,
+    /// Skip the write and reply with nil
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BitfieldOp {
+    Get {
+        ty: BitfieldType,
+        offset: usize,
+    },
+    Set {
+        ty: BitfieldType,
+        offset: usize,
+        value: i64,
+        overflow: Overflow,
+    },
+    IncrBy {
+        ty: BitfieldType,
+        offset: usize,
+        increment: i64,
+        overflow: Overflow,
+    },
+}
+
+/// `BITFIELD` and `BITFIELD_RO`.
+#[derive(Debug, Clone)]
+pub struct Bitfield {
+    pub key: Key,
+    pub ops: Vec<BitfieldOp>,
+}
+
+/// Parses a bit offset, which may not reach past the greatest string.
+fn parse_offset(arg: &[u8]) -> util::Result<usize> {
+    match util::parse_integer(arg) {
+        Some(offset) if offset >= 0 && (offset as usize) < MAX_STRING_LEN * 8 => {
+            Ok(offset as usize)
+        }
+        _ => Err(INVALID_OFFSET.into()),
+    }
+}
+
+/// The bit at `offset`, bit 0 being the most significant bit of the first byte like in
+/// redis. Bits past the end are 0.
+fn get_bit(bytes: &[u8], offset: usize) -> bool {
+    bytes
+        .get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+/// Sets the bit at `offset`, growing `bytes` with zeros as needed.
+fn set_bit(bytes: &mut Vec<u8>, offset: usize, bit: bool) {
+    if bytes.len() <= offset / 8 {
+        bytes.resize(offset / 8 + 1, 0);
+    }
+    let mask = 0x80 >> (offset % 8);
+    if bit {
+        bytes[offset / 8] |= mask;
+    } else {
+        bytes[offset / 8] &= !mask;
+    }
+}
+
+impl BitRange {
+    fn parse(args: &mut Arguments, end_optional: bool) -> util::Result<Option<BitRange>> {
+        if args.is_empty() {
+            return Ok(None);
+        }
+        let start = args.next_integer()?;
+        let end = match args.is_empty() {
+            true if end_optional => None,
+            true => return Err(SYNTAX_ERROR.into()),
+            false => Some(args.next_integer()?),
+        };
+        let bits = match args.len() {
+            0 => false,
+            1 => match args.next_keyword()?.as_str() {
+                "bit" => true,
+                "byte" => false,
+                _ => return Err(SYNTAX_ERROR.into()),
+            },
+            _ => return Err(SYNTAX_ERROR.into()),
+        };
+        Ok(Some(BitRange { start, end, bits }))
+    }
+
+    /// The inclusive range of bits covered in a string of `len` bytes, `None` if empty.
+    fn bit_range(range: Option<BitRange>, len: usize) -> Option<(usize, usize)> {
+        let range = range.unwrap_or(BitRange {
+            start: 0,
+            end: None,
+            bits: false,
+        });
+        let total = if range.bits { len * 8 } else { len } as i64;
+        let (mut start, mut end) = (range.start, range.end.unwrap_or(-1));
+        if start < 0 {
+            start += total;
+        }
+        if end < 0 {
+            end += total;
+        }
+        let (start, end) = (start.max(0), end.max(0).min(total - 1));
+        if total == 0 || start > end {
+            return None;
+        }
+        let (start, end) = (start as usize, end as usize);
+        Some(if range.bits {
+            (start, end)
+        } else {
+            (start * 8, end * 8 + 7)
+        })
+    }
+}
+
+/// Counts the set bits from `start` to `end`, inclusive.
+fn count_bits(bytes: &[u8], start: usize, end: usize) -> usize {
+    let (first, last) = (start / 8, end / 8);
+    if first == last {
+        return (start..=end).filter(|x| get_bit(bytes, *x)).count();
+    }
+    let head = (start..(first + 1) * 8)
+        .filter(|x| get_bit(bytes, *x))
+        .count();
+    let tail = (last * 8..=end).filter(|x| get_bit(bytes, *x)).count();
+    let middle = bytes[first + 1..last]
+        .iter()
+        .map(|x| x.count_ones() as usize)
+        .sum::<usize>();
+    head + middle + tail
+}
+
+/// The first bit set to `bit` from `start` to `end`, inclusive.
+fn find_bit(bytes: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    // whole bytes without the bit can be skipped
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut offset = start;
+    while offset <= end {
+        if offset.is_multiple_of(8) && offset + 7 <= end && bytes[offset / 8] == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(bytes, offset) == bit {
+            return Some(offset);
+        }
+        offset += 1;
+    }
+    None
+}
+
+impl BitfieldType {
+    fn parse(arg: &[u8]) -> util::Result<BitfieldType> {
+        let error = "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.";
+        let signed = match arg.first().map(u8::to_ascii_lowercase) {
+            Some(b'i') => true,
+            Some(b'u') => false,
+            _ => return Err(error.into()),
+        };
+        let bits = util::parse_integer(&arg[1..]).ok_or(error)?;
+        let max = if signed { 64 } else { 63 };
+        if bits < 1 || bits > max {
+            return Err(error.into());
+        }
+        Ok(BitfieldType {
+            signed,
+            bits: bits as u32,
+        })
+    }
+
+    /// Parses an offset, where `#n` means the `n`th integer of this type.
+    fn parse_offset(self, arg: &[u8]) -> util::Result<usize> {
+        let offset = match arg.strip_prefix(b"#") {
+            Some(index) => util::parse_integer(index)
+                .and_then(|x| x.checked_mul(self.bits as i64))
+                .ok_or(INVALID_OFFSET)?,
+            None => util::parse_integer(arg).ok_or(INVALID_OFFSET)?,
+        };
+        if offset < 0 || offset as usize + self.bits as usize > MAX_STRING_LEN * 8 {
+            return Err(INVALID_OFFSET.into());
+        }
+        Ok(offset as usize)
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    fn get(self, bytes: &[u8], offset: usize) -> i64 {
+        let mut value = 0u64;
+        for i in 0..self.bits as usize {
+            value = (value << 1) | get_bit(bytes, offset + i) as u64;
+        }
+        if self.signed && self.bits < 64 && value & (1 << (self.bits - 1)) != 0 {
+            // sign extension
+            value |= u64::MAX << self.bits;
+        }
+        value as i64
+    }
+
+    fn set(self, bytes: &mut Vec<u8>, offset: usize, value: i64) {
+        for i in 0..self.bits as usize {
+            let bit = (value as u64 >> (self.bits as usize - 1 - i)) & 1 == 1;
+            set_bit(bytes, offset + i, bit);
+        }
+    }
+
+    /// Fits `value` into the type, `None` when it overflows with `FAIL`.
+    fn fit(self, value: i128, overflow: Overflow) -> Option<i64> {
+        if value >= self.min() && value <= self.max() {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat if value < self.min() => Some(self.min() as i64),
+            Overflow::Sat => Some(self.max() as i64),
+            Overflow::Wrap => {
+                let wrapped = value.rem_euclid(1 << self.bits);
+                Some(if wrapped > self.max() {
+                    wrapped - (1 << self.bits)
+                } else {
+                    wrapped
+                } as i64)
+            }
+        }
+    }
+}
+
+impl SetBit {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SetBit> {
+        args.expect_len(3)?;
+        let key = args.next_key()?;
+        let offset = parse_offset(&args.next_bytes()?)?;
+        let bit = match &args.next_bytes()?[..] {
+            b"0" => false,
+            b"1" => true,
+            _ => return Err("ERR bit is not an integer or out of range".into()),
+        };
+        Ok(SetBit { key, offset, bit })
+    }
+}
+
+impl GetBit {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GetBit> {
+        args.expect_len(2)?;
+        let key = args.next_key()?;
+        Ok(GetBit {
+            key,
+            offset: parse_offset(&args.next_bytes()?)?,
+        })
+    }
+}
+
+impl BitCount {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<BitCount> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        Ok(BitCount {
+            key,
+            range: BitRange::parse(&mut args, false)?,
+        })
+    }
+}
+
+impl BitPos {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<BitPos> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let bit = match util::parse_integer(&args.next_bytes()?) {
+            Some(0) => false,
+            Some(1) => true,
+            Some(_) => return Err("ERR The bit argument must be 1 or 0.".into()),
+            None => return Err("ERR value is not an integer or out of range".into()),
+        };
+        Ok(BitPos {
+            key,
+            bit,
+            range: BitRange::parse(&mut args, true)?,
+        })
+    }
+}
+
+impl BitOp {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<BitOp> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let operation = match args.next_keyword()?.as_str() {
+            "and" => BitOperation::And,
+            "or" => BitOperation::Or,
+            "xor" => BitOperation::Xor,
+            "not" => BitOperation::Not,
+            _ => return Err(SYNTAX_ERROR.into()),
+        };
+        let destination = args.next_key()?;
+        let mut keys = vec![];
+        while !args.is_empty() {
+            keys.push(args.next_key()?);
+        }
+        if operation == BitOperation::Not && keys.len() != 1 {
+            return Err("ERR BITOP NOT must be called with a single source key.".into());
+        }
+        Ok(BitOp {
+            operation,
+            destination,
+            keys,
+        })
+    }
+}
+
+impl Bitfield {
+    pub(super) fn parse(mut args: Arguments, read_only: bool) -> util::Result<Bitfield> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut ops = vec![];
+        let mut overflow = Overflow::Wrap;
+        while !args.is_empty() {
+            let subcommand = args.next_keyword()?;
+            if read_only && subcommand != "get" {
+                return Err("ERR BITFIELD_RO only supports the GET subcommand".into());
+            }
+            if subcommand == "overflow" {
+                overflow = match args.next_keyword()?.as_str() {
+                    "wrap" => Overflow::Wrap,
+                    "sat" => Overflow::Sat,
+                    "fail" => Overflow::Fail,
+                    _ => return Err("ERR Invalid OVERFLOW type specified".into()),
+                };
+                continue;
+            }
+            if args.len() < 2 {
+                return Err(SYNTAX_ERROR.into());
+            }
+            let ty = BitfieldType::parse(&args.next_bytes()?)?;
+            let offset = ty.parse_offset(&args.next_bytes()?)?;
+            ops.push(match subcommand.as_str() {
+                "get" => BitfieldOp::Get { ty, offset },
+                "set" if !args.is_empty() => BitfieldOp::Set {
+                    ty,
+                    offset,
+                    value: args.next_integer()?,
+                    overflow,
+                },
+                "incrby" if !args.is_empty() => BitfieldOp::IncrBy {
+                    ty,
+                    offset,
+                    increment: args.next_integer()?,
+                    overflow,
+                },
+                _ => return Err(SYNTAX_ERROR.into()),
+            });
+        }
+        Ok(Bitfield { key, ops })
+    }
+}
+
+impl<'a> Command<'a, Db> for SetBit {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let mut value = get_string(context, &self.key)?
+                .map(|x| x.to_vec())
+                .unwrap_or_default();
+            let old = get_bit(&value, self.offset);
+            set_bit(&mut value, self.offset, self.bit);
+            put_string(context, &self.key, RedisDataType::Strings(value.into()));
+            Ok(RespDataType::integers(old as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GetBit {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = get_string(context, &self.key)?.unwrap_or_default();
+            Ok(RespDataType::integers(get_bit(&value, self.offset) as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for BitCount {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = get_string(context, &self.key)?.unwrap_or_default();
+            let count = match BitRange::bit_range(self.range, value.len()) {
+                Some((start, end)) => count_bits(&value, start, end),
+                None => 0,
+            };
+            Ok(RespDataType::integers(count as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for BitPos {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = match get_string(context, &self.key)? {
+                Some(value) => value,
+                None => return Ok(RespDataType::integers(if self.bit { -1 } else { 0 })),
+            };
+            let (start, end) = match BitRange::bit_range(self.range, value.len()) {
+                Some(range) => range,
+                None => return Ok(RespDataType::integers(-1)),
+            };
+            let position = match find_bit(&value, self.bit, start, end) {
+                Some(position) => position as i64,
+                // without an end, the string is as good as padded with clear bits
+                None if !self.bit && self.range.is_none_or(|x| x.end.is_none()) => end as i64 + 1,
+                None => -1,
+            };
+            Ok(RespDataType::integers(position))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for BitOp {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let mut values = vec![];
+            for key in &self.keys {
+                values.push(get_string(context, key)?.unwrap_or_default());
+            }
+            let len = values.iter().map(|x| x.len()).max().unwrap_or(0);
+            // missing bytes are zeros
+            let byte = |value: &[u8], i: usize| value.get(i).copied().unwrap_or(0);
+            let result = (0..len)
+                .map(|i| {
+                    let mut bytes = values.iter().map(|x| byte(x, i));
+                    let first = bytes.next().unwrap_or(0);
+                    match self.operation {
+                        BitOperation::And => bytes.fold(first, |a, b| a & b),
+                        BitOperation::Or => bytes.fold(first, |a, b| a | b),
+                        BitOperation::Xor => bytes.fold(first, |a, b| a ^ b),
+                        BitOperation::Not => !first,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            context.remove(&self.destination);
+            if !result.is_empty() {
+                put_string(
+                    context,
+                    &self.destination,
+                    RedisDataType::Strings(result.into()),
+                );
+            }
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Bitfield {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = get_string(context, &self.key)?;
+            let mut value = current.as_ref().map(|x| x.to_vec()).unwrap_or_default();
+            let mut written = false;
+            let mut replies = vec![];
+            for op in &self.ops {
+                let reply = match *op {
+                    BitfieldOp::Get { ty, offset } => Some(ty.get(&value, offset)),
+                    BitfieldOp::Set {
+                        ty,
+                        offset,
+                        value: new,
+                        overflow,
+                    } => {
+                        // unsigned values are taken as the bits of the given integer
+                        let new = if ty.signed {
+                            new as i128
+                        } else {
+                            new as u64 as i128
+                        };
+                        ty.fit(new, overflow).map(|new| {
+                            let old = ty.get(&value, offset);
+                            ty.set(&mut value, offset, new);
+                            written = true;
+                            old
+                        })
+                    }
+                    BitfieldOp::IncrBy {
+                        ty,
+                        offset,
+                        increment,
+                        overflow,
+                    } => {
+                        let old = ty.get(&value, offset) as i128;
+                        ty.fit(old + increment as i128, overflow).inspect(|new| {
+                            ty.set(&mut value, offset, *new);
+                            written = true;
+                        })
+                    }
+                };
+                replies.push(reply.map_or_else(RespDataType::empty_bulk_strings, |x| {
+                    RespDataType::integers(x)
+                }));
+            }
+            if written {
+                put_string(context, &self.key, RedisDataType::Strings(value.into()));
+            }
+            Ok(RespDataType::arrays(replies))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    fn integers(values: &[i64]) -> RespDataType {
+        RespDataType::arrays(values.iter().map(|x| RespDataType::integers(*x)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_setbit_getbit() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "SETBIT b 7 1").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SETBIT b 7 1").await,
+            RespDataType::integers(1)
+        );
+        // bit 0 is the most significant bit of the first byte
+        assert_eq!(
+            run_ok(&mut db, "GET b").await,
+            RespDataType::bulk_strings("\x01")
+        );
+        run_ok(&mut db, "SETBIT b 17 1").await;
+        assert_eq!(
+            run_ok(&mut db, "GET b").await,
+            RespDataType::bulk_strings(&b"\x01\x00\x40"[..])
+        );
+        assert_eq!(
+            run_ok(&mut db, "GETBIT b 17").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GETBIT b 1000").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GETBIT missing 0").await,
+            RespDataType::integers(0)
+        );
+
+        // integers are strings too
+        run_ok(&mut db, "SET n 1").await;
+        run_ok(&mut db, "SETBIT n 6 1").await;
+        assert_eq!(
+            run_ok(&mut db, "GET n").await,
+            RespDataType::bulk_strings("3")
+        );
+
+        run_ok(&mut db, "LPUSH l a").await;
+        for (line, error) in &[
+            (
+                "SETBIT b -1 1",
+                "ERR bit offset is not an integer or out of range",
+            ),
+            (
+                "SETBIT b 4294967296 1",
+                "ERR bit offset is not an integer or out of range",
+            ),
+            ("SETBIT b 0 2", "ERR bit is not an integer or out of range"),
+            (
+                "GETBIT l 0",
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bitcount_bitpos() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET s foobar").await;
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s").await,
+            RespDataType::integers(26)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s 0 0").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s 1 1").await,
+            RespDataType::integers(6)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s -2 -1").await,
+            RespDataType::integers(7)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s 5 30 BIT").await,
+            RespDataType::integers(17)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT s 3 1").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITCOUNT missing").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(run_err(&mut db, "BITCOUNT s 0").await, "ERR syntax error");
+
+        run_ok(&mut db, "SET p \"\\xff\\xf0\\x00\"").await;
+        assert_eq!(
+            run_ok(&mut db, "BITPOS p 0").await,
+            RespDataType::integers(12)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS p 1 2").await,
+            RespDataType::integers(-1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS p 1 7 15 BIT").await,
+            RespDataType::integers(7)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS p 0 -3 -2").await,
+            RespDataType::integers(12)
+        );
+        // clear bits past the end count, unless the range has an end
+        run_ok(&mut db, "SET ones \"\\xff\\xff\"").await;
+        assert_eq!(
+            run_ok(&mut db, "BITPOS ones 0").await,
+            RespDataType::integers(16)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS ones 0 0 -1").await,
+            RespDataType::integers(-1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS missing 0").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITPOS missing 1").await,
+            RespDataType::integers(-1)
+        );
+        assert_eq!(
+            run_err(&mut db, "BITPOS p 2").await,
+            "ERR The bit argument must be 1 or 0."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bitop() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET a \"\\x0f\\xff\"").await;
+        run_ok(&mut db, "SET b \"\\xf0\"").await;
+        assert_eq!(
+            run_ok(&mut db, "BITOP AND dest a b").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET dest").await,
+            RespDataType::bulk_strings(&b"\x00\x00"[..])
+        );
+        run_ok(&mut db, "BITOP OR dest a b").await;
+        assert_eq!(
+            run_ok(&mut db, "GET dest").await,
+            RespDataType::bulk_strings(&b"\xff\xff"[..])
+        );
+        run_ok(&mut db, "BITOP XOR dest a b missing").await;
+        assert_eq!(
+            run_ok(&mut db, "GET dest").await,
+            RespDataType::bulk_strings(&b"\xff\xff"[..])
+        );
+        run_ok(&mut db, "BITOP NOT dest b").await;
+        assert_eq!(
+            run_ok(&mut db, "GET dest").await,
+            RespDataType::bulk_strings(&b"\x0f"[..])
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITOP AND dest missing").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS dest").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_err(&mut db, "BITOP NOT dest a b").await,
+            "ERR BITOP NOT must be called with a single source key."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bitfield() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD f SET i8 0 100 GET i8 0 INCRBY u4 #2 3").await,
+            integers(&[0, 100, 3])
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET f").await,
+            RespDataType::bulk_strings(&b"\x64\x30"[..])
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD f INCRBY i8 0 100").await,
+            integers(&[-56])
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "BITFIELD f OVERFLOW SAT INCRBY i8 0 -100 INCRBY u4 8 100"
+            )
+            .await,
+            integers(&[-128, 15])
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD f OVERFLOW FAIL INCRBY u4 8 1 GET u4 8").await,
+            RespDataType::arrays(vec![
+                RespDataType::empty_bulk_strings(),
+                RespDataType::integers(15)
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD f SET u8 0 -1 GET i64 0").await,
+            integers(&[128, -0x0010_0000_0000_0000])
+        );
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD_RO f GET u8 0 GET u4 12").await,
+            integers(&[255, 0])
+        );
+
+        // only reading does not create the key
+        assert_eq!(
+            run_ok(&mut db, "BITFIELD missing GET u8 0").await,
+            integers(&[0])
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS missing").await,
+            RespDataType::integers(0)
+        );
+        for (line, error) in &[
+            (
+                "BITFIELD f GET u64 0",
+                "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.",
+            ),
+            (
+                "BITFIELD f GET i8 -1",
+                "ERR bit offset is not an integer or out of range",
+            ),
+            (
+                "BITFIELD f OVERFLOW MAYBE",
+                "ERR Invalid OVERFLOW type specified",
+            ),
+            (
+                "BITFIELD_RO f SET u8 0 1",
+                "ERR BITFIELD_RO only supports the GET subcommand",
+            ),
+            ("BITFIELD f SET u8 0", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use super::{Arguments, Command};
+use crate::{
+    data_type::{Key, RespDataType},
+    db::Db,
+    util::{self, BoxFuture, GenericError},
+};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpireCondition {
+    /// `NX`, only if the key has no expiry
+    NotExists,
+    /// `XX`, only if the key has an expiry
+    Exists,
+    /// `GT`, only if the new expiry is later than the current one
+    GreaterThan,
+    /// `LT`, only if the new expiry is earlier than the current one
+    LessThan,
+}
+
+impl ExpireCondition {
+    pub(super) fn from_keyword(option: &str) -> Option<ExpireCondition> {
+        Some(match option {
+            "nx" => ExpireCondition::NotExists,
+            "xx" => ExpireCondition::Exists,
+            "gt" => ExpireCondition::GreaterThan,
+            "lt" => ExpireCondition::LessThan,
+            _ => return None,
+        })
+    }
+
+    /// Whether a deadline may be set to `new` under `condition`, both as unix timestamps
+    /// in milliseconds.
+    pub(super) fn allows(condition: Option<Self>, current: Option<i64>, new: i64) -> bool {
+        match (condition, current) {
+            (None, _) => true,
+            (Some(ExpireCondition::NotExists), current) => current.is_none(),
+            (Some(ExpireCondition::Exists), current) => current.is_some(),
+            // no expiry counts as expiring never
+            (Some(ExpireCondition::GreaterThan), None) => false,
+            (Some(ExpireCondition::GreaterThan), Some(current)) => new > current,
+            (Some(ExpireCondition::LessThan), None) => true,
+            (Some(ExpireCondition::LessThan), Some(current)) => new < current,
+        }
+    }
+}
+
+/// The reply of the `TTL` family for a deadline, see [`Ttl`].
+pub(super) fn ttl_reply(deadline: Instant, millis: bool, absolute: bool) -> i64 {
+    let time = if absolute {
+        util::unix_millis_from_instant(deadline)
+    } else {
+        deadline
+            .saturating_duration_since(Instant::now())
+            .as_millis() as i64
+    };
+    if millis {
+        time
+    } else if absolute {
+        time / 1000
+    } else {
+        // rounded, so that a key set to expire in 10 seconds reports 10
+        (time + 500) / 1000
+    }
+}
+
+/// `EXPIRE`, `PEXPIRE`, `EXPIREAT` and `PEXPIREAT`.
+#[derive(Debug, Clone)]
+pub struct Expire {
+    pub key: Key,
+    /// The new deadline in milliseconds, from when the command runs unless `absolute`
+    pub millis: i64,
+    /// Whether `millis` is a unix timestamp
+    pub absolute: bool,
+    pub condition: Option<ExpireCondition>,
+}
+
+/// `TTL`, `PTTL`, `EXPIRETIME` and `PEXPIRETIME`.
+#[derive(Debug, Clone)]
+pub struct Ttl {
+    pub key: Key,
+    /// Reply in milliseconds instead of seconds
+    pub millis: bool,
+    /// Reply with the deadline as a unix timestamp instead of the time left
+    pub absolute: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Persist {
+    pub key: Key,
+}
+
+impl Expire {
+    /// `unit` is the number of milliseconds the given time is counted in, `absolute` is
+    /// whether it is a unix timestamp rather than a time from now.
+    pub(super) fn parse(mut args: Arguments, unit: i64, absolute: bool) -> util::Result<Expire> {
+        let command = args.command;
+        let invalid = || -> GenericError {
+            format!("ERR invalid expire time in '{}' command", command).into()
+        };
+
+        let key = args.next_key()?;
+        let time = args.next_integer()?;
+        let mut condition = None;
+        while !args.is_empty() {
+            let option = args.next_keyword()?;
+            let new = ExpireCondition::from_keyword(&option)
+                .ok_or_else(|| format!("ERR Unsupported option {}", option))?;
+            condition = match (condition, new) {
+                (None, new) => Some(new),
+                (Some(old), new) if old == new => Some(new),
+                (Some(ExpireCondition::NotExists), _) | (_, ExpireCondition::NotExists) => {
+                    return Err(
+                        "ERR NX and XX, GT or LT options at the same time are not compatible"
+                            .into(),
+                    )
+                }
+                (Some(ExpireCondition::Exists), new) | (Some(new), ExpireCondition::Exists) => {
+                    // XX combines with either of GT and LT
+                    Some(new)
+                }
+                _ => return Err("ERR GT and LT options at the same time are not compatible".into()),
+            };
+        }
+
+        let millis = time.checked_mul(unit).ok_or_else(invalid)?;
+        if !absolute {
+            // an overflowing deadline is refused when queued already
+            millis
+                .checked_add(util::unix_millis())
+                .ok_or_else(invalid)?;
+        }
+        Ok(Expire {
+            key,
+            millis,
+            absolute,
+            condition,
+        })
+    }
+}
+
+impl Ttl {
+    pub(super) fn parse(mut args: Arguments, millis: bool, absolute: bool) -> util::Result<Ttl> {
+        args.expect_len(1)?;
+        Ok(Ttl {
+            key: args.next_key()?,
+            millis,
+            absolute,
+        })
+    }
+}
+
+impl Persist {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Persist> {
+        args.expect_len(1)?;
+        Ok(Persist {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Expire {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = match context.get(&self.key) {
+                Some(entry) => entry.deadline().map(util::unix_millis_from_instant),
+                None => return Ok(RespDataType::integers(0)),
+            };
+
+            // relative to when it runs, which is later than it was parsed inside MULTI
+            let now = util::unix_millis();
+            let unix_millis = match self.absolute {
+                true => self.millis,
+                false => self.millis.saturating_add(now),
+            };
+            if !ExpireCondition::allows(self.condition, current, unix_millis) {
+                return Ok(RespDataType::integers(0));
+            }
+
+            if unix_millis <= now {
+                // a deadline in the past deletes the key right away
+                context.remove(&self.key);
+            } else {
+                let deadline = util::instant_from_unix_millis(unix_millis as u64);
+                context.set_deadline(&self.key, Some(deadline));
+            }
+            Ok(RespDataType::integers(1))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Ttl {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let deadline = match context.get(&self.key) {
+                None => return Ok(RespDataType::integers(-2)),
+                Some(entry) => match entry.deadline() {
+                    None => return Ok(RespDataType::integers(-1)),
+                    Some(deadline) => deadline,
+                },
+            };
+            Ok(RespDataType::integers(ttl_reply(
+                deadline,
+                self.millis,
+                self.absolute,
+            )))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Persist {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let volatile = matches!(context.get(&self.key), Some(x) if x.deadline().is_some());
+            if volatile {
+                context.set_deadline(&self.key, None);
+            }
+            Ok(RespDataType::integers(volatile as i64))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{command::test_util::run, data_type::RespDataType, db::Db};
+
+    async fn run_int(db: &mut Db, line: &str) -> i64 {
+        match run(db, line).await.expect("command succeeds") {
+            RespDataType::Integers(n) => n,
+            other => panic!("expected an integer, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ttl_replies() {
+        let mut db = Db::new();
+        assert_eq!(run_int(&mut db, "TTL missing").await, -2);
+        assert_eq!(run_int(&mut db, "PTTL missing").await, -2);
+        assert_eq!(run_int(&mut db, "EXPIRETIME missing").await, -2);
+        run(&mut db, "SET key v").await.unwrap();
+        assert_eq!(run_int(&mut db, "TTL key").await, -1);
+        assert_eq!(run_int(&mut db, "PEXPIRETIME key").await, -1);
+
+        assert_eq!(run_int(&mut db, "EXPIRE key 100").await, 1);
+        assert_eq!(run_int(&mut db, "TTL key").await, 100);
+        let pttl = run_int(&mut db, "PTTL key").await;
+        assert!(pttl > 99_000 && pttl <= 100_000);
+
+        assert_eq!(run_int(&mut db, "PEXPIREAT key 4102444800000").await, 1);
+        assert_eq!(run_int(&mut db, "EXPIRETIME key").await, 4102444800);
+        let pexpiretime = run_int(&mut db, "PEXPIRETIME key").await;
+        assert!((pexpiretime - 4102444800000).abs() <= 1);
+
+        assert_eq!(run_int(&mut db, "PERSIST key").await, 1);
+        assert_eq!(run_int(&mut db, "PERSIST key").await, 0);
+        assert_eq!(run_int(&mut db, "TTL key").await, -1);
+        assert_eq!(run_int(&mut db, "PERSIST missing").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expire_in_the_past_deletes() {
+        let mut db = Db::new();
+        assert_eq!(run_int(&mut db, "EXPIRE missing 100").await, 0);
+        run(&mut db, "MSET a 1 b 2 c 3").await.unwrap();
+        assert_eq!(run_int(&mut db, "EXPIRE a -1").await, 1);
+        assert_eq!(run_int(&mut db, "PEXPIRE b 0").await, 1);
+        assert_eq!(run_int(&mut db, "EXPIREAT c 1").await, 1);
+        assert_eq!(run_int(&mut db, "EXISTS a b c").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expire_conditions() {
+        let mut db = Db::new();
+        run(&mut db, "SET key v").await.unwrap();
+        assert_eq!(run_int(&mut db, "EXPIRE key 100 XX").await, 0);
+        assert_eq!(run_int(&mut db, "EXPIRE key 100 GT").await, 0);
+        assert_eq!(run_int(&mut db, "EXPIRE key 100 NX").await, 1);
+        assert_eq!(run_int(&mut db, "EXPIRE key 200 NX").await, 0);
+        assert_eq!(run_int(&mut db, "EXPIRE key 50 GT").await, 0);
+        assert_eq!(run_int(&mut db, "EXPIRE key 200 GT").await, 1);
+        assert_eq!(run_int(&mut db, "TTL key").await, 200);
+        assert_eq!(run_int(&mut db, "EXPIRE key 300 LT").await, 0);
+        assert_eq!(run_int(&mut db, "EXPIRE key 150 LT XX").await, 1);
+        assert_eq!(run_int(&mut db, "TTL key").await, 150);
+
+        run(&mut db, "PERSIST key").await.unwrap();
+        assert_eq!(run_int(&mut db, "EXPIRE key 300 LT").await, 1);
+        assert_eq!(run_int(&mut db, "TTL key").await, 300);
+
+        for (line, error) in &[
+            (
+                "EXPIRE key 10 NX XX",
+                "ERR NX and XX, GT or LT options at the same time are not compatible",
+            ),
+            (
+                "EXPIRE key 10 GT LT",
+                "ERR GT and LT options at the same time are not compatible",
+            ),
+            ("EXPIRE key 10 FOO", "ERR Unsupported option foo"),
+            (
+                "EXPIRE key 9223372036854775807",
+                "ERR invalid expire time in 'expire' command",
+            ),
+            (
+                "PEXPIRE key 9223372036854775807",
+                "ERR invalid expire time in 'pexpire' command",
+            ),
+            (
+                "EXPIRE key ten",
+                "ERR value is not an integer or out of range",
+            ),
+        ] {
+            assert_eq!(run(&mut db, line).await.unwrap_err().to_string(), *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sliding_expire() {
+        let mut db = Db::new();
+        run(&mut db, "SET session data EX 10").await.unwrap();
+        assert_eq!(run_int(&mut db, "EXPIRE session 1000").await, 1);
+        assert_eq!(run_int(&mut db, "TTL session").await, 1000);
+        // overwriting the value drops the expiry, as in redis
+        run(&mut db, "SET session other").await.unwrap();
+        assert_eq!(run_int(&mut db, "TTL session").await, -1);
+    }
+}
@@ -0,0 +1,715 @@
+use super::{
+    zset::{get_zset, store},
+    Arguments, Command, ZAdd, SYNTAX_ERROR,
+};
+use crate::{
+    data_type::{geohash, GeoHashBits, GeoShape, Key, RespDataType, ScoreRange, SortedSet},
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct GeoDist {
+    pub key: Key,
+    pub members: (Bytes, Bytes),
+    /// Meters in the unit of the reply
+    pub unit: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoHash {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeoPos {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+/// Where `GEOSEARCH` searches around.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoFrom {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+/// `GEOSEARCH` and `GEOSEARCHSTORE`.
+#[derive(Debug, Clone)]
+pub struct GeoSearch {
+    pub key: Key,
+    pub from: GeoFrom,
+    /// Sizes in meters
+    pub shape: GeoShape,
+    /// Meters in the unit of the sizes and the distances
+    pub unit: f64,
+    /// `ASC` or `DESC`, by distance
+    pub desc: Option<bool>,
+    pub count: Option<usize>,
+    /// `ANY`, stop at the first `count` matches rather than the closest ones
+    pub any: bool,
+    pub with_coord: bool,
+    pub with_dist: bool,
+    pub with_hash: bool,
+    /// Where `GEOSEARCHSTORE` stores the matches
+    pub destination: Option<Key>,
+    /// `STOREDIST`, store the distances rather than the geohashes as scores
+    pub store_dist: bool,
+}
+
+/// A member matching a search.
+struct GeoMatch {
+    member: Bytes,
+    hash: GeoHashBits,
+    distance: f64,
+}
+
+fn parse_unit(args: &mut Arguments) -> util::Result<f64> {
+    match args.next_keyword()?.as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err("ERR unsupported unit provided. please use M, KM, FT, MI".into()),
+    }
+}
+
+fn parse_position(args: &mut Arguments) -> util::Result<(f64, f64)> {
+    let (longitude, latitude) = (args.next_float()?, args.next_float()?);
+    if !geohash::is_valid(longitude, latitude) {
+        return Err(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            longitude, latitude
+        )
+        .into());
+    }
+    Ok((longitude, latitude))
+}
+
+fn parse_size(args: &mut Arguments, name: &str) -> util::Result<f64> {
+    let arg = args.next_bytes()?;
+    util::parse_float(&arg).ok_or_else(|| format!("ERR need numeric {}", name).into())
+}
+
+/// The position of a member, from its score.
+fn position(zset: &SortedSet, member: &[u8]) -> Option<(f64, f64)> {
+    zset.score(member)
+        .map(|score| GeoHashBits::from_score(score).decode())
+}
+
+fn distance_reply(meters: f64, unit: f64) -> RespDataType {
+    RespDataType::bulk_strings(format!("{:.4}", meters / unit))
+}
+
+fn position_reply((longitude, latitude): (f64, f64)) -> RespDataType {
+    RespDataType::arrays(vec![
+        RespDataType::bulk_strings(util::format_double(longitude)),
+        RespDataType::bulk_strings(util::format_double(latitude)),
+    ])
+}
+
+impl ZAdd {
+    pub(super) fn parse_geoadd(mut args: Arguments) -> util::Result<ZAdd> {
+        if args.len() < 4 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let (mut nx, mut xx, mut ch) = (false, false, false);
+        loop {
+            match args.peek_keyword().as_deref() {
+                Some("nx") => nx = true,
+                Some("xx") => xx = true,
+                Some("ch") => ch = true,
+                _ => break,
+            }
+            args.next_bytes()?;
+        }
+        if args.is_empty() || !args.len().is_multiple_of(3) || (nx && xx) {
+            return Err(SYNTAX_ERROR.into());
+        }
+        let mut pairs = vec![];
+        while !args.is_empty() {
+            let (longitude, latitude) = parse_position(&mut args)?;
+            let hash = GeoHashBits::encode(longitude, latitude, geohash::STEP_MAX);
+            pairs.push((hash.score(), args.next_bytes()?));
+        }
+        Ok(ZAdd {
+            key,
+            pairs,
+            nx,
+            xx,
+            gt: false,
+            lt: false,
+            ch,
+            incr: false,
+        })
+    }
+}
+
+impl GeoDist {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GeoDist> {
+        if !(3..=4).contains(&args.len()) {
+            return Err(if args.len() > 4 {
+                SYNTAX_ERROR.into()
+            } else {
+                args.wrong_arity()
+            });
+        }
+        let key = args.next_key()?;
+        let members = (args.next_bytes()?, args.next_bytes()?);
+        let unit = match args.is_empty() {
+            true => 1.0,
+            false => parse_unit(&mut args)?,
+        };
+        Ok(GeoDist { key, members, unit })
+    }
+}
+
+impl GeoHash {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GeoHash> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut members = vec![];
+        while !args.is_empty() {
+            members.push(args.next_bytes()?);
+        }
+        Ok(GeoHash { key, members })
+    }
+}
+
+impl GeoPos {
+    pub(super) fn parse(args: Arguments) -> util::Result<GeoPos> {
+        GeoHash::parse(args).map(|x| GeoPos {
+            key: x.key,
+            members: x.members,
+        })
+    }
+}
+
+impl GeoSearch {
+    pub(super) fn parse(mut args: Arguments, store: bool) -> util::Result<GeoSearch> {
+        if args.len() < if store { 7 } else { 6 } {
+            return Err(args.wrong_arity());
+        }
+        let destination = match store {
+            true => Some(args.next_key()?),
+            false => None,
+        };
+        let key = args.next_key()?;
+        let (mut from, mut shape, mut unit) = (None, None, 1.0);
+        let mut search = GeoSearch {
+            key,
+            from: GeoFrom::LonLat(0.0, 0.0),
+            shape: GeoShape::Radius(0.0),
+            unit,
+            desc: None,
+            count: None,
+            any: false,
+            with_coord: false,
+            with_dist: false,
+            with_hash: false,
+            destination,
+            store_dist: false,
+        };
+        let from_error = format!(
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for {}",
+            args.command
+        );
+        let by_error = format!(
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for {}",
+            args.command
+        );
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "frommember" if from.is_none() => {
+                    from = Some(GeoFrom::Member(args.next_bytes()?));
+                }
+                "fromlonlat" if from.is_none() => {
+                    let (longitude, latitude) = parse_position(&mut args)?;
+                    from = Some(GeoFrom::LonLat(longitude, latitude));
+                }
+                "frommember" | "fromlonlat" => return Err(from_error.into()),
+                "byradius" if shape.is_none() => {
+                    let radius = parse_size(&mut args, "radius")?;
+                    if radius < 0.0 {
+                        return Err("ERR radius cannot be negative".into());
+                    }
+                    unit = parse_unit(&mut args)?;
+                    shape = Some(GeoShape::Radius(radius * unit));
+                }
+                "bybox" if shape.is_none() => {
+                    let width = parse_size(&mut args, "width")?;
+                    let height = parse_size(&mut args, "height")?;
+                    if width < 0.0 || height < 0.0 {
+                        return Err("ERR height or width cannot be negative".into());
+                    }
+                    unit = parse_unit(&mut args)?;
+                    shape = Some(GeoShape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                    });
+                }
+                "byradius" | "bybox" => return Err(by_error.into()),
+                "asc" => search.desc = Some(false),
+                "desc" => search.desc = Some(true),
+                "count" => {
+                    let count = args.next_integer()?;
+                    if count <= 0 {
+                        return Err("ERR COUNT must be > 0".into());
+                    }
+                    search.count = Some(count as usize);
+                    if args.peek_keyword().as_deref() == Some("any") {
+                        args.next_bytes()?;
+                        search.any = true;
+                    }
+                }
+                "withcoord" => search.with_coord = true,
+                "withdist" => search.with_dist = true,
+                "withhash" => search.with_hash = true,
+                "storedist" if store => search.store_dist = true,
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+
+        search.from = from.ok_or(from_error)?;
+        search.shape = shape.ok_or(by_error)?;
+        search.unit = unit;
+        if search.any && search.count.is_none() {
+            return Err("ERR the ANY argument requires COUNT argument".into());
+        }
+        if store && (search.with_coord || search.with_dist || search.with_hash) {
+            return Err(
+                "ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options"
+                    .into(),
+            );
+        }
+        // only the closest members make sense to keep a number of
+        if search.count.is_some() && !search.any && search.desc.is_none() {
+            search.desc = Some(false);
+        }
+        Ok(search)
+    }
+
+    /// The members in the shape, in the order to reply with.
+    fn search(&self, zset: &SortedSet) -> util::Result<Vec<GeoMatch>> {
+        let center = match &self.from {
+            GeoFrom::LonLat(longitude, latitude) => (*longitude, *latitude),
+            GeoFrom::Member(member) => {
+                position(zset, member).ok_or("ERR could not decode requested zset member")?
+            }
+        };
+        let mut matches = vec![];
+        'areas: for area in self.shape.search_areas(center) {
+            let (min, max) = area.score_range();
+            let range = ScoreRange {
+                min,
+                max,
+                min_exclusive: false,
+                max_exclusive: true,
+            };
+            let (first, last) = match zset.score_ranks(&range) {
+                Some(ranks) => ranks,
+                None => continue,
+            };
+            for (member, score) in zset.range(first, last) {
+                let hash = GeoHashBits::from_score(score);
+                if let Some(distance) = self.shape.distance(center, hash.decode()) {
+                    matches.push(GeoMatch {
+                        member,
+                        hash,
+                        distance,
+                    });
+                    if self.any && Some(matches.len()) == self.count {
+                        break 'areas;
+                    }
+                }
+            }
+        }
+        match self.desc {
+            Some(false) => matches.sort_by(|a, b| a.distance.total_cmp(&b.distance)),
+            Some(true) => matches.sort_by(|a, b| b.distance.total_cmp(&a.distance)),
+            None => {}
+        }
+        if let Some(count) = self.count {
+            matches.truncate(count);
+        }
+        Ok(matches)
+    }
+}
+
+impl<'a> Command<'a, Db> for GeoDist {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = match get_zset(context, &self.key)? {
+                Some(zset) => zset,
+                None => return Ok(RespDataType::empty_bulk_strings()),
+            };
+            match (
+                position(zset, &self.members.0),
+                position(zset, &self.members.1),
+            ) {
+                (Some((long1, lat1)), Some((long2, lat2))) => Ok(distance_reply(
+                    geohash::distance(long1, lat1, long2, lat2),
+                    self.unit,
+                )),
+                _ => Ok(RespDataType::empty_bulk_strings()),
+            }
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GeoHash {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = get_zset(context, &self.key)?;
+            Ok(RespDataType::arrays(
+                self.members
+                    .iter()
+                    .map(|member| match zset.and_then(|x| x.score(member)) {
+                        Some(score) => {
+                            RespDataType::bulk_strings(GeoHashBits::from_score(score).to_base32())
+                        }
+                        None => RespDataType::empty_bulk_strings(),
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GeoPos {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = get_zset(context, &self.key)?;
+            Ok(RespDataType::arrays(
+                self.members
+                    .iter()
+                    .map(|member| match zset.and_then(|x| position(x, member)) {
+                        Some(position) => position_reply(position),
+                        None => RespDataType::empty_arrays(),
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GeoSearch {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let matches = match get_zset(context, &self.key)? {
+                Some(zset) => self.search(zset)?,
+                None => vec![],
+            };
+
+            if let Some(destination) = &self.destination {
+                let mut zset = SortedSet::new();
+                for found in matches {
+                    let score = match self.store_dist {
+                        true => found.distance / self.unit,
+                        false => found.hash.score(),
+                    };
+                    zset.insert(found.member, score);
+                }
+                return Ok(RespDataType::integers(
+                    store(context, destination, zset) as i64
+                ));
+            }
+
+            let plain = !(self.with_dist || self.with_hash || self.with_coord);
+            Ok(RespDataType::arrays(
+                matches
+                    .into_iter()
+                    .map(|found| {
+                        let member = RespDataType::bulk_strings(found.member);
+                        if plain {
+                            return member;
+                        }
+                        let mut reply = vec![member];
+                        if self.with_dist {
+                            reply.push(distance_reply(found.distance, self.unit));
+                        }
+                        if self.with_hash {
+                            reply.push(RespDataType::integers(found.hash.bits as i64));
+                        }
+                        if self.with_coord {
+                            reply.push(position_reply(found.hash.decode()));
+                        }
+                        RespDataType::arrays(reply)
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    fn bulks(values: &[&str]) -> RespDataType {
+        RespDataType::arrays(
+            values
+                .iter()
+                .map(|x| RespDataType::bulk_strings(x.to_string()))
+                .collect(),
+        )
+    }
+
+    async fn sicily(db: &mut Db) {
+        assert_eq!(
+            run_ok(
+                db,
+                "GEOADD Sicily 13.361389 38.115556 Palermo 15.087269 37.502669 Catania"
+            )
+            .await,
+            RespDataType::integers(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_geoadd() {
+        let mut db = Db::new();
+        sicily(&mut db).await;
+        assert_eq!(
+            run_ok(&mut db, "ZRANGE Sicily 0 -1 WITHSCORES").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("Palermo"),
+                RespDataType::doubles(3479099956230698.0),
+                RespDataType::bulk_strings("Catania"),
+                RespDataType::doubles(3479447370796909.0),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEOADD Sicily NX CH 13 38 Palermo 14 38 Enna").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEOADD Sicily XX CH 13 38 Palermo 14 38 Messina").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZCARD Sicily").await,
+            RespDataType::integers(3)
+        );
+
+        for (line, error) in &[
+            (
+                "GEOADD Sicily 181 38 Nowhere",
+                "ERR invalid longitude,latitude pair 181.000000,38.000000",
+            ),
+            ("GEOADD Sicily NX XX 13 38 Palermo", "ERR syntax error"),
+            ("GEOADD Sicily GT 13 38 Palermo", "ERR syntax error"),
+            ("GEOADD Sicily 13 38 Palermo 14", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_geodist_geohash_geopos() {
+        let mut db = Db::new();
+        sicily(&mut db).await;
+        assert_eq!(
+            run_ok(&mut db, "GEODIST Sicily Palermo Catania").await,
+            RespDataType::bulk_strings("166274.1516")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEODIST Sicily Palermo Catania km").await,
+            RespDataType::bulk_strings("166.2742")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEODIST Sicily Palermo Catania MI").await,
+            RespDataType::bulk_strings("103.3182")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEODIST Sicily Palermo Rome").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_err(&mut db, "GEODIST Sicily Palermo Catania yd").await,
+            "ERR unsupported unit provided. please use M, KM, FT, MI"
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "GEOHASH Sicily Palermo Catania Rome").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("sqc8b49rny0"),
+                RespDataType::bulk_strings("sqdtr74hyu0"),
+                RespDataType::empty_bulk_strings(),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEOPOS Sicily Palermo Rome").await,
+            RespDataType::arrays(vec![
+                bulks(&["13.361389338970184", "38.1155563954963"]),
+                RespDataType::empty_arrays(),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEOPOS missing Palermo").await,
+            RespDataType::arrays(vec![RespDataType::empty_arrays()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_geosearch() {
+        let mut db = Db::new();
+        sicily(&mut db).await;
+        run_ok(
+            &mut db,
+            "GEOADD Sicily 12.758489 38.788135 edge1 17.241510 38.788135 edge2",
+        )
+        .await;
+
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km ASC"
+            )
+            .await,
+            bulks(&["Catania", "Palermo"])
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYBOX 400 400 km ASC WITHDIST"
+            )
+            .await,
+            RespDataType::arrays(vec![
+                bulks(&["Catania", "56.4413"]),
+                bulks(&["Palermo", "190.4424"]),
+                bulks(&["edge2", "279.7403"]),
+                bulks(&["edge1", "279.7405"]),
+            ])
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCH Sicily FROMMEMBER Palermo BYRADIUS 200 km DESC COUNT 2 WITHHASH"
+            )
+            .await,
+            RespDataType::arrays(vec![
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("Catania"),
+                    RespDataType::integers(3479447370796909),
+                ]),
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("edge1"),
+                    RespDataType::integers(3479273021651468),
+                ]),
+            ])
+        );
+        // COUNT alone keeps the closest ones
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 1000 km COUNT 1 WITHCOORD"
+            )
+            .await,
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("Catania"),
+                bulks(&["15.087267458438873", "37.50266842333162"]),
+            ])])
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCH missing FROMLONLAT 15 37 BYRADIUS 200 km"
+            )
+            .await,
+            RespDataType::arrays(vec![])
+        );
+
+        for (line, error) in &[
+            (
+                "GEOSEARCH Sicily BYRADIUS 200 km ASC WITHDIST",
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 FROMMEMBER Palermo",
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 ASC WITHDIST",
+                "ERR exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+            ),
+            (
+                "GEOSEARCH Sicily FROMMEMBER Rome BYRADIUS 200 km",
+                "ERR could not decode requested zset member",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS -1 km",
+                "ERR radius cannot be negative",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km ANY",
+                "ERR syntax error",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km COUNT 0",
+                "ERR COUNT must be > 0",
+            ),
+            (
+                "GEOSEARCH Sicily FROMLONLAT 15 37 BYRADIUS 200 km STOREDIST",
+                "ERR syntax error",
+            ),
+            (
+                "GEOSEARCHSTORE dest Sicily FROMLONLAT 15 37 BYRADIUS 200 km WITHDIST",
+                "ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_geosearchstore() {
+        let mut db = Db::new();
+        sicily(&mut db).await;
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCHSTORE dest Sicily FROMLONLAT 15 37 BYRADIUS 200 km"
+            )
+            .await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GEOHASH dest Palermo").await,
+            bulks(&["sqc8b49rny0"])
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCHSTORE dist Sicily FROMLONLAT 15 37 BYRADIUS 200 km COUNT 1 STOREDIST"
+            )
+            .await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZSCORE dist Catania").await,
+            RespDataType::doubles(56.4412578701582)
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "GEOSEARCHSTORE dest Sicily FROMLONLAT 0 0 BYRADIUS 1 km"
+            )
+            .await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS dest").await,
+            RespDataType::integers(0)
+        );
+    }
+}
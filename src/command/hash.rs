@@ -0,0 +1,1238 @@
+use super::{expire, Arguments, Command, ExpireCondition, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{Hash, Key, RedisDataType, RedisDataTypeWithTTL, RespDataType},
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+
+/// `HSET` and `HMSET`.
+#[derive(Debug, Clone)]
+pub struct HSet {
+    pub key: Key,
+    pub pairs: Vec<(Bytes, Bytes)>,
+    /// `HMSET`, replying with `OK` rather than the number of new fields
+    pub ok: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HSetNx {
+    pub key: Key,
+    pub field: Bytes,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct HGet {
+    pub key: Key,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct HMGet {
+    pub key: Key,
+    pub fields: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HDel {
+    pub key: Key,
+    pub fields: Vec<Bytes>,
+}
+
+/// `HGETALL`, `HKEYS` and `HVALS`.
+#[derive(Debug, Clone)]
+pub struct HGetAll {
+    pub key: Key,
+    pub fields: bool,
+    pub values: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HLen {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct HExists {
+    pub key: Key,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct HStrlen {
+    pub key: Key,
+    pub field: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct HIncrBy {
+    pub key: Key,
+    pub field: Bytes,
+    pub delta: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct HIncrByFloat {
+    pub key: Key,
+    pub field: Bytes,
+    pub delta: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct HRandField {
+    pub key: Key,
+    /// Reply with an array of this many fields, possibly repeated if negative
+    pub count: Option<i64>,
+    pub with_values: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HScan {
+    pub key: Key,
+    pub scan: Scan,
+    /// `NOVALUES`, only reply with the fields
+    pub no_values: bool,
+}
+
+/// `HEXPIRE`, `HPEXPIRE`, `HEXPIREAT` and `HPEXPIREAT`.
+#[derive(Debug, Clone)]
+pub struct HExpire {
+    pub key: Key,
+    /// The new deadline in milliseconds, from when the command runs unless `absolute`
+    pub millis: i64,
+    /// Whether `millis` is a unix timestamp
+    pub absolute: bool,
+    pub condition: Option<ExpireCondition>,
+    pub fields: Vec<Bytes>,
+}
+
+/// `HTTL`, `HPTTL`, `HEXPIRETIME` and `HPEXPIRETIME`.
+#[derive(Debug, Clone)]
+pub struct HTtl {
+    pub key: Key,
+    pub fields: Vec<Bytes>,
+    /// Reply in milliseconds instead of seconds
+    pub millis: bool,
+    /// Reply with the deadlines as unix timestamps instead of the time left
+    pub absolute: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct HPersist {
+    pub key: Key,
+    pub fields: Vec<Bytes>,
+}
+
+/// The cursor and options shared by the `*SCAN` commands.
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub cursor: u64,
+    /// `MATCH`, only reply with the names matching this glob-style pattern
+    pub pattern: Option<Bytes>,
+    /// `COUNT`, roughly how many elements to go through
+    pub count: usize,
+}
+
+impl Scan {
+    /// Parses `cursor [MATCH pattern] [COUNT count]`, leaving the options it does not
+    /// know to the caller.
+    pub(super) fn parse(
+        args: &mut Arguments,
+        mut other: impl FnMut(&str) -> bool,
+    ) -> util::Result<Scan> {
+        let cursor = args.next_bytes()?;
+        let cursor = std::str::from_utf8(&cursor)
+            .ok()
+            .and_then(|x| x.parse().ok())
+            .ok_or("ERR invalid cursor")?;
+        let mut scan = Scan {
+            cursor,
+            pattern: None,
+            count: 10,
+        };
+        while !args.is_empty() {
+            let option = args.next_keyword()?;
+            match option.as_str() {
+                "match" if !args.is_empty() => scan.pattern = Some(args.next_bytes()?),
+                "count" if !args.is_empty() => {
+                    scan.count = match args.next_integer()? {
+                        count if count < 1 => return Err(SYNTAX_ERROR.into()),
+                        count => count as usize,
+                    };
+                }
+                option if other(option) => {}
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Whether an element of the page goes in the reply, as far as the pattern goes.
+    pub(super) fn matches(&self, name: &[u8]) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| util::glob_match(pattern, name, false))
+    }
+
+    pub(super) fn reply(cursor: u64, elements: Vec<RespDataType>) -> RespDataType {
+        RespDataType::arrays(vec![
+            RespDataType::bulk_strings(cursor.to_string()),
+            RespDataType::arrays(elements),
+        ])
+    }
+}
+
+/// Looks up a hash, `None` if the key does not exist.
+fn get_hash<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a Hash>> {
+    match db.get(key).map(|x| x.value()) {
+        None => Ok(None),
+        Some(RedisDataType::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+fn get_hash_mut<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a mut Hash>> {
+    match db.get_mut(key).map(|x| x.value_mut()) {
+        None => Ok(None),
+        Some(RedisDataType::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+/// Looks up a hash to write to, creating it if needed.
+fn get_or_create_hash<'a>(db: &'a mut Db, key: &Key) -> util::Result<&'a mut Hash> {
+    if get_hash(db, key)?.is_none() {
+        db.insert(
+            key.clone(),
+            RedisDataTypeWithTTL::Infinite(RedisDataType::Hash(Hash::new())),
+        );
+    }
+    Ok(get_hash_mut(db, key)?.expect("the hash exists"))
+}
+
+/// Sets a field the way increments do, keeping its deadline.
+fn update(hash: &mut Hash, field: &Bytes, value: Bytes) {
+    match hash.get_mut(field) {
+        Some(current) => *current = value,
+        None => {
+            hash.insert(field.clone(), value);
+        }
+    }
+}
+
+fn parse_fields(args: &mut Arguments) -> util::Result<Vec<Bytes>> {
+    if args.is_empty() {
+        return Err(args.wrong_arity());
+    }
+    let mut fields = vec![];
+    while !args.is_empty() {
+        fields.push(args.next_bytes()?);
+    }
+    Ok(fields)
+}
+
+impl HSet {
+    pub(super) fn parse(mut args: Arguments, ok: bool) -> util::Result<HSet> {
+        if args.len() < 3 || args.len().is_multiple_of(2) {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut pairs = vec![];
+        while !args.is_empty() {
+            pairs.push((args.next_bytes()?, args.next_bytes()?));
+        }
+        Ok(HSet { key, pairs, ok })
+    }
+}
+
+impl HSetNx {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HSetNx> {
+        args.expect_len(3)?;
+        Ok(HSetNx {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl HGet {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HGet> {
+        args.expect_len(2)?;
+        Ok(HGet {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl HMGet {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HMGet> {
+        let key = args.next_key()?;
+        Ok(HMGet {
+            key,
+            fields: parse_fields(&mut args)?,
+        })
+    }
+}
+
+impl HDel {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HDel> {
+        let key = args.next_key()?;
+        Ok(HDel {
+            key,
+            fields: parse_fields(&mut args)?,
+        })
+    }
+}
+
+impl HGetAll {
+    pub(super) fn parse(mut args: Arguments, fields: bool, values: bool) -> util::Result<HGetAll> {
+        args.expect_len(1)?;
+        Ok(HGetAll {
+            key: args.next_key()?,
+            fields,
+            values,
+        })
+    }
+}
+
+impl HLen {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HLen> {
+        args.expect_len(1)?;
+        Ok(HLen {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl HExists {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HExists> {
+        args.expect_len(2)?;
+        Ok(HExists {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl HStrlen {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HStrlen> {
+        args.expect_len(2)?;
+        Ok(HStrlen {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+        })
+    }
+}
+
+impl HIncrBy {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HIncrBy> {
+        args.expect_len(3)?;
+        Ok(HIncrBy {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+            delta: args.next_integer()?,
+        })
+    }
+}
+
+impl HIncrByFloat {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HIncrByFloat> {
+        args.expect_len(3)?;
+        Ok(HIncrByFloat {
+            key: args.next_key()?,
+            field: args.next_bytes()?,
+            delta: args.next_float()?,
+        })
+    }
+}
+
+impl HRandField {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HRandField> {
+        if args.is_empty() || args.len() > 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let count = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_random_count()?)
+        };
+        let with_values = match args.is_empty() {
+            true => false,
+            false if args.next_keyword()? == "withvalues" => true,
+            false => return Err(SYNTAX_ERROR.into()),
+        };
+        Ok(HRandField {
+            key,
+            count,
+            with_values,
+        })
+    }
+}
+
+impl HScan {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HScan> {
+        let key = args.next_key()?;
+        let mut no_values = false;
+        let scan = Scan::parse(&mut args, |option| {
+            no_values |= option == "novalues";
+            option == "novalues"
+        })?;
+        Ok(HScan {
+            key,
+            scan,
+            no_values,
+        })
+    }
+}
+
+/// Parses the `FIELDS numfields field [field ...]` ending the field expiry commands.
+fn parse_numfields(args: &mut Arguments) -> util::Result<Vec<Bytes>> {
+    if args.is_empty() || args.next_keyword()? != "fields" {
+        return Err("ERR Mandatory argument FIELDS is missing or not at the right position".into());
+    }
+    let count = args.next_integer()?;
+    if count <= 0 {
+        return Err("ERR Parameter `numFields` should be greater than 0".into());
+    }
+    if count as usize != args.len() {
+        return Err("ERR The `numfields` parameter must match the number of arguments".into());
+    }
+    parse_fields(args)
+}
+
+impl HExpire {
+    /// `unit` is the number of milliseconds the given time is counted in, `absolute` is
+    /// whether it is a unix timestamp rather than a time from now.
+    pub(super) fn parse(mut args: Arguments, unit: i64, absolute: bool) -> util::Result<HExpire> {
+        let command = args.command;
+        let invalid = || format!("ERR invalid expire time in '{}' command", command);
+        if args.len() < 5 {
+            return Err(args.wrong_arity());
+        }
+
+        let key = args.next_key()?;
+        let time = args.next_integer()?;
+        if time < 0 {
+            return Err(invalid().into());
+        }
+        let mut condition = None;
+        if let Some(option) = args.peek_keyword() {
+            condition = ExpireCondition::from_keyword(&option);
+            if condition.is_some() {
+                args.next_keyword()?;
+            }
+        }
+        let fields = parse_numfields(&mut args)?;
+
+        let millis = time.checked_mul(unit).ok_or_else(invalid)?;
+        if !absolute {
+            // an overflowing deadline is refused when queued already
+            millis
+                .checked_add(util::unix_millis())
+                .ok_or_else(invalid)?;
+        }
+        Ok(HExpire {
+            key,
+            millis,
+            absolute,
+            condition,
+            fields,
+        })
+    }
+}
+
+impl HTtl {
+    pub(super) fn parse(mut args: Arguments, millis: bool, absolute: bool) -> util::Result<HTtl> {
+        if args.len() < 4 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        Ok(HTtl {
+            key,
+            fields: parse_numfields(&mut args)?,
+            millis,
+            absolute,
+        })
+    }
+}
+
+impl HPersist {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<HPersist> {
+        if args.len() < 4 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        Ok(HPersist {
+            key,
+            fields: parse_numfields(&mut args)?,
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HSet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = get_or_create_hash(context, &self.key)?;
+            let added = self
+                .pairs
+                .iter()
+                .filter(|(field, value)| hash.insert(field.clone(), value.clone()).is_none())
+                .count();
+            context.signal_modified(&self.key);
+            Ok(if self.ok {
+                RespDataType::simple_strings("OK")
+            } else {
+                RespDataType::integers(added as i64)
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HSetNx {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if get_hash(context, &self.key)?.is_some_and(|x| x.contains_key(&self.field)) {
+                return Ok(RespDataType::integers(0));
+            }
+            let hash = get_or_create_hash(context, &self.key)?;
+            hash.insert(self.field.clone(), self.value.clone());
+            context.signal_modified(&self.key);
+            Ok(RespDataType::integers(1))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HGet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(get_hash(context, &self.key)?
+                .and_then(|x| x.get(&self.field))
+                .cloned()
+                .map(RespDataType::bulk_strings)
+                .unwrap_or_else(RespDataType::empty_bulk_strings))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HMGet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = get_hash(context, &self.key)?;
+            Ok(RespDataType::arrays(
+                self.fields
+                    .iter()
+                    .map(|field| {
+                        hash.and_then(|x| x.get(field))
+                            .cloned()
+                            .map(RespDataType::bulk_strings)
+                            .unwrap_or_else(RespDataType::empty_bulk_strings)
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HDel {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = match get_hash_mut(context, &self.key)? {
+                Some(hash) => hash,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            let removed = self
+                .fields
+                .iter()
+                .filter(|field| hash.remove(field).is_some())
+                .count();
+            if hash.is_empty() {
+                context.remove(&self.key);
+            } else if removed > 0 {
+                context.signal_modified(&self.key);
+            }
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HGetAll {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = get_hash(context, &self.key)?;
+            let entries = hash.into_iter().flat_map(|x| x.iter());
+            Ok(match (self.fields, self.values) {
+                (true, true) => RespDataType::maps(
+                    entries
+                        .map(|(field, value)| {
+                            (
+                                RespDataType::bulk_strings(field.clone()),
+                                RespDataType::bulk_strings(value.clone()),
+                            )
+                        })
+                        .collect(),
+                ),
+                (true, false) => RespDataType::arrays(
+                    entries
+                        .map(|(field, _)| RespDataType::bulk_strings(field.clone()))
+                        .collect(),
+                ),
+                _ => RespDataType::arrays(
+                    entries
+                        .map(|(_, value)| RespDataType::bulk_strings(value.clone()))
+                        .collect(),
+                ),
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HLen {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_hash(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HExists {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let exists = get_hash(context, &self.key)?.is_some_and(|x| x.contains_key(&self.field));
+            Ok(RespDataType::integers(exists as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HStrlen {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_hash(context, &self.key)?
+                .and_then(|x| x.get(&self.field))
+                .map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HIncrBy {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = match get_hash(context, &self.key)?.and_then(|x| x.get(&self.field)) {
+                Some(value) => {
+                    util::parse_integer(value).ok_or("ERR hash value is not an integer")?
+                }
+                None => 0,
+            };
+            let value = current
+                .checked_add(self.delta)
+                .ok_or("ERR increment or decrement would overflow")?;
+            let hash = get_or_create_hash(context, &self.key)?;
+            update(hash, &self.field, value.to_string().into());
+            context.signal_modified(&self.key);
+            Ok(RespDataType::integers(value))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HIncrByFloat {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = match get_hash(context, &self.key)?.and_then(|x| x.get(&self.field)) {
+                Some(value) => util::parse_float(value).ok_or("ERR hash value is not a float")?,
+                None => 0.0,
+            };
+            let value = current + self.delta;
+            if !value.is_finite() {
+                return Err("ERR increment would produce NaN or Infinity".into());
+            }
+            let value = Bytes::from(util::format_double(value));
+            let hash = get_or_create_hash(context, &self.key)?;
+            update(hash, &self.field, value.clone());
+            context.signal_modified(&self.key);
+            Ok(RespDataType::bulk_strings(value))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HRandField {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = get_hash(context, &self.key)?;
+            let count = match self.count {
+                None => {
+                    return Ok(hash
+                        .and_then(|x| x.random())
+                        .map(|(field, _)| RespDataType::bulk_strings(field.clone()))
+                        .unwrap_or_else(RespDataType::empty_bulk_strings));
+                }
+                Some(count) => count,
+            };
+            let hash = match hash {
+                Some(hash) => hash,
+                None => return Ok(RespDataType::arrays(vec![])),
+            };
+
+            let picked = if count < 0 {
+                // the same field may come up more than once
+                let mut picked = vec![];
+                for _ in 0..count.unsigned_abs() {
+                    picked.extend(hash.random());
+                }
+                picked
+            } else {
+                // a partial shuffle, for distinct fields
+                let mut entries = hash.iter().collect::<Vec<_>>();
+                let count = (count as usize).min(entries.len());
+                for i in 0..count {
+                    let j = i + util::random() as usize % (entries.len() - i);
+                    entries.swap(i, j);
+                }
+                entries.truncate(count);
+                entries
+            };
+            Ok(RespDataType::arrays(
+                picked
+                    .into_iter()
+                    .flat_map(|(field, value)| {
+                        let value = if self.with_values {
+                            Some(RespDataType::bulk_strings(value.clone()))
+                        } else {
+                            None
+                        };
+                        std::iter::once(RespDataType::bulk_strings(field.clone())).chain(value)
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HScan {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = match get_hash(context, &self.key)? {
+                Some(hash) => hash,
+                None => return Ok(Scan::reply(0, vec![])),
+            };
+            let (cursor, page) = hash.scan(self.scan.cursor, self.scan.count);
+            let mut elements = vec![];
+            for (field, value) in page {
+                if !self.scan.matches(field) {
+                    continue;
+                }
+                elements.push(RespDataType::bulk_strings(field.clone()));
+                if !self.no_values {
+                    elements.push(RespDataType::bulk_strings(value.clone()));
+                }
+            }
+            Ok(Scan::reply(cursor, elements))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HExpire {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if get_hash(context, &self.key)?.is_none() {
+                return Ok(RespDataType::arrays(
+                    self.fields
+                        .iter()
+                        .map(|_| RespDataType::integers(-2))
+                        .collect(),
+                ));
+            }
+
+            // relative to when it runs, which is later than it was parsed inside MULTI
+            let now = util::unix_millis();
+            let unix_millis = match self.absolute {
+                true => self.millis,
+                false => self.millis.saturating_add(now),
+            };
+            let past = unix_millis <= now;
+            let deadline = util::instant_from_unix_millis(unix_millis.max(0) as u64);
+            let mut replies = vec![];
+            for field in &self.fields {
+                let hash = get_hash_mut(context, &self.key)?.expect("the hash exists");
+                if !hash.contains_key(field) {
+                    replies.push(-2);
+                    continue;
+                }
+                let current = hash.deadline(field).map(util::unix_millis_from_instant);
+                if !ExpireCondition::allows(self.condition, current, unix_millis) {
+                    replies.push(0);
+                } else if past {
+                    // a deadline in the past deletes the field right away
+                    hash.remove(field);
+                    context.signal_modified(&self.key);
+                    replies.push(2);
+                } else {
+                    context.set_field_deadline(&self.key, field, Some(deadline));
+                    replies.push(1);
+                }
+            }
+            if get_hash(context, &self.key)?.is_some_and(|x| x.is_empty()) {
+                context.remove(&self.key);
+            }
+            Ok(RespDataType::arrays(
+                replies.into_iter().map(RespDataType::integers).collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HTtl {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let hash = get_hash(context, &self.key)?;
+            Ok(RespDataType::arrays(
+                self.fields
+                    .iter()
+                    .map(|field| {
+                        RespDataType::integers(match hash {
+                            Some(hash) if hash.contains_key(field) => match hash.deadline(field) {
+                                Some(deadline) => {
+                                    expire::ttl_reply(deadline, self.millis, self.absolute)
+                                }
+                                None => -1,
+                            },
+                            _ => -2,
+                        })
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for HPersist {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let mut replies = vec![];
+            for field in &self.fields {
+                let reply = match get_hash(context, &self.key)? {
+                    Some(hash) if hash.contains_key(field) => match hash.deadline(field) {
+                        Some(_) => 1,
+                        None => -1,
+                    },
+                    _ => -2,
+                };
+                if reply == 1 {
+                    context.set_field_deadline(&self.key, field, None);
+                }
+                replies.push(RespDataType::integers(reply));
+            }
+            Ok(RespDataType::arrays(replies))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+    use std::{collections::HashSet, time::Duration};
+
+    /// The bulk strings of an array reply, sorted since hashes have no order.
+    fn sorted(reply: RespDataType) -> Vec<Vec<u8>> {
+        let mut values = match reply {
+            RespDataType::Arrays(Some(values)) => values
+                .into_iter()
+                .map(|x| x.into_bulk_strings().unwrap().unwrap().to_vec())
+                .collect::<Vec<_>>(),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        values.sort();
+        values
+    }
+
+    fn strings(values: &[&str]) -> Vec<Vec<u8>> {
+        values.iter().map(|x| x.as_bytes().to_vec()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_set_get() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "HSET user:1 name ann age 30").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HSET user:1 name bob city paris").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HMSET user:1 age 31").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            run_ok(&mut db, "HGET user:1 name").await,
+            RespDataType::bulk_strings("bob")
+        );
+        assert_eq!(
+            run_ok(&mut db, "HGET user:1 missing").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "HMGET user:1 age missing city").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("31"),
+                RespDataType::empty_bulk_strings(),
+                RespDataType::bulk_strings("paris"),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "HSETNX user:1 name carl").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HSETNX user:1 zip 75000").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HLEN user:1").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HEXISTS user:1 zip").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HSTRLEN user:1 city").await,
+            RespDataType::integers(5)
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "HKEYS user:1").await),
+            strings(&["age", "city", "name", "zip"])
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "HVALS user:1").await),
+            strings(&["31", "75000", "bob", "paris"])
+        );
+        match run_ok(&mut db, "HGETALL user:1").await {
+            RespDataType::Maps(pairs) => assert_eq!(pairs.len(), 4),
+            other => panic!("expected a map, got {:?}", other),
+        }
+        assert_eq!(
+            run_ok(&mut db, "TYPE user:1").await,
+            RespDataType::simple_strings("hash")
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "HDEL user:1 age city nope").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HDEL user:1 name zip").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS user:1").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_err(&mut db, "HSET user:1 name").await,
+            "ERR wrong number of arguments for 'hset' command"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_binary_fields() {
+        let mut db = Db::new();
+        run_ok(&mut db, "HSET h \"\\x00\\xff\" \"a b\"").await;
+        assert_eq!(
+            run_ok(&mut db, "HGET h \"\\x00\\xff\"").await,
+            RespDataType::bulk_strings("a b")
+        );
+        assert_eq!(
+            run_ok(&mut db, "HGET h \"\\x00\"").await,
+            RespDataType::empty_bulk_strings()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incr() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "HINCRBY h n 5").await,
+            RespDataType::integers(5)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HINCRBY h n -7").await,
+            RespDataType::integers(-2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "HINCRBYFLOAT h n 0.5").await,
+            RespDataType::bulk_strings("-1.5")
+        );
+        assert_eq!(
+            run_err(&mut db, "HINCRBY h n 1").await,
+            "ERR hash value is not an integer"
+        );
+        run_ok(&mut db, "HSET h s abc big 9223372036854775807").await;
+        assert_eq!(
+            run_err(&mut db, "HINCRBYFLOAT h s 1").await,
+            "ERR hash value is not a float"
+        );
+        assert_eq!(
+            run_err(&mut db, "HINCRBY h big 1").await,
+            "ERR increment or decrement would overflow"
+        );
+        run_ok(&mut db, "SET str v").await;
+        assert_eq!(
+            run_err(&mut db, "HINCRBY str f 1").await,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+        assert_eq!(
+            run_err(&mut db, "HGET str f").await,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_randfield() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "HRANDFIELD h").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "HRANDFIELD h 3").await,
+            RespDataType::arrays(vec![])
+        );
+        run_ok(&mut db, "HSET h a 1 b 2 c 3").await;
+        assert_eq!(
+            sorted(run_ok(&mut db, "HRANDFIELD h 10").await),
+            strings(&["a", "b", "c"])
+        );
+        assert_eq!(sorted(run_ok(&mut db, "HRANDFIELD h 2").await).len(), 2);
+        let repeated = sorted(run_ok(&mut db, "HRANDFIELD h -10").await);
+        assert_eq!(repeated.len(), 10);
+        assert!(repeated
+            .iter()
+            .all(|x| strings(&["a", "b", "c"]).contains(x)));
+        assert_eq!(
+            sorted(run_ok(&mut db, "HRANDFIELD h 1 WITHVALUES").await).len(),
+            2
+        );
+        for count in &["-9223372036854775808", "9223372036854775807"] {
+            assert_eq!(
+                run_err(&mut db, &format!("HRANDFIELD h {} WITHVALUES", count)).await,
+                "ERR value is out of range"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let mut db = Db::new();
+        for i in 0..100 {
+            run_ok(&mut db, &format!("HSET h field:{} {}", i, i)).await;
+        }
+
+        let mut cursor = "0".to_owned();
+        let mut seen = HashSet::new();
+        loop {
+            let reply = run_ok(&mut db, &format!("HSCAN h {} COUNT 7", cursor)).await;
+            let (next, elements) = match reply {
+                RespDataType::Arrays(Some(mut reply)) => {
+                    let elements = sorted(reply.pop().unwrap());
+                    (reply.pop().unwrap(), elements)
+                }
+                other => panic!("expected an array, got {:?}", other),
+            };
+            assert!(elements.len() <= 14);
+            seen.extend(elements);
+            // changes in between calls do not hide the fields that stay
+            run_ok(&mut db, &format!("HSET h extra:{} x", cursor)).await;
+            cursor =
+                String::from_utf8(next.into_bulk_strings().unwrap().unwrap().to_vec()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        for i in 0..100 {
+            assert!(seen.contains(format!("field:{}", i).as_bytes()));
+            assert!(seen.contains(i.to_string().as_bytes()));
+        }
+
+        let reply = run_ok(&mut db, "HSCAN h 0 MATCH field:1? COUNT 1000 NOVALUES").await;
+        let fields = match reply {
+            RespDataType::Arrays(Some(mut reply)) => {
+                let fields = sorted(reply.pop().unwrap());
+                assert_eq!(reply, vec![RespDataType::bulk_strings("0")]);
+                fields
+            }
+            other => panic!("expected an array, got {:?}", other),
+        };
+        assert_eq!(
+            fields,
+            (10..20)
+                .map(|i| format!("field:{}", i).into_bytes())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(run_err(&mut db, "HSCAN h abc").await, "ERR invalid cursor");
+        assert_eq!(
+            run_ok(&mut db, "HSCAN missing 0").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("0"),
+                RespDataType::arrays(vec![])
+            ])
+        );
+    }
+
+    fn integers(reply: RespDataType) -> Vec<i64> {
+        match reply {
+            RespDataType::Arrays(Some(values)) => values
+                .into_iter()
+                .map(|x| x.into_integers().unwrap())
+                .collect(),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_field_expire() {
+        let mut db = Db::new();
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 100 FIELDS 2 a b").await),
+            vec![-2, -2]
+        );
+        run_ok(&mut db, "HSET h a 1 b 2 c 3").await;
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 100 FIELDS 2 a missing").await),
+            vec![1, -2]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HTTL h FIELDS 3 a b missing").await),
+            vec![100, -1, -2]
+        );
+        let pttl = integers(run_ok(&mut db, "HPTTL h FIELDS 1 a").await)[0];
+        assert!(pttl > 99_000 && pttl <= 100_000);
+
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 200 NX FIELDS 2 a b").await),
+            vec![0, 1]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 50 GT FIELDS 2 a c").await),
+            vec![0, 0]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 50 LT FIELDS 2 a c").await),
+            vec![1, 1]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HPEXPIREAT h 4102444800000 XX FIELDS 1 a").await),
+            vec![1]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRETIME h FIELDS 1 a").await),
+            vec![4102444800]
+        );
+
+        // writing a field drops its deadline, incrementing it does not
+        run_ok(&mut db, "HSET h a 10").await;
+        run_ok(&mut db, "HINCRBY h b 10").await;
+        assert_eq!(
+            integers(run_ok(&mut db, "HTTL h FIELDS 2 a b").await),
+            vec![-1, 200]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HPERSIST h FIELDS 3 a b missing").await),
+            vec![-1, 1, -2]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HTTL h FIELDS 1 b").await),
+            vec![-1]
+        );
+        // the key itself has no deadline
+        assert_eq!(run_ok(&mut db, "TTL h").await, RespDataType::integers(-1));
+
+        // a deadline in the past deletes the field, and the hash with its last field
+        assert_eq!(
+            integers(run_ok(&mut db, "HEXPIRE h 0 FIELDS 2 a b").await),
+            vec![2, 2]
+        );
+        assert_eq!(
+            integers(run_ok(&mut db, "HPEXPIREAT h 1 FIELDS 1 c").await),
+            vec![2]
+        );
+        assert_eq!(run_ok(&mut db, "EXISTS h").await, RespDataType::integers(0));
+    }
+
+    #[tokio::test]
+    async fn test_fields_expire_lazily() {
+        let mut db = Db::new();
+        run_ok(&mut db, "HSET flags a 1 b 2").await;
+        run_ok(&mut db, "HPEXPIRE flags 10 FIELDS 1 a").await;
+        assert_eq!(
+            run_ok(&mut db, "HLEN flags").await,
+            RespDataType::integers(2)
+        );
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+        assert_eq!(
+            run_ok(&mut db, "HGET flags a").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "HKEYS flags").await),
+            strings(&["b"])
+        );
+
+        run_ok(&mut db, "HPEXPIRE flags 10 FIELDS 1 b").await;
+        tokio::time::delay_for(Duration::from_millis(20)).await;
+        assert_eq!(
+            run_ok(&mut db, "EXISTS flags").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(db.expiry_stats().expired_subkeys, 2);
+        assert_eq!(db.expiry_stats().expired_keys, 0);
+    }
+
+    #[tokio::test]
+    async fn test_field_expire_errors() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET str v").await;
+        for (line, error) in &[
+            (
+                "HEXPIRE h 10 FIELDS 2 a",
+                "ERR The `numfields` parameter must match the number of arguments",
+            ),
+            (
+                "HEXPIRE h 10 FIELDS 0 a",
+                "ERR Parameter `numFields` should be greater than 0",
+            ),
+            (
+                "HEXPIRE h 10 a b c",
+                "ERR Mandatory argument FIELDS is missing or not at the right position",
+            ),
+            (
+                "HEXPIRE h 10 NX XX FIELDS 1 a",
+                "ERR Mandatory argument FIELDS is missing or not at the right position",
+            ),
+            (
+                "HEXPIRE h -1 FIELDS 1 a",
+                "ERR invalid expire time in 'hexpire' command",
+            ),
+            (
+                "HPEXPIRE h 9223372036854775807 FIELDS 1 a",
+                "ERR invalid expire time in 'hpexpire' command",
+            ),
+            ("HTTL h", "ERR wrong number of arguments for 'httl' command"),
+            (
+                "HPERSIST str FIELDS 1 a",
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use super::{
+    string::{get_string, put_string},
+    Arguments, Command,
+};
+use crate::{
+    data_type::{HyperLogLog, Key, RedisDataType, RespDataType},
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+
+const INVALID_HLL: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+
+#[derive(Debug, Clone)]
+pub struct PfAdd {
+    pub key: Key,
+    pub elements: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PfCount {
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PfMerge {
+    pub destination: Key,
+    pub keys: Vec<Key>,
+}
+
+/// Looks up a HyperLogLog, `None` if the key does not exist.
+fn get_hll(db: &mut Db, key: &Key) -> util::Result<Option<HyperLogLog>> {
+    match get_string(db, key)? {
+        Some(value) => HyperLogLog::from_bytes(&value)
+            .map(Some)
+            .ok_or_else(|| INVALID_HLL.into()),
+        None => Ok(None),
+    }
+}
+
+fn put_hll(db: &mut Db, key: &Key, hll: &HyperLogLog) {
+    put_string(db, key, RedisDataType::Strings(hll.to_bytes().into()));
+}
+
+impl PfAdd {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<PfAdd> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut elements = vec![];
+        while !args.is_empty() {
+            elements.push(args.next_bytes()?);
+        }
+        Ok(PfAdd { key, elements })
+    }
+}
+
+impl PfCount {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<PfCount> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let mut keys = vec![];
+        while !args.is_empty() {
+            keys.push(args.next_key()?);
+        }
+        Ok(PfCount { keys })
+    }
+}
+
+impl PfMerge {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<PfMerge> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let destination = args.next_key()?;
+        let mut keys = vec![];
+        while !args.is_empty() {
+            keys.push(args.next_key()?);
+        }
+        Ok(PfMerge { destination, keys })
+    }
+}
+
+impl<'a> Command<'a, Db> for PfAdd {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let (mut hll, mut changed) = match get_hll(context, &self.key)? {
+                Some(hll) => (hll, false),
+                None => (HyperLogLog::new(), true),
+            };
+            for element in &self.elements {
+                changed |= hll.add(element);
+            }
+            if changed {
+                put_hll(context, &self.key, &hll);
+            }
+            Ok(RespDataType::integers(changed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for PfCount {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if let [key] = &self.keys[..] {
+                let mut hll = match get_hll(context, key)? {
+                    Some(hll) => hll,
+                    None => return Ok(RespDataType::integers(0)),
+                };
+                let cached = hll.is_cached();
+                let count = hll.count();
+                // the count is kept in the header for next time
+                if !cached {
+                    put_hll(context, key, &hll);
+                }
+                return Ok(RespDataType::integers(count as i64));
+            }
+
+            // several keys count their union, leaving every key as it was
+            let mut union = HyperLogLog::new();
+            for key in &self.keys {
+                if let Some(hll) = get_hll(context, key)? {
+                    union.merge(&hll);
+                }
+            }
+            Ok(RespDataType::integers(union.count() as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for PfMerge {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let mut union = get_hll(context, &self.destination)?.unwrap_or_default();
+            for key in &self.keys {
+                if let Some(hll) = get_hll(context, key)? {
+                    union.merge(&hll);
+                }
+            }
+            put_hll(context, &self.destination, &union);
+            Ok(RespDataType::simple_strings("OK"))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    async fn encoding(db: &mut Db, key: &str) -> u8 {
+        match run_ok(db, &format!("GETRANGE {} 4 4", key)).await {
+            RespDataType::BulkStrings(Some(value)) => value[0],
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pfadd_pfcount() {
+        let mut db = Db::new();
+        assert_eq!(run_ok(&mut db, "PFADD h").await, RespDataType::integers(1));
+        assert_eq!(run_ok(&mut db, "PFADD h").await, RespDataType::integers(0));
+        assert_eq!(
+            run_ok(&mut db, "PFADD h a b c d e f g").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFADD h a b").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT h").await,
+            RespDataType::integers(7)
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT missing").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(encoding(&mut db, "h").await, 1);
+
+        // promoted to dense once the sparse encoding grows too big
+        for i in 0..50 {
+            let elements = (0..100)
+                .map(|j| format!("e{}", i * 100 + j))
+                .collect::<Vec<_>>();
+            run_ok(&mut db, &format!("PFADD h {}", elements.join(" "))).await;
+        }
+        assert_eq!(encoding(&mut db, "h").await, 0);
+        match run_ok(&mut db, "PFCOUNT h").await {
+            RespDataType::Integers(count) => assert!((4_900..=5_100).contains(&count)),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+
+        run_ok(&mut db, "SET s foo").await;
+        run_ok(&mut db, "LPUSH l a").await;
+        assert_eq!(
+            run_err(&mut db, "PFADD s a").await,
+            "WRONGTYPE Key is not a valid HyperLogLog string value."
+        );
+        assert_eq!(
+            run_err(&mut db, "PFCOUNT h l").await,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pfmerge() {
+        let mut db = Db::new();
+        run_ok(&mut db, "PFADD a 1 2 3 4").await;
+        run_ok(&mut db, "PFADD b 3 4 5 6").await;
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT a b").await,
+            RespDataType::integers(6)
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT a").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFMERGE c a b missing").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT c").await,
+            RespDataType::integers(6)
+        );
+        run_ok(&mut db, "PFMERGE c").await;
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT c").await,
+            RespDataType::integers(6)
+        );
+        run_ok(&mut db, "PFMERGE empty").await;
+        assert_eq!(
+            run_ok(&mut db, "PFCOUNT empty").await,
+            RespDataType::integers(0)
+        );
+    }
+}
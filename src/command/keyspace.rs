@@ -0,0 +1,505 @@
+use super::{Arguments, Command, SYNTAX_ERROR};
+use crate::{
+    data_type::{Key, RespDataType},
+    db::Db,
+    util::{self, BoxFuture},
+};
+
+/// `DEL` and `UNLINK`; values are freed right away either way.
+#[derive(Debug, Clone)]
+pub struct Del {
+    pub keys: Vec<Key>,
+}
+
+/// `EXISTS` and `TOUCH`, counting the given keys that exist.
+#[derive(Debug, Clone)]
+pub struct Exists {
+    pub keys: Vec<Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Type {
+    pub key: Key,
+}
+
+/// `RENAME` and `RENAMENX`.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub key: Key,
+    pub new_key: Key,
+    /// `RENAMENX`, only rename if the new key does not exist
+    pub not_exists: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Copy {
+    pub source: Key,
+    pub destination: Key,
+    pub replace: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RandomKey;
+
+#[derive(Debug, Clone)]
+pub struct DbSize;
+
+/// `FLUSHDB` and `FLUSHALL`, the same thing with a single database.
+#[derive(Debug, Clone)]
+pub struct Flush;
+
+/// `INFO`, with the sections about the keyspace.
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub section: Option<String>,
+}
+
+/// Parses the one or more keys making up all the arguments of a command.
+pub(super) fn parse_keys(mut args: Arguments) -> util::Result<Vec<Key>> {
+    if args.is_empty() {
+        return Err(args.wrong_arity());
+    }
+    let mut keys = vec![];
+    while !args.is_empty() {
+        keys.push(args.next_key()?);
+    }
+    Ok(keys)
+}
+
+impl Del {
+    pub(super) fn parse(args: Arguments) -> util::Result<Del> {
+        parse_keys(args).map(|keys| Del { keys })
+    }
+}
+
+impl Exists {
+    pub(super) fn parse(args: Arguments) -> util::Result<Exists> {
+        parse_keys(args).map(|keys| Exists { keys })
+    }
+}
+
+impl Type {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Type> {
+        args.expect_len(1)?;
+        Ok(Type {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl Rename {
+    pub(super) fn parse(mut args: Arguments, not_exists: bool) -> util::Result<Rename> {
+        args.expect_len(2)?;
+        Ok(Rename {
+            key: args.next_key()?,
+            new_key: args.next_key()?,
+            not_exists,
+        })
+    }
+}
+
+impl Copy {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Copy> {
+        let source = args.next_key()?;
+        let destination = args.next_key()?;
+        let mut replace = false;
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "replace" => replace = true,
+                "db" if !args.is_empty() => {
+                    // there is only database 0
+                    if args.next_integer()? != 0 {
+                        return Err("ERR DB index is out of range".into());
+                    }
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(Copy {
+            source,
+            destination,
+            replace,
+        })
+    }
+}
+
+impl RandomKey {
+    pub(super) fn parse(args: Arguments) -> util::Result<RandomKey> {
+        args.expect_len(0)?;
+        Ok(RandomKey)
+    }
+}
+
+impl DbSize {
+    pub(super) fn parse(args: Arguments) -> util::Result<DbSize> {
+        args.expect_len(0)?;
+        Ok(DbSize)
+    }
+}
+
+impl Flush {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Flush> {
+        if args.len() > 1 {
+            return Err(SYNTAX_ERROR.into());
+        }
+        if !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "sync" | "async" => {}
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(Flush)
+    }
+}
+
+impl Info {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Info> {
+        let section = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_keyword()?)
+        };
+        if !args.is_empty() {
+            return Err(SYNTAX_ERROR.into());
+        }
+        Ok(Info { section })
+    }
+}
+
+impl<'a> Command<'a, Db> for Del {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let removed = self
+                .keys
+                .iter()
+                .filter(|key| context.remove(key).is_some())
+                .count();
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Exists {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            // a key given twice is counted twice
+            let found = self
+                .keys
+                .iter()
+                .filter(|key| context.contains_key(key))
+                .count();
+            Ok(RespDataType::integers(found as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Type {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let name = context
+                .get(&self.key)
+                .map_or("none", |x| x.value().type_name());
+            Ok(RespDataType::simple_strings(name))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Rename {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if !context.contains_key(&self.key) {
+                return Err("ERR no such key".into());
+            }
+            let reply = |done: bool| {
+                if self.not_exists {
+                    RespDataType::integers(done as i64)
+                } else {
+                    RespDataType::simple_strings("OK")
+                }
+            };
+            if self.not_exists && context.contains_key(&self.new_key) {
+                return Ok(reply(false));
+            }
+            if self.key != self.new_key {
+                // the time to live moves along with the value
+                let entry = context.remove(&self.key).expect("the key exists");
+                context.insert(self.new_key.clone(), entry);
+            }
+            Ok(reply(true))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Copy {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if self.source == self.destination {
+                return Err("ERR source and destination objects are the same".into());
+            }
+            let entry = match context.get(&self.source) {
+                Some(entry) => entry.clone(),
+                None => return Ok(RespDataType::integers(0)),
+            };
+            if !self.replace && context.contains_key(&self.destination) {
+                return Ok(RespDataType::integers(0));
+            }
+            context.insert(self.destination.clone(), entry);
+            Ok(RespDataType::integers(1))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for RandomKey {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(context
+                .random_key()
+                .map(RespDataType::bulk_strings)
+                .unwrap_or_else(RespDataType::empty_bulk_strings))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for DbSize {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move { Ok(RespDataType::integers(context.len() as i64)) })
+    }
+}
+
+impl<'a> Command<'a, Db> for Flush {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            context.clear();
+            Ok(RespDataType::simple_strings("OK"))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Info {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let all = matches!(
+                self.section.as_deref(),
+                None | Some("all") | Some("default") | Some("everything")
+            );
+            let mut info = String::new();
+            if all || self.section.as_deref() == Some("clients") {
+                info.push_str(&format!(
+                    "# Clients\r\nblocked_clients:{}\r\n",
+                    context.blocked_len()
+                ));
+            }
+            if all || self.section.as_deref() == Some("stats") {
+                let stats = context.expiry_stats();
+                info.push_str(&format!(
+                    "# Stats\r\n\
+                     expired_keys:{}\r\n\
+                     expired_subkeys:{}\r\n\
+                     expired_stale_perc:{:.2}\r\n\
+                     expired_time_cap_reached_count:{}\r\n\
+                     expire_cycle_cpu_milliseconds:{}\r\n",
+                    stats.expired_keys,
+                    stats.expired_subkeys,
+                    stats.expired_stale_perc,
+                    stats.expired_time_cap_reached_count,
+                    stats.expire_cycle_time.as_millis(),
+                ));
+            }
+            if all || self.section.as_deref() == Some("keyspace") {
+                info.push_str("# Keyspace\r\n");
+                let keys = context.len();
+                if keys > 0 {
+                    info.push_str(&format!(
+                        "db0:keys={},expires={},avg_ttl=0\r\n",
+                        keys,
+                        context.volatile_len()
+                    ));
+                }
+            }
+            Ok(RespDataType::bulk_strings(info))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run, run_ok},
+        data_type::{RedisDataType, RedisDataTypeWithTTL, RespDataType},
+        db::Db,
+    };
+    use tokio::time::{Duration, Instant};
+
+    /// Inserts a key that expired a moment ago, but is still in the map.
+    fn insert_expired(db: &mut Db, key: &'static str) {
+        let deadline = Instant::now() - Duration::from_millis(1);
+        db.insert(
+            key.into(),
+            RedisDataTypeWithTTL::new(RedisDataType::Strings("gone".into()), Some(deadline)),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_del_exists() {
+        let mut db = Db::new();
+        run_ok(&mut db, "MSET a 1 b 2 c 3").await;
+        insert_expired(&mut db, "d");
+        assert_eq!(
+            run_ok(&mut db, "EXISTS a a b d x").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            run_ok(&mut db, "TOUCH a b d").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "DEL a b d x").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(run_ok(&mut db, "UNLINK c").await, RespDataType::integers(1));
+        assert_eq!(run_ok(&mut db, "DBSIZE").await, RespDataType::integers(0));
+        assert_eq!(
+            run(&mut db, "DEL").await.unwrap_err().to_string(),
+            "ERR wrong number of arguments for 'del' command"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_type() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET s v").await;
+        run_ok(&mut db, "INCR n").await;
+        insert_expired(&mut db, "e");
+        for (key, expected) in &[("s", "string"), ("n", "string"), ("e", "none")] {
+            assert_eq!(
+                run_ok(&mut db, &format!("TYPE {}", key)).await,
+                RespDataType::simple_strings(*expected)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET a 1 EX 100").await;
+        run_ok(&mut db, "SET b 2").await;
+        assert_eq!(
+            run_ok(&mut db, "RENAMENX a b").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "RENAME a b").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET b").await,
+            RespDataType::bulk_strings("1")
+        );
+        assert!(db.get(&"b".into()).and_then(|x| x.deadline()).is_some());
+        assert_eq!(
+            run_ok(&mut db, "RENAMENX b c").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run(&mut db, "RENAME b c").await.unwrap_err().to_string(),
+            "ERR no such key"
+        );
+        insert_expired(&mut db, "e");
+        assert_eq!(
+            run(&mut db, "RENAME e f").await.unwrap_err().to_string(),
+            "ERR no such key"
+        );
+        assert_eq!(
+            run_ok(&mut db, "RENAME c c").await,
+            RespDataType::simple_strings("OK")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET a 1").await;
+        run_ok(&mut db, "SET b 2").await;
+        assert_eq!(run_ok(&mut db, "COPY a b").await, RespDataType::integers(0));
+        assert_eq!(
+            run_ok(&mut db, "COPY a b REPLACE").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET b").await,
+            RespDataType::bulk_strings("1")
+        );
+        assert_eq!(
+            run_ok(&mut db, "COPY x y DB 0").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run(&mut db, "COPY a b DB 1").await.unwrap_err().to_string(),
+            "ERR DB index is out of range"
+        );
+        assert_eq!(
+            run(&mut db, "COPY a a").await.unwrap_err().to_string(),
+            "ERR source and destination objects are the same"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_randomkey_dbsize_flush() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "RANDOMKEY").await,
+            RespDataType::empty_bulk_strings()
+        );
+        // like redis, expired keys count until they are removed, here by RANDOMKEY
+        insert_expired(&mut db, "e");
+        assert_eq!(run_ok(&mut db, "DBSIZE").await, RespDataType::integers(1));
+        assert_eq!(
+            run_ok(&mut db, "RANDOMKEY").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(run_ok(&mut db, "DBSIZE").await, RespDataType::integers(0));
+
+        run_ok(&mut db, "MSET a 1 b 2").await;
+        for _ in 0..10 {
+            let key = run_ok(&mut db, "RANDOMKEY").await;
+            assert!(
+                key == RespDataType::bulk_strings("a") || key == RespDataType::bulk_strings("b")
+            );
+        }
+        assert_eq!(run_ok(&mut db, "DBSIZE").await, RespDataType::integers(2));
+        assert_eq!(
+            run_ok(&mut db, "FLUSHDB ASYNC").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(run_ok(&mut db, "DBSIZE").await, RespDataType::integers(0));
+        run_ok(&mut db, "SET a 1").await;
+        run_ok(&mut db, "FLUSHALL").await;
+        assert_eq!(run_ok(&mut db, "EXISTS a").await, RespDataType::integers(0));
+        assert_eq!(
+            run(&mut db, "FLUSHALL LATER")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "ERR syntax error"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_info() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET a 1 EX 100").await;
+        insert_expired(&mut db, "e");
+        run_ok(&mut db, "GET e").await;
+        let info = match run_ok(&mut db, "INFO").await {
+            RespDataType::BulkStrings(Some(info)) => info,
+            other => panic!("expected a bulk string, got {:?}", other),
+        };
+        let info = String::from_utf8_lossy(&info);
+        assert!(info.contains("expired_keys:1\r\n"));
+        assert!(info.contains("db0:keys=1,expires=1,avg_ttl=0\r\n"));
+        assert_eq!(
+            run_ok(&mut db, "INFO replication").await,
+            RespDataType::bulk_strings("")
+        );
+    }
+}
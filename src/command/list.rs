@@ -0,0 +1,1107 @@
+use super::{Arguments, BlockingCommand, Command, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{Key, RedisDataType, RedisDataTypeWithTTL, RespDataType},
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+use std::{collections::VecDeque, time::Duration};
+
+/// An end of a list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+/// `LPUSH`, `RPUSH`, `LPUSHX` and `RPUSHX`.
+#[derive(Debug, Clone)]
+pub struct Push {
+    pub key: Key,
+    pub values: Vec<Bytes>,
+    pub end: End,
+    /// `LPUSHX`/`RPUSHX`, only push onto an existing list
+    pub exists: bool,
+}
+
+/// `LPOP` and `RPOP`.
+#[derive(Debug, Clone)]
+pub struct Pop {
+    pub key: Key,
+    pub end: End,
+    /// Pop this many elements and reply with an array, rather than a single element
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LRange {
+    pub key: Key,
+    pub start: i64,
+    pub stop: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LIndex {
+    pub key: Key,
+    pub index: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LSet {
+    pub key: Key,
+    pub index: i64,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct LInsert {
+    pub key: Key,
+    /// Insert before the pivot rather than after it
+    pub before: bool,
+    pub pivot: Bytes,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct LRem {
+    pub key: Key,
+    pub count: i64,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct LTrim {
+    pub key: Key,
+    pub start: i64,
+    pub stop: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LLen {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct LPos {
+    pub key: Key,
+    pub value: Bytes,
+    /// Which match to start from, negative to search from the tail
+    pub rank: i64,
+    /// `COUNT`, reply with this many positions (0 for all of them) in an array
+    pub count: Option<usize>,
+    /// Compare at most this many elements, 0 for no limit
+    pub max_len: usize,
+}
+
+/// `LMOVE` and `RPOPLPUSH`.
+#[derive(Debug, Clone)]
+pub struct LMove {
+    pub source: Key,
+    pub destination: Key,
+    pub from: End,
+    pub to: End,
+}
+
+/// `BLPOP` and `BRPOP`.
+#[derive(Debug, Clone)]
+pub struct BPop {
+    pub keys: Vec<Key>,
+    pub end: End,
+    /// `None` to wait for as long as it takes
+    pub timeout: Option<Duration>,
+}
+
+/// `BLMOVE` and `BRPOPLPUSH`.
+#[derive(Debug, Clone)]
+pub struct BLMove {
+    pub lmove: LMove,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LMPop {
+    pub keys: Vec<Key>,
+    pub end: End,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BLMPop {
+    pub lmpop: LMPop,
+    pub timeout: Option<Duration>,
+}
+
+impl End {
+    fn parse(args: &mut Arguments) -> util::Result<End> {
+        match args.next_keyword()?.as_str() {
+            "left" => Ok(End::Left),
+            "right" => Ok(End::Right),
+            _ => Err(SYNTAX_ERROR.into()),
+        }
+    }
+}
+
+/// Looks up a list, `None` if the key does not exist.
+pub(super) fn get_list<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a VecDeque<Bytes>>> {
+    match db.get(key).map(|x| x.value()) {
+        None => Ok(None),
+        Some(RedisDataType::List(list)) => Ok(Some(list)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+pub(super) fn get_list_mut<'a>(
+    db: &'a mut Db,
+    key: &Key,
+) -> util::Result<Option<&'a mut VecDeque<Bytes>>> {
+    match db.get_mut(key).map(|x| x.value_mut()) {
+        None => Ok(None),
+        Some(RedisDataType::List(list)) => Ok(Some(list)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+/// Pushes onto a list, creating it if needed.
+pub(super) fn push(
+    db: &mut Db,
+    key: &Key,
+    end: End,
+    values: impl IntoIterator<Item = Bytes>,
+) -> util::Result<usize> {
+    if get_list(db, key)?.is_none() {
+        db.insert(
+            key.clone(),
+            RedisDataTypeWithTTL::Infinite(RedisDataType::List(VecDeque::new())),
+        );
+    }
+    let list = get_list_mut(db, key)?.expect("the list exists");
+    for value in values {
+        match end {
+            End::Left => list.push_front(value),
+            End::Right => list.push_back(value),
+        }
+    }
+    let len = list.len();
+    db.signal_modified(key);
+    db.signal_ready(key);
+    Ok(len)
+}
+
+/// Pops up to `count` elements off a list, removing the key once the list is empty.
+pub(super) fn pop(
+    db: &mut Db,
+    key: &Key,
+    end: End,
+    count: usize,
+) -> util::Result<Option<Vec<Bytes>>> {
+    let list = match get_list_mut(db, key)? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+    let count = count.min(list.len());
+    let popped = match end {
+        End::Left => list.drain(..count).collect(),
+        End::Right => list.drain(list.len() - count..).rev().collect(),
+    };
+    if list.is_empty() {
+        db.remove(key);
+    } else if count > 0 {
+        db.signal_modified(key);
+    }
+    Ok(Some(popped))
+}
+
+/// Resolves a `start`/`stop` pair of possibly negative inclusive indices into a range
+/// of a list of length `len`, the way `LRANGE`, `LTRIM` and `ZRANGE` do.
+pub(super) fn range(start: i64, stop: i64, len: usize) -> std::ops::Range<usize> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        0..0
+    } else {
+        start as usize..stop as usize + 1
+    }
+}
+
+/// Resolves a possibly negative index into a list of length `len`.
+fn index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    if index >= 0 && index < len as i64 {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+impl Push {
+    pub(super) fn parse(mut args: Arguments, end: End, exists: bool) -> util::Result<Push> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut values = vec![];
+        while !args.is_empty() {
+            values.push(args.next_bytes()?);
+        }
+        Ok(Push {
+            key,
+            values,
+            end,
+            exists,
+        })
+    }
+}
+
+impl Pop {
+    pub(super) fn parse(mut args: Arguments, end: End) -> util::Result<Pop> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let count = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_positive()?)
+        };
+        Ok(Pop { key, end, count })
+    }
+}
+
+impl LRange {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LRange> {
+        args.expect_len(3)?;
+        Ok(LRange {
+            key: args.next_key()?,
+            start: args.next_integer()?,
+            stop: args.next_integer()?,
+        })
+    }
+}
+
+impl LIndex {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LIndex> {
+        args.expect_len(2)?;
+        Ok(LIndex {
+            key: args.next_key()?,
+            index: args.next_integer()?,
+        })
+    }
+}
+
+impl LSet {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LSet> {
+        args.expect_len(3)?;
+        Ok(LSet {
+            key: args.next_key()?,
+            index: args.next_integer()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl LInsert {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LInsert> {
+        args.expect_len(4)?;
+        let key = args.next_key()?;
+        let before = match args.next_keyword()?.as_str() {
+            "before" => true,
+            "after" => false,
+            _ => return Err(SYNTAX_ERROR.into()),
+        };
+        Ok(LInsert {
+            key,
+            before,
+            pivot: args.next_bytes()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl LRem {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LRem> {
+        args.expect_len(3)?;
+        Ok(LRem {
+            key: args.next_key()?,
+            count: args.next_integer()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl LTrim {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LTrim> {
+        args.expect_len(3)?;
+        Ok(LTrim {
+            key: args.next_key()?,
+            start: args.next_integer()?,
+            stop: args.next_integer()?,
+        })
+    }
+}
+
+impl LLen {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LLen> {
+        args.expect_len(1)?;
+        Ok(LLen {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl LPos {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LPos> {
+        let key = args.next_key()?;
+        let value = args.next_bytes()?;
+        let mut lpos = LPos {
+            key,
+            value,
+            rank: 1,
+            count: None,
+            max_len: 0,
+        };
+        while !args.is_empty() {
+            let option = args.next_keyword()?;
+            if args.is_empty() {
+                return Err(SYNTAX_ERROR.into());
+            }
+            match option.as_str() {
+                "rank" => {
+                    lpos.rank = match args.next_integer()? {
+                        0 => return Err("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".into()),
+                        i64::MIN => return Err("ERR value is out of range, value must between -9223372036854775807 and 9223372036854775807".into()),
+                        rank => rank,
+                    }
+                }
+                "count" => {
+                    let count = args.next_integer()?;
+                    if count < 0 {
+                        return Err("ERR COUNT can't be negative".into());
+                    }
+                    lpos.count = Some(count as usize);
+                }
+                "maxlen" => {
+                    let max_len = args.next_integer()?;
+                    if max_len < 0 {
+                        return Err("ERR MAXLEN can't be negative".into());
+                    }
+                    lpos.max_len = max_len as usize;
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(lpos)
+    }
+}
+
+impl LMove {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LMove> {
+        args.expect_len(4)?;
+        Ok(LMove {
+            source: args.next_key()?,
+            destination: args.next_key()?,
+            from: End::parse(&mut args)?,
+            to: End::parse(&mut args)?,
+        })
+    }
+
+    /// `RPOPLPUSH`, which is `LMOVE` from the right to the left.
+    pub(super) fn parse_rpoplpush(mut args: Arguments) -> util::Result<LMove> {
+        args.expect_len(2)?;
+        Ok(LMove {
+            source: args.next_key()?,
+            destination: args.next_key()?,
+            from: End::Right,
+            to: End::Left,
+        })
+    }
+}
+
+impl BPop {
+    pub(super) fn parse(mut args: Arguments, end: End) -> util::Result<BPop> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let mut keys = vec![];
+        while args.len() > 1 {
+            keys.push(args.next_key()?);
+        }
+        let timeout = args.next_timeout()?;
+        Ok(BPop { keys, end, timeout })
+    }
+}
+
+impl BLMove {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<BLMove> {
+        args.expect_len(5)?;
+        Ok(BLMove {
+            lmove: LMove {
+                source: args.next_key()?,
+                destination: args.next_key()?,
+                from: End::parse(&mut args)?,
+                to: End::parse(&mut args)?,
+            },
+            timeout: args.next_timeout()?,
+        })
+    }
+
+    /// `BRPOPLPUSH`, which is `BLMOVE` from the right to the left.
+    pub(super) fn parse_brpoplpush(mut args: Arguments) -> util::Result<BLMove> {
+        args.expect_len(3)?;
+        Ok(BLMove {
+            lmove: LMove {
+                source: args.next_key()?,
+                destination: args.next_key()?,
+                from: End::Right,
+                to: End::Left,
+            },
+            timeout: args.next_timeout()?,
+        })
+    }
+}
+
+impl LMPop {
+    /// Parses `numkeys key [key ...] LEFT|RIGHT [COUNT count]`.
+    pub(super) fn parse(mut args: Arguments) -> util::Result<LMPop> {
+        let num_keys = args.next_integer()?;
+        if num_keys <= 0 {
+            return Err("ERR numkeys should be greater than 0".into());
+        }
+        if num_keys as usize >= args.len() {
+            return Err(SYNTAX_ERROR.into());
+        }
+        let mut keys = vec![];
+        for _ in 0..num_keys {
+            keys.push(args.next_key()?);
+        }
+        let end = End::parse(&mut args)?;
+        let mut count = None;
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "count" if count.is_none() && !args.is_empty() => {
+                    let n = args.next_integer()?;
+                    if n <= 0 {
+                        return Err("ERR count should be greater than 0".into());
+                    }
+                    count = Some(n as usize);
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(LMPop {
+            keys,
+            end,
+            count: count.unwrap_or(1),
+        })
+    }
+
+    /// Pops from the first non-empty list.
+    fn try_pop(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        for key in &self.keys {
+            if let Some(popped) = pop(db, key, self.end, self.count)? {
+                return Ok(Some(RespDataType::arrays(vec![
+                    RespDataType::bulk_strings(key.clone()),
+                    RespDataType::arrays(
+                        popped.into_iter().map(RespDataType::bulk_strings).collect(),
+                    ),
+                ])));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl BLMPop {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<BLMPop> {
+        let timeout = args.next_timeout()?;
+        Ok(BLMPop {
+            lmpop: LMPop::parse(args)?,
+            timeout,
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Push {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if get_list(context, &self.key)?.is_none() && self.exists {
+                return Ok(RespDataType::integers(0));
+            }
+            let len = push(context, &self.key, self.end, self.values.iter().cloned())?;
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Pop {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let popped = pop(context, &self.key, self.end, self.count.unwrap_or(1))?;
+            Ok(match (popped, self.count) {
+                (None, None) => RespDataType::empty_bulk_strings(),
+                (None, Some(_)) => RespDataType::empty_arrays(),
+                (Some(mut popped), None) => RespDataType::bulk_strings(popped.remove(0)),
+                (Some(popped), Some(_)) => RespDataType::arrays(
+                    popped.into_iter().map(RespDataType::bulk_strings).collect(),
+                ),
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LRange {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let list = match get_list(context, &self.key)? {
+                Some(list) => list,
+                None => return Ok(RespDataType::arrays(vec![])),
+            };
+            Ok(RespDataType::arrays(
+                list.range(range(self.start, self.stop, list.len()))
+                    .cloned()
+                    .map(RespDataType::bulk_strings)
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LIndex {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(get_list(context, &self.key)?
+                .and_then(|list| list.get(index(self.index, list.len())?))
+                .cloned()
+                .map(RespDataType::bulk_strings)
+                .unwrap_or_else(RespDataType::empty_bulk_strings))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LSet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let list = get_list_mut(context, &self.key)?.ok_or("ERR no such key")?;
+            let index = index(self.index, list.len()).ok_or("ERR index out of range")?;
+            list[index] = self.value.clone();
+            context.signal_modified(&self.key);
+            Ok(RespDataType::simple_strings("OK"))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LInsert {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let list = match get_list_mut(context, &self.key)? {
+                Some(list) => list,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            let position = match list.iter().position(|x| *x == self.pivot) {
+                Some(position) => position,
+                None => return Ok(RespDataType::integers(-1)),
+            };
+            let position = if self.before { position } else { position + 1 };
+            list.insert(position, self.value.clone());
+            let len = list.len();
+            context.signal_modified(&self.key);
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LRem {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let list = match get_list_mut(context, &self.key)? {
+                Some(list) => list,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            // a negative count removes from the tail, zero removes every match
+            let limit = if self.count == 0 {
+                usize::MAX
+            } else {
+                self.count.unsigned_abs() as usize
+            };
+            let mut positions = list
+                .iter()
+                .enumerate()
+                .filter(|(_, x)| **x == self.value)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if self.count < 0 {
+                positions.reverse();
+            }
+            positions.truncate(limit);
+            positions.sort_unstable();
+            for (removed, position) in positions.iter().enumerate() {
+                list.remove(position - removed);
+            }
+            if list.is_empty() {
+                context.remove(&self.key);
+            } else if !positions.is_empty() {
+                context.signal_modified(&self.key);
+            }
+            Ok(RespDataType::integers(positions.len() as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LTrim {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if let Some(list) = get_list_mut(context, &self.key)? {
+                let range = range(self.start, self.stop, list.len());
+                list.truncate(range.end);
+                list.drain(..range.start.min(list.len()));
+                if list.is_empty() {
+                    context.remove(&self.key);
+                } else {
+                    context.signal_modified(&self.key);
+                }
+            }
+            Ok(RespDataType::simple_strings("OK"))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LLen {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_list(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LPos {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let empty = || match self.count {
+                Some(_) => RespDataType::arrays(vec![]),
+                None => RespDataType::empty_bulk_strings(),
+            };
+            let list = match get_list(context, &self.key)? {
+                Some(list) => list,
+                None => return Ok(empty()),
+            };
+
+            let max_len = if self.max_len == 0 {
+                list.len()
+            } else {
+                self.max_len
+            };
+            let wanted = match self.count {
+                Some(0) => usize::MAX,
+                Some(count) => count,
+                None => 1,
+            };
+            let matches = |(_, x): &(usize, &Bytes)| **x == self.value;
+            let skip = self.rank.unsigned_abs() as usize - 1;
+            let positions: Vec<usize> = if self.rank > 0 {
+                list.iter()
+                    .enumerate()
+                    .take(max_len)
+                    .filter(matches)
+                    .skip(skip)
+                    .take(wanted)
+                    .map(|(i, _)| i)
+                    .collect()
+            } else {
+                list.iter()
+                    .enumerate()
+                    .rev()
+                    .take(max_len)
+                    .filter(matches)
+                    .skip(skip)
+                    .take(wanted)
+                    .map(|(i, _)| i)
+                    .collect()
+            };
+
+            Ok(match self.count {
+                Some(_) => RespDataType::arrays(
+                    positions
+                        .into_iter()
+                        .map(|x| RespDataType::integers(x as i64))
+                        .collect(),
+                ),
+                None => positions
+                    .first()
+                    .map(|x| RespDataType::integers(*x as i64))
+                    .unwrap_or_else(empty),
+            })
+        })
+    }
+}
+
+impl LMove {
+    fn try_move(&self, db: &mut Db) -> util::Result<Option<Bytes>> {
+        // check the destination first, so that nothing is popped when it cannot be
+        // pushed anywhere
+        get_list(db, &self.destination)?;
+        Ok(match pop(db, &self.source, self.from, 1)? {
+            Some(mut popped) => {
+                let value = popped.remove(0);
+                push(db, &self.destination, self.to, Some(value.clone()))?;
+                Some(value)
+            }
+            None => None,
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LMove {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(self
+                .try_move(context)?
+                .map(RespDataType::bulk_strings)
+                .unwrap_or_else(RespDataType::empty_bulk_strings))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for LMPop {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(self
+                .try_pop(context)?
+                .unwrap_or_else(RespDataType::empty_arrays))
+        })
+    }
+}
+
+impl BlockingCommand for BPop {
+    fn keys(&self) -> Vec<Key> {
+        self.keys.clone()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        for key in &self.keys {
+            if let Some(mut popped) = pop(db, key, self.end, 1)? {
+                return Ok(Some(RespDataType::arrays(vec![
+                    RespDataType::bulk_strings(key.clone()),
+                    RespDataType::bulk_strings(popped.remove(0)),
+                ])));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl BlockingCommand for BLMove {
+    fn keys(&self) -> Vec<Key> {
+        vec![self.lmove.source.clone()]
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        Ok(self.lmove.try_move(db)?.map(RespDataType::bulk_strings))
+    }
+
+    fn timeout_reply(&self) -> RespDataType {
+        RespDataType::empty_bulk_strings()
+    }
+}
+
+impl BlockingCommand for BLMPop {
+    fn keys(&self) -> Vec<Key> {
+        self.lmpop.keys.clone()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        self.lmpop.try_pop(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    fn bulks(values: &[&'static str]) -> RespDataType {
+        RespDataType::arrays(
+            values
+                .iter()
+                .map(|x| RespDataType::bulk_strings(*x))
+                .collect(),
+        )
+    }
+
+    fn integers(values: &[i64]) -> RespDataType {
+        RespDataType::arrays(values.iter().map(|x| RespDataType::integers(*x)).collect())
+    }
+
+    #[tokio::test]
+    async fn test_push_pop() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "RPUSH q a b c").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LPUSH q z y").await,
+            RespDataType::integers(5)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE q 0 -1").await,
+            bulks(&["y", "z", "a", "b", "c"])
+        );
+        assert_eq!(
+            run_ok(&mut db, "LPUSHX missing a").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "RPUSHX q d").await,
+            RespDataType::integers(6)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LPOP q").await,
+            RespDataType::bulk_strings("y")
+        );
+        assert_eq!(run_ok(&mut db, "RPOP q 2").await, bulks(&["d", "c"]));
+        assert_eq!(run_ok(&mut db, "LPOP q 0").await, bulks(&[]));
+        assert_eq!(run_ok(&mut db, "LPOP q 10").await, bulks(&["z", "a", "b"]));
+        assert_eq!(run_ok(&mut db, "EXISTS q").await, RespDataType::integers(0));
+        assert_eq!(
+            run_ok(&mut db, "LPOP q").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "LPOP q 1").await,
+            RespDataType::empty_arrays()
+        );
+        assert_eq!(
+            run(&mut db, "LPOP q -1").await.unwrap_err().to_string(),
+            "ERR value is out of range, must be positive"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_type() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET s v").await;
+        for line in &[
+            "LPUSH s a",
+            "LPOP s",
+            "LRANGE s 0 -1",
+            "LLEN s",
+            "LMOVE s d LEFT LEFT",
+        ] {
+            assert_eq!(
+                run(&mut db, line).await.unwrap_err().to_string(),
+                "WRONGTYPE Operation against a key holding the wrong kind of value"
+            );
+        }
+        run_ok(&mut db, "RPUSH l a").await;
+        assert_eq!(
+            run(&mut db, "GET l").await.unwrap_err().to_string(),
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+        assert_eq!(
+            run(&mut db, "LMOVE l s LEFT LEFT")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+        assert_eq!(run_ok(&mut db, "LLEN l").await, RespDataType::integers(1));
+        assert_eq!(
+            run_ok(&mut db, "TYPE l").await,
+            RespDataType::simple_strings("list")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_indices() {
+        let mut db = Db::new();
+        run_ok(&mut db, "RPUSH l a b c d e").await;
+        for (line, expected) in &[
+            ("LRANGE l 1 2", bulks(&["b", "c"])),
+            ("LRANGE l -2 -1", bulks(&["d", "e"])),
+            ("LRANGE l -100 1", bulks(&["a", "b"])),
+            ("LRANGE l 3 100", bulks(&["d", "e"])),
+            ("LRANGE l 3 1", bulks(&[])),
+            ("LRANGE l 5 10", bulks(&[])),
+            ("LRANGE missing 0 -1", bulks(&[])),
+            ("LINDEX l 0", RespDataType::bulk_strings("a")),
+            ("LINDEX l -1", RespDataType::bulk_strings("e")),
+            ("LINDEX l 5", RespDataType::empty_bulk_strings()),
+            ("LINDEX l -6", RespDataType::empty_bulk_strings()),
+        ] {
+            assert_eq!(&run_ok(&mut db, line).await, expected, "{}", line);
+        }
+
+        run_ok(&mut db, "LSET l -2 D").await;
+        assert_eq!(
+            run_ok(&mut db, "LINDEX l 3").await,
+            RespDataType::bulk_strings("D")
+        );
+        assert_eq!(
+            run(&mut db, "LSET l 5 x").await.unwrap_err().to_string(),
+            "ERR index out of range"
+        );
+        assert_eq!(
+            run(&mut db, "LSET missing 0 x")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "ERR no such key"
+        );
+
+        run_ok(&mut db, "LTRIM l 1 -2").await;
+        assert_eq!(
+            run_ok(&mut db, "LRANGE l 0 -1").await,
+            bulks(&["b", "c", "D"])
+        );
+        run_ok(&mut db, "LTRIM l 2 1").await;
+        assert_eq!(run_ok(&mut db, "EXISTS l").await, RespDataType::integers(0));
+    }
+
+    #[tokio::test]
+    async fn test_insert_rem() {
+        let mut db = Db::new();
+        run_ok(&mut db, "RPUSH l a b a c a").await;
+        assert_eq!(
+            run_ok(&mut db, "LINSERT l BEFORE c x").await,
+            RespDataType::integers(6)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LINSERT l after c y").await,
+            RespDataType::integers(7)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LINSERT l AFTER nope y").await,
+            RespDataType::integers(-1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LINSERT missing AFTER a y").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE l 0 -1").await,
+            bulks(&["a", "b", "a", "x", "c", "y", "a"])
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "LREM l -1 a").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE l 0 -1").await,
+            bulks(&["a", "b", "a", "x", "c", "y"])
+        );
+        assert_eq!(
+            run_ok(&mut db, "LREM l 1 a").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE l 0 -1").await,
+            bulks(&["b", "a", "x", "c", "y"])
+        );
+        run_ok(&mut db, "RPUSH l a a").await;
+        assert_eq!(
+            run_ok(&mut db, "LREM l 0 a").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE l 0 -1").await,
+            bulks(&["b", "x", "c", "y"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lpos() {
+        let mut db = Db::new();
+        run_ok(&mut db, "RPUSH l a b c 1 2 3 c c").await;
+        for (line, expected) in &[
+            ("LPOS l c", RespDataType::integers(2)),
+            ("LPOS l x", RespDataType::empty_bulk_strings()),
+            ("LPOS l c RANK 2", RespDataType::integers(6)),
+            ("LPOS l c RANK -1", RespDataType::integers(7)),
+            ("LPOS l c COUNT 2", integers(&[2, 6])),
+            ("LPOS l c COUNT 0", integers(&[2, 6, 7])),
+            ("LPOS l c RANK -1 COUNT 2", integers(&[7, 6])),
+            ("LPOS l c COUNT 0 MAXLEN 7", integers(&[2, 6])),
+            ("LPOS l x COUNT 0", integers(&[])),
+            ("LPOS missing c", RespDataType::empty_bulk_strings()),
+        ] {
+            assert_eq!(&run_ok(&mut db, line).await, expected, "{}", line);
+        }
+        for (line, error) in &[
+            ("LPOS l c RANK 0", "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list"),
+            ("LPOS l c COUNT -1", "ERR COUNT can't be negative"),
+            ("LPOS l c MAXLEN -1", "ERR MAXLEN can't be negative"),
+            ("LPOS l c RANK", "ERR syntax error"),
+        ] {
+            assert_eq!(run(&mut db, line).await.unwrap_err().to_string(), *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lmove() {
+        let mut db = Db::new();
+        run_ok(&mut db, "RPUSH src a b c").await;
+        assert_eq!(
+            run_ok(&mut db, "LMOVE src dst RIGHT LEFT").await,
+            RespDataType::bulk_strings("c")
+        );
+        assert_eq!(
+            run_ok(&mut db, "LMOVE src dst LEFT RIGHT").await,
+            RespDataType::bulk_strings("a")
+        );
+        assert_eq!(run_ok(&mut db, "LRANGE dst 0 -1").await, bulks(&["c", "a"]));
+        assert_eq!(
+            run_ok(&mut db, "RPOPLPUSH src dst").await,
+            RespDataType::bulk_strings("b")
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS src").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "LMOVE src dst LEFT LEFT").await,
+            RespDataType::empty_bulk_strings()
+        );
+
+        // rotating a list onto itself
+        assert_eq!(
+            run_ok(&mut db, "LMOVE dst dst LEFT RIGHT").await,
+            RespDataType::bulk_strings("b")
+        );
+        assert_eq!(
+            run_ok(&mut db, "LRANGE dst 0 -1").await,
+            bulks(&["c", "a", "b"])
+        );
+        assert_eq!(
+            run(&mut db, "LMOVE dst src UP DOWN")
+                .await
+                .unwrap_err()
+                .to_string(),
+            "ERR syntax error"
+        );
+    }
+}
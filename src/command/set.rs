@@ -0,0 +1,875 @@
+use super::{keyspace::parse_keys, Arguments, Command, Scan, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{Key, RedisDataType, RedisDataTypeWithTTL, RespDataType, Set},
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+
+#[derive(Debug, Clone)]
+pub struct SAdd {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SRem {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SMembers {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct SIsMember {
+    pub key: Key,
+    pub member: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct SMIsMember {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SCard {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct SPop {
+    pub key: Key,
+    /// Reply with an array of up to this many members
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SRandMember {
+    pub key: Key,
+    /// Reply with an array of this many members, possibly repeated if negative
+    pub count: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SMove {
+    pub source: Key,
+    pub destination: Key,
+    pub member: Bytes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetOperation {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// `SINTER`, `SUNION`, `SDIFF` and their `*STORE` variants.
+#[derive(Debug, Clone)]
+pub struct SetOp {
+    pub operation: SetOperation,
+    pub keys: Vec<Key>,
+    /// Store the result there and reply with its size, rather than with the result
+    pub destination: Option<Key>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SInterCard {
+    pub keys: Vec<Key>,
+    /// Stop counting at this many members, 0 for no limit
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SScan {
+    pub key: Key,
+    pub scan: Scan,
+}
+
+/// Looks up a set, `None` if the key does not exist.
+fn get_set<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a Set>> {
+    match db.get(key).map(|x| x.value()) {
+        None => Ok(None),
+        Some(RedisDataType::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+fn get_set_mut<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a mut Set>> {
+    match db.get_mut(key).map(|x| x.value_mut()) {
+        None => Ok(None),
+        Some(RedisDataType::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+/// Looks up a set to write to, creating it if needed.
+fn get_or_create_set<'a>(db: &'a mut Db, key: &Key) -> util::Result<&'a mut Set> {
+    if get_set(db, key)?.is_none() {
+        db.insert(
+            key.clone(),
+            RedisDataTypeWithTTL::Infinite(RedisDataType::Set(Set::new())),
+        );
+    }
+    Ok(get_set_mut(db, key)?.expect("the set exists"))
+}
+
+/// Removes the set at `key` if it has no members left.
+fn remove_if_empty(db: &mut Db, key: &Key) {
+    if matches!(get_set(db, key), Ok(Some(set)) if set.is_empty()) {
+        db.remove(key);
+    }
+}
+
+/// Computes the intersection, union or difference of the sets at `keys`, missing keys
+/// being empty sets.
+fn combine(db: &mut Db, operation: SetOperation, keys: &[Key]) -> util::Result<Set> {
+    // all the keys have to be sets, even the ones the result does not depend on
+    let mut lens = vec![];
+    for key in keys {
+        lens.push(get_set(db, key)?.map_or(0, |x| x.len()));
+    }
+
+    match operation {
+        SetOperation::Inter => {
+            if lens.contains(&0) {
+                return Ok(Set::new());
+            }
+            // starting from the smallest set keeps the candidates few
+            let mut order = (0..keys.len()).collect::<Vec<_>>();
+            order.sort_by_key(|i| lens[*i]);
+            let mut members = get_set(db, &keys[order[0]])?
+                .map(|x| x.iter().collect::<Vec<_>>())
+                .unwrap_or_default();
+            for i in &order[1..] {
+                let set = get_set(db, &keys[*i])?.expect("the set exists");
+                members.retain(|member| set.contains(member));
+            }
+            Ok(members.into_iter().collect())
+        }
+        SetOperation::Union => {
+            let mut result = Set::new();
+            for key in keys {
+                for member in get_set(db, key)?.iter().flat_map(|x| x.iter()) {
+                    result.insert(member);
+                }
+            }
+            Ok(result)
+        }
+        SetOperation::Diff => {
+            let mut result = get_set(db, &keys[0])?.cloned().unwrap_or_default();
+            for key in &keys[1..] {
+                if result.is_empty() {
+                    break;
+                }
+                for member in get_set(db, key)?.iter().flat_map(|x| x.iter()) {
+                    result.remove(&member);
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+fn parse_members(args: &mut Arguments) -> util::Result<Vec<Bytes>> {
+    if args.is_empty() {
+        return Err(args.wrong_arity());
+    }
+    let mut members = vec![];
+    while !args.is_empty() {
+        members.push(args.next_bytes()?);
+    }
+    Ok(members)
+}
+
+impl SAdd {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SAdd> {
+        let key = args.next_key()?;
+        Ok(SAdd {
+            key,
+            members: parse_members(&mut args)?,
+        })
+    }
+}
+
+impl SRem {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SRem> {
+        let key = args.next_key()?;
+        Ok(SRem {
+            key,
+            members: parse_members(&mut args)?,
+        })
+    }
+}
+
+impl SMembers {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SMembers> {
+        args.expect_len(1)?;
+        Ok(SMembers {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl SIsMember {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SIsMember> {
+        args.expect_len(2)?;
+        Ok(SIsMember {
+            key: args.next_key()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl SMIsMember {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SMIsMember> {
+        let key = args.next_key()?;
+        Ok(SMIsMember {
+            key,
+            members: parse_members(&mut args)?,
+        })
+    }
+}
+
+impl SCard {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SCard> {
+        args.expect_len(1)?;
+        Ok(SCard {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl SPop {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SPop> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let count = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_positive()?)
+        };
+        Ok(SPop { key, count })
+    }
+}
+
+impl SRandMember {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SRandMember> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let count = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_random_count()?)
+        };
+        Ok(SRandMember { key, count })
+    }
+}
+
+impl SMove {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SMove> {
+        args.expect_len(3)?;
+        Ok(SMove {
+            source: args.next_key()?,
+            destination: args.next_key()?,
+            member: args.next_bytes()?,
+        })
+    }
+}
+
+impl SetOp {
+    pub(super) fn parse(
+        mut args: Arguments,
+        operation: SetOperation,
+        store: bool,
+    ) -> util::Result<SetOp> {
+        let destination = if store { Some(args.next_key()?) } else { None };
+        Ok(SetOp {
+            operation,
+            keys: parse_keys(args)?,
+            destination,
+        })
+    }
+}
+
+impl SInterCard {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SInterCard> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let count = args.next_integer()?;
+        if count <= 0 {
+            return Err("ERR numkeys should be greater than 0".into());
+        }
+        if count as usize > args.len() {
+            return Err("ERR Number of keys can't be greater than number of args".into());
+        }
+        let mut keys = vec![];
+        for _ in 0..count {
+            keys.push(args.next_key()?);
+        }
+        let mut limit = 0;
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "limit" if !args.is_empty() => {
+                    limit = match args.next_integer()? {
+                        limit if limit < 0 => return Err("ERR LIMIT can't be negative".into()),
+                        limit => limit as usize,
+                    };
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(SInterCard { keys, limit })
+    }
+}
+
+impl SScan {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SScan> {
+        let key = args.next_key()?;
+        let scan = Scan::parse(&mut args, |_| false)?;
+        Ok(SScan { key, scan })
+    }
+}
+
+impl<'a> Command<'a, Db> for SAdd {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let set = get_or_create_set(context, &self.key)?;
+            let added = self
+                .members
+                .iter()
+                .filter(|member| set.insert((*member).clone()))
+                .count();
+            if added > 0 {
+                context.signal_modified(&self.key);
+            }
+            Ok(RespDataType::integers(added as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SRem {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let set = match get_set_mut(context, &self.key)? {
+                Some(set) => set,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            let removed = self
+                .members
+                .iter()
+                .filter(|member| set.remove(member))
+                .count();
+            if removed > 0 {
+                context.signal_modified(&self.key);
+            }
+            remove_if_empty(context, &self.key);
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SMembers {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let members = get_set(context, &self.key)?
+                .iter()
+                .flat_map(|x| x.iter())
+                .map(RespDataType::bulk_strings)
+                .collect();
+            Ok(RespDataType::sets(members))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SIsMember {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let exists = get_set(context, &self.key)?.is_some_and(|x| x.contains(&self.member));
+            Ok(RespDataType::integers(exists as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SMIsMember {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let set = get_set(context, &self.key)?;
+            Ok(RespDataType::arrays(
+                self.members
+                    .iter()
+                    .map(|member| {
+                        RespDataType::integers(set.is_some_and(|x| x.contains(member)) as i64)
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SCard {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_set(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SPop {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let set = match get_set_mut(context, &self.key)? {
+                Some(set) => set,
+                None if self.count.is_some() => return Ok(RespDataType::arrays(vec![])),
+                None => return Ok(RespDataType::empty_bulk_strings()),
+            };
+            let reply = match self.count {
+                None => RespDataType::bulk_strings(set.pop().expect("sets are never empty")),
+                Some(count) if count >= set.len() => {
+                    let members = set.iter().map(RespDataType::bulk_strings).collect();
+                    *set = Set::new();
+                    RespDataType::arrays(members)
+                }
+                Some(count) => RespDataType::arrays(
+                    (0..count)
+                        .filter_map(|_| set.pop())
+                        .map(RespDataType::bulk_strings)
+                        .collect(),
+                ),
+            };
+            if self.count != Some(0) {
+                context.signal_modified(&self.key);
+            }
+            remove_if_empty(context, &self.key);
+            Ok(reply)
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SRandMember {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let set = get_set(context, &self.key)?;
+            let count = match self.count {
+                None => {
+                    return Ok(set
+                        .and_then(|x| x.random())
+                        .map(RespDataType::bulk_strings)
+                        .unwrap_or_else(RespDataType::empty_bulk_strings))
+                }
+                Some(count) => count,
+            };
+            let set = match set {
+                Some(set) => set,
+                None => return Ok(RespDataType::arrays(vec![])),
+            };
+
+            let members = if count < 0 {
+                // the same member may come up more than once
+                let mut members = vec![];
+                for _ in 0..count.unsigned_abs() {
+                    members.extend(set.random());
+                }
+                members
+            } else {
+                // a partial shuffle, for distinct members
+                let mut members = set.iter().collect::<Vec<_>>();
+                let count = (count as usize).min(members.len());
+                for i in 0..count {
+                    let j = i + util::random() as usize % (members.len() - i);
+                    members.swap(i, j);
+                }
+                members.truncate(count);
+                members
+            };
+            Ok(RespDataType::arrays(
+                members
+                    .into_iter()
+                    .map(RespDataType::bulk_strings)
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SMove {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            // both keys have to be sets before anything moves
+            get_set(context, &self.destination)?;
+            let source = match get_set_mut(context, &self.source)? {
+                Some(set) if set.contains(&self.member) => set,
+                _ => return Ok(RespDataType::integers(0)),
+            };
+            if self.source == self.destination {
+                return Ok(RespDataType::integers(1));
+            }
+            source.remove(&self.member);
+            context.signal_modified(&self.source);
+            remove_if_empty(context, &self.source);
+            if get_or_create_set(context, &self.destination)?.insert(self.member.clone()) {
+                context.signal_modified(&self.destination);
+            }
+            Ok(RespDataType::integers(1))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SetOp {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let result = combine(context, self.operation, &self.keys)?;
+            let destination = match &self.destination {
+                Some(destination) => destination,
+                None => {
+                    return Ok(RespDataType::sets(
+                        result.iter().map(RespDataType::bulk_strings).collect(),
+                    ))
+                }
+            };
+            let len = result.len();
+            if result.is_empty() {
+                context.remove(destination);
+            } else {
+                context.insert(
+                    destination.clone(),
+                    RedisDataTypeWithTTL::Infinite(RedisDataType::Set(result)),
+                );
+            }
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SInterCard {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            // all the keys have to be sets, even once one turns out to be empty
+            let mut lens = vec![];
+            for key in &self.keys {
+                lens.push(get_set(context, key)?.map_or(0, |x| x.len()));
+            }
+            if lens.contains(&0) {
+                return Ok(RespDataType::integers(0));
+            }
+
+            // counted without building the intersection, so that LIMIT saves the work
+            let mut sets = vec![];
+            for key in &self.keys {
+                match context.peek(key).map(|x| x.value()) {
+                    Some(RedisDataType::Set(set)) => sets.push(set),
+                    // expired since it was looked up
+                    _ => return Ok(RespDataType::integers(0)),
+                }
+            }
+            sets.sort_by_key(|x| x.len());
+            let limit = match self.limit {
+                0 => usize::MAX,
+                limit => limit,
+            };
+            let len = sets[0]
+                .iter()
+                .filter(|member| sets[1..].iter().all(|set| set.contains(member)))
+                .take(limit)
+                .count();
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SScan {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let (cursor, page) = match get_set(context, &self.key)? {
+                Some(set) => set.scan(self.scan.cursor, self.scan.count),
+                None => return Ok(Scan::reply(0, vec![])),
+            };
+            Ok(Scan::reply(
+                cursor,
+                page.into_iter()
+                    .filter(|x| self.scan.matches(x))
+                    .map(RespDataType::bulk_strings)
+                    .collect(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    /// The members in a set or array reply, sorted since sets have no order.
+    fn sorted(reply: RespDataType) -> Vec<String> {
+        let mut members = match reply {
+            RespDataType::Sets(members) | RespDataType::Arrays(Some(members)) => members
+                .into_iter()
+                .map(|x| {
+                    let x = x.into_bulk_strings().unwrap().unwrap();
+                    String::from_utf8(x.to_vec()).unwrap()
+                })
+                .collect::<Vec<_>>(),
+            other => panic!("expected a set, got {:?}", other),
+        };
+        members.sort();
+        members
+    }
+
+    fn strings(members: &[&str]) -> Vec<String> {
+        members.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_members() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "SADD tags 3 1 2 1").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SADD tags red 2").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SMEMBERS tags").await),
+            strings(&["1", "2", "3", "red"])
+        );
+        assert_eq!(
+            run_ok(&mut db, "SCARD tags").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SISMEMBER tags red").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SMISMEMBER tags 1 blue 3").await,
+            RespDataType::arrays(vec![
+                RespDataType::integers(1),
+                RespDataType::integers(0),
+                RespDataType::integers(1),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "TYPE tags").await,
+            RespDataType::simple_strings("set")
+        );
+        assert_eq!(
+            run_ok(&mut db, "SREM tags 1 2 nope").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SREM tags 3 red").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS tags").await,
+            RespDataType::integers(0)
+        );
+
+        run_ok(&mut db, "SET str v").await;
+        assert_eq!(
+            run_err(&mut db, "SADD str a").await,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+        assert_eq!(
+            run_err(&mut db, "SADD tags").await,
+            "ERR wrong number of arguments for 'sadd' command"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_random() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "SPOP s").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "SRANDMEMBER s 5").await,
+            RespDataType::arrays(vec![])
+        );
+        run_ok(&mut db, "SADD s a b c d e").await;
+        assert_eq!(sorted(run_ok(&mut db, "SRANDMEMBER s 3").await).len(), 3);
+        assert_eq!(
+            sorted(run_ok(&mut db, "SRANDMEMBER s 10").await),
+            strings(&["a", "b", "c", "d", "e"])
+        );
+        assert_eq!(sorted(run_ok(&mut db, "SRANDMEMBER s -10").await).len(), 10);
+        for count in &["-9223372036854775808", "9223372036854775807"] {
+            assert_eq!(
+                run_err(&mut db, &format!("SRANDMEMBER s {}", count)).await,
+                "ERR value is out of range"
+            );
+        }
+        assert_eq!(run_ok(&mut db, "SCARD s").await, RespDataType::integers(5));
+
+        let popped = sorted(run_ok(&mut db, "SPOP s 2").await);
+        assert_eq!(popped.len(), 2);
+        assert_eq!(run_ok(&mut db, "SCARD s").await, RespDataType::integers(3));
+        let rest = sorted(run_ok(&mut db, "SPOP s 10").await);
+        let mut all = [popped, rest].concat();
+        all.sort();
+        assert_eq!(all, strings(&["a", "b", "c", "d", "e"]));
+        assert_eq!(run_ok(&mut db, "EXISTS s").await, RespDataType::integers(0));
+        assert_eq!(
+            run_err(&mut db, "SPOP s -1").await,
+            "ERR value is out of range, must be positive"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_smove() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SADD src a b").await;
+        assert_eq!(
+            run_ok(&mut db, "SMOVE src dst a").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SMOVE src dst a").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SMOVE src src b").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SMOVE src dst b").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS src").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SMEMBERS dst").await),
+            strings(&["a", "b"])
+        );
+        run_ok(&mut db, "SET str v").await;
+        assert_eq!(
+            run_err(&mut db, "SMOVE dst str a").await,
+            "WRONGTYPE Operation against a key holding the wrong kind of value"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_algebra() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SADD a 1 2 3 4 x").await;
+        run_ok(&mut db, "SADD b 2 3 5 x y").await;
+        run_ok(&mut db, "SADD c 3 x z").await;
+
+        assert_eq!(
+            sorted(run_ok(&mut db, "SINTER a b c").await),
+            strings(&["3", "x"])
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SUNION a c missing").await),
+            strings(&["1", "2", "3", "4", "x", "z"])
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SDIFF a b missing").await),
+            strings(&["1", "4"])
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SINTER a missing").await),
+            strings(&[])
+        );
+        assert_eq!(
+            run_ok(&mut db, "SINTERCARD 2 a b").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SINTERCARD 2 a b LIMIT 1").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SINTERCARD 3 a b c LIMIT 5").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SINTERCARD 2 a missing").await,
+            RespDataType::integers(0)
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "SINTERSTORE dst a b").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            sorted(run_ok(&mut db, "SMEMBERS dst").await),
+            strings(&["2", "3", "x"])
+        );
+        // the destination may be one of the sources
+        assert_eq!(
+            run_ok(&mut db, "SUNIONSTORE dst dst c").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SDIFFSTORE dst a a").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS dst").await,
+            RespDataType::integers(0)
+        );
+
+        run_ok(&mut db, "SET str v").await;
+        for (line, error) in &[
+            (
+                "SINTER missing str",
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+            ("SINTERCARD 0 a", "ERR numkeys should be greater than 0"),
+            (
+                "SINTERCARD 3 a b",
+                "ERR Number of keys can't be greater than number of args",
+            ),
+            ("SINTERCARD 1 a LIMIT -1", "ERR LIMIT can't be negative"),
+            ("SINTERCARD 1 a LIMIT", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sscan() {
+        let mut db = Db::new();
+        for i in 0..50 {
+            run_ok(&mut db, &format!("SADD s {} m{}", i, i)).await;
+        }
+        let mut cursor = "0".to_owned();
+        let mut seen = vec![];
+        loop {
+            let mut reply = match run_ok(&mut db, &format!("SSCAN s {} MATCH m*", cursor)).await {
+                RespDataType::Arrays(Some(reply)) => reply,
+                other => panic!("expected an array, got {:?}", other),
+            };
+            seen.extend(sorted(reply.pop().unwrap()));
+            let next = reply.pop().unwrap().into_bulk_strings().unwrap().unwrap();
+            cursor = String::from_utf8(next.to_vec()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        seen.sort();
+        let mut expected = (0..50).map(|i| format!("m{}", i)).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(seen, expected);
+    }
+}
@@ -0,0 +1,2178 @@
+use super::{Arguments, BlockingCommand, Command, RespCommand, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{
+        stream_entry, ConsumerGroup, Fields, Key, RedisDataType, RedisDataTypeWithTTL,
+        RespDataType, Stream, StreamId,
+    },
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+use std::{ops::Bound, time::Duration};
+
+const INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+const EXHAUSTED_ID: &str =
+    "ERR The stream has exhausted the last possible ID, unable to add more items";
+
+/// The ID given to `XADD`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AddId {
+    /// `*`, generated from the clock
+    Auto,
+    /// `ms-*`, with the sequence number generated
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+/// How `XADD` and `XTRIM` trim a stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrimStrategy {
+    /// `MAXLEN`, keeping at most this many entries
+    MaxLen(usize),
+    /// `MINID`, deleting the entries with smaller IDs
+    MinId(StreamId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    /// `LIMIT`, deleting at most this many entries. Only allowed with `~`, as trimming
+    /// is otherwise exact.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XAdd {
+    pub key: Key,
+    pub id: AddId,
+    pub fields: Fields,
+    /// `NOMKSTREAM`, not creating the stream if it is missing
+    pub no_create: bool,
+    pub trim: Option<Trim>,
+}
+
+/// `XRANGE` and `XREVRANGE`.
+#[derive(Debug, Clone)]
+pub struct XRange {
+    pub key: Key,
+    pub start: Bound<StreamId>,
+    pub end: Bound<StreamId>,
+    pub count: Option<usize>,
+    pub rev: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct XLen {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct XTrim {
+    pub key: Key,
+    pub trim: Trim,
+}
+
+#[derive(Debug, Clone)]
+pub struct XDel {
+    pub key: Key,
+    pub ids: Vec<StreamId>,
+}
+
+/// Where `XREAD` and `XREADGROUP` read a stream from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadFrom {
+    /// `$`, only the entries added from now on
+    New,
+    /// `>`, the entries never delivered to the group
+    Undelivered,
+    /// The entries after this ID, or for a consumer the ones pending for it after this ID
+    After(StreamId),
+}
+
+/// The `GROUP` of `XREADGROUP`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadGroup {
+    pub group: Bytes,
+    pub consumer: Bytes,
+    /// `NOACK`, not adding the entries read to the pending entries list
+    pub no_ack: bool,
+}
+
+/// `XREAD` and `XREADGROUP`.
+#[derive(Debug, Clone)]
+pub struct XRead {
+    pub keys: Vec<Key>,
+    pub from: Vec<ReadFrom>,
+    pub count: Option<usize>,
+    /// `BLOCK`, in milliseconds with 0 for waiting forever
+    pub block: Option<u64>,
+    pub group: Option<ReadGroup>,
+}
+
+/// The ID `XGROUP CREATE` and `XGROUP SETID` set the last delivered ID to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupId {
+    /// `$`, the last ID of the stream
+    Last,
+    Id(StreamId),
+}
+
+#[derive(Debug, Clone)]
+pub enum XGroup {
+    Create {
+        key: Key,
+        group: Bytes,
+        id: GroupId,
+        /// `MKSTREAM`, creating an empty stream if it is missing
+        make_stream: bool,
+        entries_read: Option<u64>,
+    },
+    SetId {
+        key: Key,
+        group: Bytes,
+        id: GroupId,
+        entries_read: Option<u64>,
+    },
+    Destroy {
+        key: Key,
+        group: Bytes,
+    },
+    CreateConsumer {
+        key: Key,
+        group: Bytes,
+        consumer: Bytes,
+    },
+    DelConsumer {
+        key: Key,
+        group: Bytes,
+        consumer: Bytes,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct XAck {
+    pub key: Key,
+    pub group: Bytes,
+    pub ids: Vec<StreamId>,
+}
+
+/// The extended form of `XPENDING`, listing the pending entries themselves.
+#[derive(Debug, Clone)]
+pub struct PendingRange {
+    /// `IDLE`, only the entries idle for at least this many milliseconds
+    pub min_idle: Option<i64>,
+    pub start: Bound<StreamId>,
+    pub end: Bound<StreamId>,
+    pub count: usize,
+    pub consumer: Option<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XPending {
+    pub key: Key,
+    pub group: Bytes,
+    /// `None` for the summary form
+    pub range: Option<PendingRange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XClaim {
+    pub key: Key,
+    pub group: Bytes,
+    pub consumer: Bytes,
+    pub min_idle: i64,
+    pub ids: Vec<StreamId>,
+    /// `IDLE`, setting the delivery time this many milliseconds back
+    pub idle: Option<i64>,
+    /// `TIME`, setting the delivery time to this unix timestamp in milliseconds
+    pub time: Option<i64>,
+    /// `RETRYCOUNT`, setting the delivery count
+    pub retry_count: Option<u64>,
+    /// `FORCE`, claiming entries even if they are not pending
+    pub force: bool,
+    /// `JUSTID`, replying with the IDs only and not counting a delivery
+    pub just_id: bool,
+    /// `LASTID`, moving the last delivered ID of the group forward
+    pub last_id: Option<StreamId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct XAutoClaim {
+    pub key: Key,
+    pub group: Bytes,
+    pub consumer: Bytes,
+    pub min_idle: i64,
+    pub start: StreamId,
+    pub count: usize,
+    pub just_id: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum XInfo {
+    Stream {
+        key: Key,
+        /// `FULL [COUNT count]`, with 0 for all the entries
+        full: Option<usize>,
+    },
+    Groups {
+        key: Key,
+    },
+    Consumers {
+        key: Key,
+        group: Bytes,
+    },
+}
+
+/// Looks up a stream, `None` if the key does not exist.
+fn get_stream<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a Stream>> {
+    match db.get(key).map(|x| x.value()) {
+        None => Ok(None),
+        Some(RedisDataType::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+fn get_stream_mut<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a mut Stream>> {
+    match db.get_mut(key).map(|x| x.value_mut()) {
+        None => Ok(None),
+        Some(RedisDataType::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+/// Looks up a stream that has `group`, failing with the `NOGROUP` error when either is
+/// missing.
+fn get_group_stream<'a>(db: &'a mut Db, key: &Key, group: &[u8]) -> util::Result<&'a mut Stream> {
+    match get_stream_mut(db, key)? {
+        Some(stream) if stream.group(group).is_some() => Ok(stream),
+        _ => Err(format!(
+            "NOGROUP No such key '{}' or consumer group '{}'",
+            String::from_utf8_lossy(key.as_bytes()),
+            String::from_utf8_lossy(group)
+        )
+        .into()),
+    }
+}
+
+fn entries_reply<'a>(entries: impl Iterator<Item = (StreamId, &'a Fields)>) -> RespDataType {
+    RespDataType::arrays(
+        entries
+            .map(|(id, fields)| stream_entry(id, fields))
+            .collect(),
+    )
+}
+
+/// Parses an ID where one is needed in full, a bare `ms` meaning `ms-missing_seq`.
+pub(super) fn parse_id(arg: &[u8], missing_seq: u64) -> util::Result<StreamId> {
+    StreamId::parse(arg, missing_seq).ok_or_else(|| INVALID_ID.into())
+}
+
+/// Parses the start or the end of a range: `-`, `+`, an ID, or an ID after `(` to
+/// leave it out.
+pub(super) fn parse_bound(arg: &[u8], start: bool) -> util::Result<Bound<StreamId>> {
+    let missing_seq = if start { 0 } else { u64::MAX };
+    match arg {
+        b"-" => Ok(Bound::Included(StreamId::MIN)),
+        b"+" => Ok(Bound::Included(StreamId::MAX)),
+        _ => match arg.strip_prefix(b"(") {
+            Some(arg) => Ok(Bound::Excluded(parse_id(arg, missing_seq)?)),
+            None => Ok(Bound::Included(parse_id(arg, missing_seq)?)),
+        },
+    }
+}
+
+/// Whether a range has no IDs in it, which `BTreeMap::range` would panic on.
+pub(super) fn is_empty_range(start: Bound<StreamId>, end: Bound<StreamId>) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        (Bound::Excluded(start), Bound::Excluded(end)) => start.next().is_none_or(|x| x >= end),
+        _ => false,
+    }
+}
+
+impl Trim {
+    /// Parses the trimming options following `MAXLEN` or `MINID`.
+    fn parse(args: &mut Arguments, strategy: &str) -> util::Result<Trim> {
+        let mut approximate = false;
+        if let Some(operator) = args.peek_keyword() {
+            if operator == "=" || operator == "~" {
+                approximate = operator == "~";
+                args.next_bytes()?;
+            }
+        }
+        let threshold = args.next_bytes()?;
+        let strategy = match strategy {
+            "maxlen" => match util::parse_integer(&threshold) {
+                Some(n) if n < 0 => return Err("ERR The MAXLEN argument must be >= 0.".into()),
+                Some(n) => TrimStrategy::MaxLen(n as usize),
+                None => return Err("ERR value is not an integer or out of range".into()),
+            },
+            _ => TrimStrategy::MinId(parse_id(&threshold, 0)?),
+        };
+        let limit = match args.peek_keyword().as_deref() {
+            Some("limit") => {
+                args.next_bytes()?;
+                if !approximate {
+                    return Err(
+                        "ERR syntax error, LIMIT cannot be used without the special ~ option"
+                            .into(),
+                    );
+                }
+                Some(args.next_positive()?)
+            }
+            _ => None,
+        };
+        Ok(Trim { strategy, limit })
+    }
+
+    /// Trims `stream`, returning how many entries were deleted. Trimming is always
+    /// exact: an approximate trim only promises to keep at least what was asked for.
+    fn apply(&self, stream: &mut Stream) -> usize {
+        let limit = match self.limit {
+            Some(0) | None => usize::MAX,
+            Some(limit) => limit,
+        };
+        match self.strategy {
+            TrimStrategy::MaxLen(len) => stream.trim(limit, |stream, _| stream.len() > len),
+            TrimStrategy::MinId(min) => stream.trim(limit, |_, id| id < min),
+        }
+    }
+}
+
+impl XAdd {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XAdd> {
+        let key = args.next_key()?;
+        let (mut no_create, mut trim) = (false, None);
+        loop {
+            match args.peek_keyword().as_deref() {
+                Some("nomkstream") => no_create = true,
+                Some(strategy @ "maxlen") | Some(strategy @ "minid") => {
+                    args.next_bytes()?;
+                    trim = Some(Trim::parse(&mut args, strategy)?);
+                    continue;
+                }
+                _ => break,
+            }
+            args.next_bytes()?;
+        }
+
+        let id = args.next_bytes()?;
+        let id = if &id[..] == b"*" {
+            AddId::Auto
+        } else if let Some(ms) = id.strip_suffix(b"-*").filter(|x| !x.contains(&b'-')) {
+            AddId::AutoSeq(parse_id(ms, 0)?.ms)
+        } else {
+            AddId::Explicit(parse_id(&id, 0)?)
+        };
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(args.wrong_arity());
+        }
+        let mut fields = vec![];
+        while !args.is_empty() {
+            fields.push((args.next_bytes()?, args.next_bytes()?));
+        }
+        Ok(XAdd {
+            key,
+            id,
+            fields,
+            no_create,
+            trim,
+        })
+    }
+}
+
+impl XRange {
+    pub(super) fn parse(mut args: Arguments, rev: bool) -> util::Result<XRange> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let (first, second) = (args.next_bytes()?, args.next_bytes()?);
+        let (start, end) = if rev {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let count = match args.len() {
+            0 => None,
+            2 if args.next_keyword()? == "count" => Some(args.next_integer()?.max(0) as usize),
+            _ => return Err(SYNTAX_ERROR.into()),
+        };
+        Ok(XRange {
+            key,
+            start: parse_bound(&start, true)?,
+            end: parse_bound(&end, false)?,
+            count,
+            rev,
+        })
+    }
+}
+
+impl XLen {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XLen> {
+        args.expect_len(1)?;
+        Ok(XLen {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl XTrim {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XTrim> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let trim = match args.next_keyword()?.as_str() {
+            strategy @ ("maxlen" | "minid") => Trim::parse(&mut args, strategy)?,
+            _ => return Err(SYNTAX_ERROR.into()),
+        };
+        if !args.is_empty() {
+            return Err(SYNTAX_ERROR.into());
+        }
+        Ok(XTrim { key, trim })
+    }
+}
+
+impl XDel {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XDel> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let mut ids = vec![];
+        while !args.is_empty() {
+            ids.push(parse_id(&args.next_bytes()?, 0)?);
+        }
+        Ok(XDel { key, ids })
+    }
+}
+
+/// Parses the options of `XGROUP CREATE` and `XGROUP SETID` after the ID: `ENTRIESREAD`,
+/// and `MKSTREAM` when `create`.
+fn parse_group_options(
+    args: &mut Arguments,
+    create: bool,
+) -> util::Result<(GroupId, bool, Option<u64>)> {
+    let id = match &args.next_bytes()?[..] {
+        b"$" => GroupId::Last,
+        id => GroupId::Id(parse_id(id, 0)?),
+    };
+    let (mut make_stream, mut entries_read) = (false, None);
+    while !args.is_empty() {
+        match args.next_keyword()?.as_str() {
+            "mkstream" if create => make_stream = true,
+            "entriesread" if !args.is_empty() => {
+                let n = args.next_integer()?;
+                if n < -1 {
+                    return Err("ERR value for ENTRIESREAD must be positive or -1".into());
+                }
+                // -1 leaves it unknown
+                entries_read = Some(n).filter(|x| *x >= 0).map(|x| x as u64);
+            }
+            _ => return Err(SYNTAX_ERROR.into()),
+        }
+    }
+    Ok((id, make_stream, entries_read))
+}
+
+/// Parses a time in milliseconds for `XCLAIM` and `XAUTOCLAIM`, negative ones meaning 0.
+fn parse_millis(arg: &[u8], error: &str) -> util::Result<i64> {
+    util::parse_integer(arg)
+        .map(|x| x.max(0))
+        .ok_or_else(|| error.into())
+}
+
+impl XRead {
+    pub(super) fn parse(mut args: Arguments, group: bool) -> util::Result<XRead> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let (mut count, mut block, mut read_group, mut no_ack) = (None, None, None, false);
+        loop {
+            match args.next_keyword()?.as_str() {
+                "count" => count = Some(args.next_integer()?.max(0) as usize),
+                "block" => {
+                    let timeout = util::parse_integer(&args.next_bytes()?)
+                        .ok_or("ERR timeout is not an integer or out of range")?;
+                    if timeout < 0 {
+                        return Err("ERR timeout is negative".into());
+                    }
+                    block = Some(timeout as u64);
+                }
+                "group" if group => read_group = Some((args.next_bytes()?, args.next_bytes()?)),
+                "group" => {
+                    return Err("ERR The GROUP option is only supported by XREADGROUP. You called XREAD instead.".into())
+                }
+                "noack" if group => no_ack = true,
+                "streams" => break,
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(format!("ERR Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.", args.command).into());
+        }
+        if group && read_group.is_none() {
+            return Err("ERR Missing GROUP option for XREADGROUP".into());
+        }
+
+        let mut keys = vec![];
+        for _ in 0..args.len() / 2 {
+            keys.push(args.next_key()?);
+        }
+        let mut from = vec![];
+        while !args.is_empty() {
+            let id = args.next_bytes()?;
+            from.push(match &id[..] {
+                b"$" if group => return Err("ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".into()),
+                b"$" => ReadFrom::New,
+                b">" if group => ReadFrom::Undelivered,
+                b">" => return Err("ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.".into()),
+                id => ReadFrom::After(parse_id(id, 0)?),
+            });
+        }
+        Ok(XRead {
+            keys,
+            from,
+            // 0 is no limit
+            count: count.filter(|x| *x > 0),
+            block,
+            group: read_group.map(|(group, consumer)| ReadGroup {
+                group,
+                consumer,
+                no_ack,
+            }),
+        })
+    }
+
+    /// Reads for a consumer of a group: new entries for `>`, or the history of the
+    /// consumer for an ID.
+    fn serve_group(&self, db: &mut Db, read: &ReadGroup) -> util::Result<Option<RespDataType>> {
+        for key in &self.keys {
+            match get_stream(db, key)? {
+                Some(stream) if stream.group(&read.group).is_some() => {}
+                _ => {
+                    return Err(format!(
+                        "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                        String::from_utf8_lossy(key.as_bytes()),
+                        String::from_utf8_lossy(&read.group)
+                    )
+                    .into())
+                }
+            }
+        }
+
+        let now = util::unix_millis();
+        let count = self.count.unwrap_or(usize::MAX);
+        let mut replies = vec![];
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            let stream = get_group_stream(db, key, &read.group)?;
+            let entries = match from {
+                ReadFrom::Undelivered => {
+                    let ids = stream.deliver(&read.group, &read.consumer, count, read.no_ack, now);
+                    if ids.is_empty() {
+                        continue;
+                    }
+                    ids.into_iter()
+                        .map(|id| stream_entry(id, stream.get(id).expect("an entry")))
+                        .collect()
+                }
+                ReadFrom::After(after) => {
+                    let group = stream.group_mut(&read.group).expect("the group exists");
+                    let ids = group
+                        .consumer(&read.consumer, now)
+                        .pending
+                        .range((Bound::Excluded(*after), Bound::Unbounded))
+                        .take(count)
+                        .copied()
+                        .collect::<Vec<_>>();
+                    // entries deleted since they were delivered are still pending
+                    ids.into_iter()
+                        .map(|id| match stream.get(id) {
+                            Some(fields) => stream_entry(id, fields),
+                            None => RespDataType::arrays(vec![
+                                RespDataType::bulk_strings(id.to_string()),
+                                RespDataType::empty_arrays(),
+                            ]),
+                        })
+                        .collect()
+                }
+                ReadFrom::New => unreachable!("XREADGROUP does not take $"),
+            };
+            replies.push(RespDataType::arrays(vec![
+                RespDataType::bulk_strings(key.as_bytes().clone()),
+                RespDataType::arrays(entries),
+            ]));
+        }
+        Ok(Some(replies)
+            .filter(|x| !x.is_empty())
+            .map(RespDataType::arrays))
+    }
+}
+
+impl XGroup {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XGroup> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let subcommand = args.next_keyword()?;
+        let arity = match subcommand.as_str() {
+            "create" => 3..=6,
+            "setid" => 3..=5,
+            "destroy" => 2..=2,
+            "createconsumer" | "delconsumer" => 3..=3,
+            _ => {
+                return Err(
+                    format!("ERR unknown subcommand '{}'. Try XGROUP HELP.", subcommand).into(),
+                )
+            }
+        };
+        if !arity.contains(&args.len()) {
+            return Err(format!(
+                "ERR wrong number of arguments for 'xgroup|{}' command",
+                subcommand
+            )
+            .into());
+        }
+        let key = args.next_key()?;
+        let group = args.next_bytes()?;
+        let command = match subcommand.as_str() {
+            "create" => {
+                let (id, make_stream, entries_read) = parse_group_options(&mut args, true)?;
+                XGroup::Create {
+                    key,
+                    group,
+                    id,
+                    make_stream,
+                    entries_read,
+                }
+            }
+            "setid" => {
+                let (id, _, entries_read) = parse_group_options(&mut args, false)?;
+                XGroup::SetId {
+                    key,
+                    group,
+                    id,
+                    entries_read,
+                }
+            }
+            "destroy" => XGroup::Destroy { key, group },
+            "createconsumer" => XGroup::CreateConsumer {
+                key,
+                group,
+                consumer: args.next_bytes()?,
+            },
+            _ => XGroup::DelConsumer {
+                key,
+                group,
+                consumer: args.next_bytes()?,
+            },
+        };
+        Ok(command)
+    }
+}
+
+impl XAck {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XAck> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let group = args.next_bytes()?;
+        let mut ids = vec![];
+        while !args.is_empty() {
+            ids.push(parse_id(&args.next_bytes()?, 0)?);
+        }
+        Ok(XAck { key, group, ids })
+    }
+}
+
+impl XPending {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XPending> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let group = args.next_bytes()?;
+        if args.is_empty() {
+            return Ok(XPending {
+                key,
+                group,
+                range: None,
+            });
+        }
+
+        let mut min_idle = None;
+        if args.peek_keyword().as_deref() == Some("idle") {
+            args.next_bytes()?;
+            min_idle = Some(args.next_integer()?);
+        }
+        if args.len() < 3 || args.len() > 4 {
+            return Err(SYNTAX_ERROR.into());
+        }
+        let start = parse_bound(&args.next_bytes()?, true)?;
+        let end = parse_bound(&args.next_bytes()?, false)?;
+        let count = args.next_integer()?.max(0) as usize;
+        let consumer = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_bytes()?)
+        };
+        Ok(XPending {
+            key,
+            group,
+            range: Some(PendingRange {
+                min_idle,
+                start,
+                end,
+                count,
+                consumer,
+            }),
+        })
+    }
+}
+
+impl XClaim {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XClaim> {
+        if args.len() < 5 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let group = args.next_bytes()?;
+        let consumer = args.next_bytes()?;
+        let min_idle = parse_millis(
+            &args.next_bytes()?,
+            "ERR Invalid min-idle-time argument for XCLAIM",
+        )?;
+        let mut claim = XClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids: vec![],
+            idle: None,
+            time: None,
+            retry_count: None,
+            force: false,
+            just_id: false,
+            last_id: None,
+        };
+        // the IDs go on until the first option
+        while let Some(id) = args
+            .peek_keyword()
+            .and_then(|x| StreamId::parse(x.as_bytes(), 0))
+        {
+            args.next_bytes()?;
+            claim.ids.push(id);
+        }
+        if claim.ids.is_empty() {
+            return Err(INVALID_ID.into());
+        }
+        while !args.is_empty() {
+            let option = args.next_bytes()?;
+            match String::from_utf8_lossy(&option)
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "idle" if !args.is_empty() => {
+                    claim.idle = Some(parse_millis(
+                        &args.next_bytes()?,
+                        "ERR Invalid IDLE option argument for XCLAIM",
+                    )?)
+                }
+                "time" if !args.is_empty() => {
+                    claim.time = Some(parse_millis(
+                        &args.next_bytes()?,
+                        "ERR Invalid TIME option argument for XCLAIM",
+                    )?)
+                }
+                "retrycount" if !args.is_empty() => {
+                    claim.retry_count = Some(args.next_positive()? as u64)
+                }
+                "lastid" if !args.is_empty() => {
+                    claim.last_id = Some(parse_id(&args.next_bytes()?, 0)?)
+                }
+                "force" => claim.force = true,
+                "justid" => claim.just_id = true,
+                _ => {
+                    return Err(format!(
+                        "ERR Unrecognized XCLAIM option '{}'",
+                        String::from_utf8_lossy(&option)
+                    )
+                    .into())
+                }
+            }
+        }
+        Ok(claim)
+    }
+}
+
+impl XAutoClaim {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XAutoClaim> {
+        if args.len() < 5 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let group = args.next_bytes()?;
+        let consumer = args.next_bytes()?;
+        let min_idle = parse_millis(
+            &args.next_bytes()?,
+            "ERR Invalid min-idle-time argument for XAUTOCLAIM",
+        )?;
+        let start = match parse_bound(&args.next_bytes()?, true)? {
+            Bound::Included(id) => id,
+            Bound::Excluded(id) => id.next().ok_or(INVALID_ID)?,
+            Bound::Unbounded => StreamId::MIN,
+        };
+        let (mut count, mut just_id) = (100, false);
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "count" if !args.is_empty() => {
+                    let n = args.next_integer()?;
+                    if n < 1 {
+                        return Err("ERR COUNT must be > 0".into());
+                    }
+                    count = n as usize;
+                }
+                "justid" => just_id = true,
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(XAutoClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            start,
+            count,
+            just_id,
+        })
+    }
+}
+
+impl XInfo {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<XInfo> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let subcommand = args.next_keyword()?;
+        let wrong_arity = || -> util::GenericError {
+            format!(
+                "ERR wrong number of arguments for 'xinfo|{}' command",
+                subcommand
+            )
+            .into()
+        };
+        match subcommand.as_str() {
+            "stream" => {
+                if args.is_empty() {
+                    return Err(wrong_arity());
+                }
+                let key = args.next_key()?;
+                let full = match args.len() {
+                    0 => None,
+                    1 if args.next_keyword()? == "full" => Some(10),
+                    3 if args.next_keyword()? == "full" && args.next_keyword()? == "count" => {
+                        Some(args.next_integer()?.max(0) as usize)
+                    }
+                    _ => return Err(SYNTAX_ERROR.into()),
+                };
+                Ok(XInfo::Stream { key, full })
+            }
+            "groups" if args.len() == 1 => Ok(XInfo::Groups {
+                key: args.next_key()?,
+            }),
+            "consumers" if args.len() == 2 => Ok(XInfo::Consumers {
+                key: args.next_key()?,
+                group: args.next_bytes()?,
+            }),
+            "groups" | "consumers" => Err(wrong_arity()),
+            _ => Err(format!("ERR unknown subcommand '{}'. Try XINFO HELP.", subcommand).into()),
+        }
+    }
+}
+
+impl<'a> Command<'a, Db> for XAdd {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let last_id = match get_stream(context, &self.key)? {
+                Some(stream) => stream.last_id(),
+                None if self.no_create => return Ok(RespDataType::empty_bulk_strings()),
+                None => StreamId::MIN,
+            };
+            let id = match self.id {
+                AddId::Auto => Stream::next_id_after(last_id, util::unix_millis().max(0) as u64)
+                    .ok_or(EXHAUSTED_ID)?,
+                AddId::AutoSeq(ms) if ms == last_id.ms => last_id.next().ok_or(EXHAUSTED_ID)?,
+                AddId::AutoSeq(ms) => StreamId::new(ms, 0),
+                AddId::Explicit(id) => id,
+            };
+            if id == StreamId::MIN {
+                return Err("ERR The ID specified in XADD must be greater than 0-0".into());
+            }
+            // `ms-*` cannot carry over to the next millisecond
+            if id <= last_id || (id.ms > last_id.ms && self.id == AddId::AutoSeq(last_id.ms)) {
+                return Err(
+                    "ERR The ID specified in XADD is equal or smaller than the target stream top item"
+                        .into(),
+                );
+            }
+
+            if get_stream(context, &self.key)?.is_none() {
+                context.insert(
+                    self.key.clone(),
+                    RedisDataTypeWithTTL::Infinite(RedisDataType::Stream(Stream::new())),
+                );
+            }
+            let stream = get_stream_mut(context, &self.key)?.expect("the stream exists");
+            stream.insert(id, self.fields.clone());
+            if let Some(trim) = &self.trim {
+                trim.apply(stream);
+            }
+            context.signal_modified(&self.key);
+            context.signal_ready(&self.key);
+            Ok(RespDataType::bulk_strings(id.to_string()))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XRange {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let stream = match get_stream(context, &self.key)? {
+                Some(stream) if !is_empty_range(self.start, self.end) => stream,
+                _ => return Ok(RespDataType::arrays(vec![])),
+            };
+            let count = self.count.unwrap_or(usize::MAX);
+            let entries = stream.range((self.start, self.end));
+            Ok(if self.rev {
+                entries_reply(entries.rev().take(count))
+            } else {
+                entries_reply(entries.take(count))
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XLen {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_stream(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XTrim {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let removed = get_stream_mut(context, &self.key)?.map_or(0, |x| self.trim.apply(x));
+            if removed > 0 {
+                context.signal_modified(&self.key);
+            }
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XDel {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let removed = match get_stream_mut(context, &self.key)? {
+                Some(stream) => self.ids.iter().filter(|id| stream.remove(**id)).count(),
+                None => 0,
+            };
+            if removed > 0 {
+                context.signal_modified(&self.key);
+            }
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl BlockingCommand for XRead {
+    fn keys(&self) -> Vec<Key> {
+        self.keys.clone()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        match self.block {
+            Some(0) | None => None,
+            Some(millis) => Some(Duration::from_millis(millis)),
+        }
+    }
+
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        if let Some(read) = &self.group {
+            return self.serve_group(db, read);
+        }
+        let mut replies = vec![];
+        for (key, from) in self.keys.iter().zip(&self.from) {
+            let (stream, after) = match (get_stream(db, key)?, from) {
+                (Some(stream), ReadFrom::After(after)) => (stream, *after),
+                _ => continue,
+            };
+            let mut entries = stream
+                .range((Bound::Excluded(after), Bound::Unbounded))
+                .take(self.count.unwrap_or(usize::MAX))
+                .peekable();
+            if entries.peek().is_some() {
+                replies.push(RespDataType::arrays(vec![
+                    RespDataType::bulk_strings(key.as_bytes().clone()),
+                    entries_reply(entries),
+                ]));
+            }
+        }
+        Ok(Some(replies)
+            .filter(|x| !x.is_empty())
+            .map(RespDataType::arrays))
+    }
+
+    /// Each client reads from its own ID, or for its own group.
+    fn in_line(&self) -> bool {
+        false
+    }
+
+    fn resolve(&self, db: &mut Db) -> util::Result<Option<RespCommand>> {
+        if !self.from.contains(&ReadFrom::New) {
+            return Ok(None);
+        }
+        let mut from = vec![];
+        for (key, x) in self.keys.iter().zip(&self.from) {
+            from.push(match x {
+                ReadFrom::New => {
+                    ReadFrom::After(get_stream(db, key)?.map_or(StreamId::MIN, |x| x.last_id()))
+                }
+                after => *after,
+            });
+        }
+        Ok(Some(RespCommand::XRead(XRead {
+            from,
+            ..self.clone()
+        })))
+    }
+}
+
+/// The error for a group missing from a stream that exists.
+fn no_group(key: &Key, group: &[u8]) -> util::GenericError {
+    format!(
+        "NOGROUP No such consumer group '{}' for key name '{}'",
+        String::from_utf8_lossy(group),
+        String::from_utf8_lossy(key.as_bytes())
+    )
+    .into()
+}
+
+/// Gives a pending entry to `consumer` for `XCLAIM` and `XAUTOCLAIM`, counting it as a
+/// delivery unless `retry_count` sets the count or `just_id` leaves it be.
+fn claim(
+    group: &mut ConsumerGroup,
+    id: StreamId,
+    consumer: &Bytes,
+    delivery_time: i64,
+    retry_count: Option<u64>,
+    just_id: bool,
+    now: i64,
+) {
+    group.assign(id, consumer, delivery_time, now);
+    let pending = group.pending.get_mut(&id).expect("the entry is pending");
+    match retry_count {
+        Some(count) => pending.delivery_count = count,
+        None if !just_id => pending.delivery_count += 1,
+        None => {}
+    }
+}
+
+fn id_reply(id: StreamId) -> RespDataType {
+    RespDataType::bulk_strings(id.to_string())
+}
+
+fn map_reply(pairs: Vec<(&str, RespDataType)>) -> RespDataType {
+    RespDataType::maps(
+        pairs
+            .into_iter()
+            .map(|(name, value)| (RespDataType::bulk_strings(name.to_owned()), value))
+            .collect(),
+    )
+}
+
+fn optional_integer(n: Option<u64>) -> RespDataType {
+    n.map_or_else(RespDataType::empty_bulk_strings, |x| {
+        RespDataType::integers(x as i64)
+    })
+}
+
+/// The part of `XINFO GROUPS` and `XINFO STREAM FULL` about where a group is at.
+fn group_progress(stream: &Stream, group: &ConsumerGroup) -> Vec<(&'static str, RespDataType)> {
+    vec![
+        ("last-delivered-id", id_reply(group.last_id)),
+        ("entries-read", optional_integer(group.entries_read)),
+        ("lag", optional_integer(stream.lag(group))),
+    ]
+}
+
+impl<'a> Command<'a, Db> for XGroup {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let no_key = "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.";
+            if let XGroup::Create {
+                key,
+                make_stream: true,
+                ..
+            } = self
+            {
+                if get_stream(context, key)?.is_none() {
+                    context.insert(
+                        key.clone(),
+                        RedisDataTypeWithTTL::Infinite(RedisDataType::Stream(Stream::new())),
+                    );
+                }
+            }
+            let (key, name) = match self {
+                XGroup::Create { key, group, .. }
+                | XGroup::SetId { key, group, .. }
+                | XGroup::Destroy { key, group }
+                | XGroup::CreateConsumer { key, group, .. }
+                | XGroup::DelConsumer { key, group, .. } => (key, group),
+            };
+            let stream = get_stream_mut(context, key)?.ok_or(no_key)?;
+            let now = util::unix_millis();
+            let last_id = |id: &GroupId| match id {
+                GroupId::Last => stream.last_id(),
+                GroupId::Id(id) => *id,
+            };
+
+            match self {
+                XGroup::Create {
+                    id, entries_read, ..
+                } => {
+                    let group = ConsumerGroup::new(last_id(id), *entries_read);
+                    if !stream.create_group(name.clone(), group) {
+                        return Err("BUSYGROUP Consumer Group name already exists".into());
+                    }
+                    Ok(RespDataType::simple_strings("OK"))
+                }
+                XGroup::SetId {
+                    id, entries_read, ..
+                } => {
+                    let id = last_id(id);
+                    let group = stream.group_mut(name).ok_or_else(|| no_group(key, name))?;
+                    group.last_id = id;
+                    group.entries_read = *entries_read;
+                    Ok(RespDataType::simple_strings("OK"))
+                }
+                XGroup::Destroy { .. } => {
+                    Ok(RespDataType::integers(stream.destroy_group(name) as i64))
+                }
+                XGroup::CreateConsumer { consumer, .. } => {
+                    let group = stream.group_mut(name).ok_or_else(|| no_group(key, name))?;
+                    let created = group.create_consumer(consumer, now);
+                    Ok(RespDataType::integers(created as i64))
+                }
+                XGroup::DelConsumer { consumer, .. } => {
+                    let group = stream.group_mut(name).ok_or_else(|| no_group(key, name))?;
+                    let pending = group.delete_consumer(consumer).unwrap_or(0);
+                    Ok(RespDataType::integers(pending as i64))
+                }
+            }
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XAck {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let group = match get_stream_mut(context, &self.key)? {
+                Some(stream) => stream.group_mut(&self.group),
+                None => None,
+            };
+            let acked = match group {
+                Some(group) => self.ids.iter().filter(|id| group.ack(**id)).count(),
+                None => 0,
+            };
+            Ok(RespDataType::integers(acked as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XPending {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let stream = get_group_stream(context, &self.key, &self.group)?;
+            let group = stream.group(&self.group).expect("the group exists");
+            let range = match &self.range {
+                Some(range) => range,
+                None if group.pending.is_empty() => {
+                    return Ok(RespDataType::arrays(vec![
+                        RespDataType::integers(0),
+                        RespDataType::empty_bulk_strings(),
+                        RespDataType::empty_bulk_strings(),
+                        RespDataType::empty_arrays(),
+                    ]))
+                }
+                None => {
+                    let first = group.pending.keys().next().expect("an entry");
+                    let last = group.pending.keys().next_back().expect("an entry");
+                    let consumers = group
+                        .consumers
+                        .iter()
+                        .filter(|(_, consumer)| !consumer.pending.is_empty())
+                        .map(|(name, consumer)| {
+                            RespDataType::arrays(vec![
+                                RespDataType::bulk_strings(name.clone()),
+                                RespDataType::bulk_strings(consumer.pending.len().to_string()),
+                            ])
+                        })
+                        .collect();
+                    return Ok(RespDataType::arrays(vec![
+                        RespDataType::integers(group.pending.len() as i64),
+                        id_reply(*first),
+                        id_reply(*last),
+                        RespDataType::arrays(consumers),
+                    ]));
+                }
+            };
+
+            if is_empty_range(range.start, range.end) {
+                return Ok(RespDataType::arrays(vec![]));
+            }
+            let ids: Box<dyn Iterator<Item = &StreamId>> = match &range.consumer {
+                Some(consumer) => match group.consumers.get(consumer) {
+                    Some(consumer) => Box::new(consumer.pending.range((range.start, range.end))),
+                    None => return Ok(RespDataType::arrays(vec![])),
+                },
+                None => Box::new(group.pending.range((range.start, range.end)).map(|x| x.0)),
+            };
+            let now = util::unix_millis();
+            let entries = ids
+                .map(|id| (id, &group.pending[id]))
+                .filter(|(_, pending)| {
+                    range
+                        .min_idle
+                        .is_none_or(|min| now - pending.delivery_time >= min)
+                })
+                .take(range.count)
+                .map(|(id, pending)| {
+                    RespDataType::arrays(vec![
+                        id_reply(*id),
+                        RespDataType::bulk_strings(pending.consumer.clone()),
+                        RespDataType::integers(now - pending.delivery_time),
+                        RespDataType::integers(pending.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            Ok(RespDataType::arrays(entries))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XClaim {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let stream = get_group_stream(context, &self.key, &self.group)?;
+            let now = util::unix_millis();
+            let delivery_time = match (self.time, self.idle) {
+                (Some(time), _) => time,
+                (None, Some(idle)) => now - idle,
+                (None, None) => now,
+            };
+            let delivery_time = if delivery_time > now {
+                now
+            } else {
+                delivery_time
+            };
+
+            if let Some(last_id) = self.last_id {
+                let group = stream.group_mut(&self.group).expect("the group exists");
+                group.last_id = group.last_id.max(last_id);
+            }
+
+            let mut replies = vec![];
+            for id in &self.ids {
+                let exists = stream.get(*id).is_some();
+                let group = stream.group_mut(&self.group).expect("the group exists");
+                if !exists {
+                    // an entry deleted since it was delivered cannot be claimed anymore
+                    group.ack(*id);
+                    continue;
+                }
+                match group.pending.get(id) {
+                    None if self.force => {
+                        group.assign(*id, &self.consumer, now, now);
+                        group
+                            .pending
+                            .get_mut(id)
+                            .expect("the entry is pending")
+                            .delivery_count = 1;
+                    }
+                    None => continue,
+                    Some(pending) if now - pending.delivery_time < self.min_idle => continue,
+                    Some(_) => {}
+                }
+                claim(
+                    group,
+                    *id,
+                    &self.consumer,
+                    delivery_time,
+                    self.retry_count,
+                    self.just_id,
+                    now,
+                );
+                replies.push(if self.just_id {
+                    id_reply(*id)
+                } else {
+                    stream_entry(*id, stream.get(*id).expect("the entry exists"))
+                });
+            }
+            Ok(RespDataType::arrays(replies))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XAutoClaim {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let stream = get_group_stream(context, &self.key, &self.group)?;
+            let now = util::unix_millis();
+            // like redis, look at no more than 10 pending entries per entry asked for
+            let attempts = self.count.saturating_mul(10);
+            let candidates = stream
+                .group(&self.group)
+                .expect("the group exists")
+                .pending
+                .range(self.start..)
+                .map(|(id, _)| *id)
+                .take(attempts.saturating_add(1))
+                .collect::<Vec<_>>();
+
+            let (mut claimed, mut deleted) = (vec![], vec![]);
+            let mut candidates = candidates.into_iter();
+            for id in candidates.by_ref().take(attempts) {
+                let exists = stream.get(id).is_some();
+                let group = stream.group_mut(&self.group).expect("the group exists");
+                if !exists {
+                    group.ack(id);
+                    deleted.push(id_reply(id));
+                    continue;
+                }
+                if now - group.pending[&id].delivery_time < self.min_idle {
+                    continue;
+                }
+                claim(group, id, &self.consumer, now, None, self.just_id, now);
+                claimed.push(if self.just_id {
+                    id_reply(id)
+                } else {
+                    stream_entry(id, stream.get(id).expect("the entry exists"))
+                });
+                if claimed.len() == self.count {
+                    break;
+                }
+            }
+            let next = candidates.next().unwrap_or(StreamId::MIN);
+            Ok(RespDataType::arrays(vec![
+                id_reply(next),
+                RespDataType::arrays(claimed),
+                RespDataType::arrays(deleted),
+            ]))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for XInfo {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let key = match self {
+                XInfo::Stream { key, .. }
+                | XInfo::Groups { key }
+                | XInfo::Consumers { key, .. } => key,
+            };
+            let stream = get_stream(context, key)?.ok_or("ERR no such key")?;
+            let now = util::unix_millis();
+            match self {
+                XInfo::Stream { full, .. } => {
+                    let entry = |x: Option<(StreamId, &Fields)>| {
+                        x.map_or_else(RespDataType::empty_bulk_strings, |(id, fields)| {
+                            stream_entry(id, fields)
+                        })
+                    };
+                    let first_id = stream.first_entry().map_or(StreamId::MIN, |(id, _)| id);
+                    let mut info = vec![
+                        ("length", RespDataType::integers(stream.len() as i64)),
+                        ("last-generated-id", id_reply(stream.last_id())),
+                        ("max-deleted-entry-id", id_reply(stream.max_deleted_id())),
+                        (
+                            "entries-added",
+                            RespDataType::integers(stream.entries_added() as i64),
+                        ),
+                        ("recorded-first-entry-id", id_reply(first_id)),
+                    ];
+                    let count = match full {
+                        None => {
+                            info.push((
+                                "groups",
+                                RespDataType::integers(stream.groups().count() as i64),
+                            ));
+                            info.push(("first-entry", entry(stream.first_entry())));
+                            info.push(("last-entry", entry(stream.last_entry())));
+                            return Ok(map_reply(info));
+                        }
+                        Some(0) => usize::MAX,
+                        Some(count) => *count,
+                    };
+
+                    info.push(("entries", entries_reply(stream.range(..).take(count))));
+                    let groups = stream
+                        .groups()
+                        .map(|(name, group)| {
+                            let pending = group
+                                .pending
+                                .iter()
+                                .take(count)
+                                .map(|(id, pending)| {
+                                    RespDataType::arrays(vec![
+                                        id_reply(*id),
+                                        RespDataType::bulk_strings(pending.consumer.clone()),
+                                        RespDataType::integers(pending.delivery_time),
+                                        RespDataType::integers(pending.delivery_count as i64),
+                                    ])
+                                })
+                                .collect();
+                            let consumers = group
+                                .consumers
+                                .iter()
+                                .map(|(name, consumer)| {
+                                    let pending = consumer
+                                        .pending
+                                        .iter()
+                                        .take(count)
+                                        .map(|id| {
+                                            let pending = &group.pending[id];
+                                            RespDataType::arrays(vec![
+                                                id_reply(*id),
+                                                RespDataType::integers(pending.delivery_time),
+                                                RespDataType::integers(
+                                                    pending.delivery_count as i64,
+                                                ),
+                                            ])
+                                        })
+                                        .collect();
+                                    map_reply(vec![
+                                        ("name", RespDataType::bulk_strings(name.clone())),
+                                        ("seen-time", RespDataType::integers(consumer.seen_time)),
+                                        (
+                                            "active-time",
+                                            RespDataType::integers(
+                                                consumer.active_time.unwrap_or(-1),
+                                            ),
+                                        ),
+                                        (
+                                            "pel-count",
+                                            RespDataType::integers(consumer.pending.len() as i64),
+                                        ),
+                                        ("pending", RespDataType::arrays(pending)),
+                                    ])
+                                })
+                                .collect();
+                            let mut info = vec![("name", RespDataType::bulk_strings(name.clone()))];
+                            info.extend(group_progress(stream, group));
+                            info.push((
+                                "pel-count",
+                                RespDataType::integers(group.pending.len() as i64),
+                            ));
+                            info.push(("pending", RespDataType::arrays(pending)));
+                            info.push(("consumers", RespDataType::arrays(consumers)));
+                            map_reply(info)
+                        })
+                        .collect();
+                    info.push(("groups", RespDataType::arrays(groups)));
+                    Ok(map_reply(info))
+                }
+                XInfo::Groups { .. } => Ok(RespDataType::arrays(
+                    stream
+                        .groups()
+                        .map(|(name, group)| {
+                            let mut info = vec![
+                                ("name", RespDataType::bulk_strings(name.clone())),
+                                (
+                                    "consumers",
+                                    RespDataType::integers(group.consumers.len() as i64),
+                                ),
+                                (
+                                    "pending",
+                                    RespDataType::integers(group.pending.len() as i64),
+                                ),
+                            ];
+                            info.extend(group_progress(stream, group));
+                            map_reply(info)
+                        })
+                        .collect(),
+                )),
+                XInfo::Consumers { group, .. } => {
+                    let group = stream.group(group).ok_or_else(|| no_group(key, group))?;
+                    Ok(RespDataType::arrays(
+                        group
+                            .consumers
+                            .iter()
+                            .map(|(name, consumer)| {
+                                map_reply(vec![
+                                    ("name", RespDataType::bulk_strings(name.clone())),
+                                    (
+                                        "pending",
+                                        RespDataType::integers(consumer.pending.len() as i64),
+                                    ),
+                                    ("idle", RespDataType::integers(now - consumer.seen_time)),
+                                    (
+                                        "inactive",
+                                        RespDataType::integers(
+                                            consumer.active_time.map_or(-1, |x| now - x),
+                                        ),
+                                    ),
+                                ])
+                            })
+                            .collect(),
+                    ))
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    /// The IDs of the entries in a reply.
+    fn ids(reply: RespDataType) -> Vec<String> {
+        match reply {
+            RespDataType::Arrays(Some(entries)) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RespDataType::Arrays(Some(mut entry)) => match entry.remove(0) {
+                        RespDataType::BulkStrings(Some(id)) => {
+                            String::from_utf8(id.to_vec()).unwrap()
+                        }
+                        other => panic!("expected an ID, got {:?}", other),
+                    },
+                    other => panic!("expected an entry, got {:?}", other),
+                })
+                .collect(),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xadd() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "XADD s 1-1 name ann").await,
+            RespDataType::bulk_strings("1-1")
+        );
+        assert_eq!(
+            run_ok(&mut db, "XADD s 1-* name bob").await,
+            RespDataType::bulk_strings("1-2")
+        );
+        assert_eq!(
+            run_ok(&mut db, "XADD s 5 name cid").await,
+            RespDataType::bulk_strings("5-0")
+        );
+        assert_eq!(
+            run_ok(&mut db, "XADD s 7-* name dan").await,
+            RespDataType::bulk_strings("7-0")
+        );
+        let auto = run_ok(&mut db, "XADD s * name eve").await;
+        let auto = auto.into_bulk_strings().unwrap().unwrap();
+        assert!(std::str::from_utf8(&auto).unwrap().ends_with("-0"));
+        assert_eq!(run_ok(&mut db, "XLEN s").await, RespDataType::integers(5));
+        assert_eq!(
+            run_ok(&mut db, "TYPE s").await,
+            RespDataType::simple_strings("stream")
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "XADD missing NOMKSTREAM * a 1").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS missing").await,
+            RespDataType::integers(0)
+        );
+
+        run_ok(&mut db, "SET str v").await;
+        for (line, error) in &[
+            (
+                "XADD s 1-1 a 1",
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+            ),
+            (
+                "XADD s 2-* a 1",
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+            ),
+            (
+                "XADD other 0-0 a 1",
+                "ERR The ID specified in XADD must be greater than 0-0",
+            ),
+            (
+                "XADD s 1-x a 1",
+                "ERR Invalid stream ID specified as stream command argument",
+            ),
+            (
+                "XADD s * a",
+                "ERR wrong number of arguments for 'xadd' command",
+            ),
+            (
+                "XADD str * a 1",
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+
+        let max = "18446744073709551615";
+        run_ok(&mut db, &format!("XADD last {}-{} a 1", max, max)).await;
+        for id in &["*".to_owned(), format!("{}-*", max)] {
+            assert_eq!(
+                run_err(&mut db, &format!("XADD last {} a 1", id)).await,
+                "ERR The stream has exhausted the last possible ID, unable to add more items"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xrange() {
+        let mut db = Db::new();
+        for id in &["1-0", "1-1", "2-0", "3-5", "4-0"] {
+            run_ok(&mut db, &format!("XADD s {} f v", id)).await;
+        }
+        assert_eq!(
+            run_ok(&mut db, "XRANGE s 1-1 1-1").await,
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("1-1"),
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("f"),
+                    RespDataType::bulk_strings("v")
+                ]),
+            ])])
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s - +").await),
+            vec!["1-0", "1-1", "2-0", "3-5", "4-0"]
+        );
+        // a bare ms covers every sequence number
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s 1 3").await),
+            vec!["1-0", "1-1", "2-0", "3-5"]
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s (1-1 + COUNT 2").await),
+            vec!["2-0", "3-5"]
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XREVRANGE s + - COUNT 2").await),
+            vec!["4-0", "3-5"]
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XREVRANGE s (4-0 (1-1").await),
+            vec!["3-5", "2-0"]
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s 3 2").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s (2-0 (2-1").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE missing - +").await),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "XDEL s 1-1 2-0 9-9").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s - +").await),
+            vec!["1-0", "3-5", "4-0"]
+        );
+        assert_eq!(
+            run_err(&mut db, "XRANGE s - + LIMIT 1").await,
+            "ERR syntax error"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trim() {
+        let mut db = Db::new();
+        for ms in 1..=10 {
+            run_ok(&mut db, &format!("XADD s {} f v", ms)).await;
+        }
+        assert_eq!(
+            run_ok(&mut db, "XTRIM s MAXLEN 8").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "XTRIM s MINID = 5").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "XTRIM s MAXLEN ~ 0 LIMIT 2").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "XADD s MAXLEN 2 11 f v").await,
+            RespDataType::bulk_strings("11-0")
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XRANGE s - +").await),
+            vec!["10-0", "11-0"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "XTRIM s MAXLEN 0").await,
+            RespDataType::integers(2)
+        );
+        // an empty stream is still there, and still remembers its last ID
+        assert_eq!(run_ok(&mut db, "EXISTS s").await, RespDataType::integers(1));
+        assert!(run_err(&mut db, "XADD s 11 f v").await.contains("smaller"));
+
+        for (line, error) in &[
+            (
+                "XTRIM s MAXLEN 1 LIMIT 1",
+                "ERR syntax error, LIMIT cannot be used without the special ~ option",
+            ),
+            ("XTRIM s MAXLEN -1", "ERR The MAXLEN argument must be >= 0."),
+            ("XTRIM s SIZE 1", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xread() {
+        let mut db = Db::new();
+        run_ok(&mut db, "XADD a 1 f v").await;
+        run_ok(&mut db, "XADD a 2 f v").await;
+        run_ok(&mut db, "XADD b 3 f v").await;
+
+        let reply = run_ok(&mut db, "XREAD COUNT 1 STREAMS a b 0 0").await;
+        let streams = match reply {
+            RespDataType::Arrays(Some(streams)) => streams,
+            other => panic!("expected an array, got {:?}", other),
+        };
+        assert_eq!(streams.len(), 2);
+        assert_eq!(
+            streams
+                .into_iter()
+                .map(|x| match x {
+                    RespDataType::Arrays(Some(mut x)) => ids(x.remove(1)),
+                    other => panic!("expected a stream, got {:?}", other),
+                })
+                .collect::<Vec<_>>(),
+            vec![vec!["1-0"], vec!["3-0"]]
+        );
+        assert_eq!(
+            run_ok(&mut db, "XREAD STREAMS a 2").await,
+            RespDataType::empty_arrays()
+        );
+        assert_eq!(
+            run_ok(&mut db, "XREAD BLOCK 10 STREAMS a $").await,
+            RespDataType::empty_arrays()
+        );
+
+        for (line, error) in &[
+            (
+                "XREAD STREAMS a b 0",
+                "ERR Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.",
+            ),
+            ("XREAD BLOCK -1 STREAMS a 0", "ERR timeout is negative"),
+            ("XREAD WAIT 1 STREAMS a 0", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    /// A field of a map reply.
+    fn field(reply: &RespDataType, name: &str) -> RespDataType {
+        match reply {
+            RespDataType::Maps(pairs) => pairs
+                .iter()
+                .find(|(key, _)| *key == RespDataType::bulk_strings(name.to_owned()))
+                .map(|(_, value)| value.clone())
+                .unwrap_or_else(|| panic!("no {} in {:?}", name, reply)),
+            other => panic!("expected a map, got {:?}", other),
+        }
+    }
+
+    /// The entries of the single stream in an `XREADGROUP` reply.
+    fn read_ids(reply: RespDataType) -> Vec<String> {
+        match reply {
+            RespDataType::Arrays(Some(mut streams)) => match streams.remove(0) {
+                RespDataType::Arrays(Some(mut stream)) => ids(stream.remove(1)),
+                other => panic!("expected a stream, got {:?}", other),
+            },
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xgroup() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_err(&mut db, "XGROUP CREATE s g $").await,
+            "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically."
+        );
+        assert_eq!(
+            run_ok(&mut db, "XGROUP CREATE s g $ MKSTREAM").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            run_err(&mut db, "XGROUP CREATE s g 0").await,
+            "BUSYGROUP Consumer Group name already exists"
+        );
+        assert_eq!(
+            run_ok(&mut db, "XGROUP CREATECONSUMER s g alice").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "XGROUP CREATECONSUMER s g alice").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_err(&mut db, "XGROUP CREATECONSUMER s nope alice").await,
+            "NOGROUP No such consumer group 'nope' for key name 's'"
+        );
+
+        for ms in 1..=3 {
+            run_ok(&mut db, &format!("XADD s {} f v", ms)).await;
+        }
+        run_ok(&mut db, "XREADGROUP GROUP g alice STREAMS s >").await;
+        assert_eq!(
+            run_ok(&mut db, "XGROUP DELCONSUMER s g alice").await,
+            RespDataType::integers(3)
+        );
+        let summary = run_ok(&mut db, "XPENDING s g").await;
+        assert_eq!(
+            summary,
+            RespDataType::arrays(vec![
+                RespDataType::integers(0),
+                RespDataType::empty_bulk_strings(),
+                RespDataType::empty_bulk_strings(),
+                RespDataType::empty_arrays(),
+            ])
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "XGROUP SETID s g 1 ENTRIESREAD 1").await,
+            RespDataType::simple_strings("OK")
+        );
+        let groups = run_ok(&mut db, "XINFO GROUPS s").await;
+        let group = match &groups {
+            RespDataType::Arrays(Some(groups)) => groups[0].clone(),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        assert_eq!(
+            field(&group, "last-delivered-id"),
+            RespDataType::bulk_strings("1-0")
+        );
+        assert_eq!(field(&group, "entries-read"), RespDataType::integers(1));
+        assert_eq!(field(&group, "lag"), RespDataType::integers(2));
+        assert_eq!(field(&group, "consumers"), RespDataType::integers(0));
+
+        assert_eq!(
+            run_ok(&mut db, "XGROUP DESTROY s g").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "XGROUP DESTROY s g").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_err(&mut db, "XGROUP CREATE s g 0 MKSTREAM BOGUS").await,
+            "ERR syntax error"
+        );
+        assert_eq!(
+            run_err(&mut db, "XGROUP WHATEVER s g").await,
+            "ERR unknown subcommand 'whatever'. Try XGROUP HELP."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_xreadgroup() {
+        let mut db = Db::new();
+        for ms in 1..=4 {
+            run_ok(&mut db, &format!("XADD s {} n {}", ms, ms)).await;
+        }
+        run_ok(&mut db, "XGROUP CREATE s g 0").await;
+
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g alice COUNT 2 STREAMS s >").await),
+            vec!["1-0", "2-0"]
+        );
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g bob STREAMS s >").await),
+            vec!["3-0", "4-0"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "XREADGROUP GROUP g bob STREAMS s >").await,
+            RespDataType::empty_arrays()
+        );
+        // the history of a consumer is its pending entries
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g alice STREAMS s 0").await),
+            vec!["1-0", "2-0"]
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "XPENDING s g").await,
+            RespDataType::arrays(vec![
+                RespDataType::integers(4),
+                RespDataType::bulk_strings("1-0"),
+                RespDataType::bulk_strings("4-0"),
+                RespDataType::arrays(vec![
+                    RespDataType::arrays(vec![
+                        RespDataType::bulk_strings("alice"),
+                        RespDataType::bulk_strings("2"),
+                    ]),
+                    RespDataType::arrays(vec![
+                        RespDataType::bulk_strings("bob"),
+                        RespDataType::bulk_strings("2"),
+                    ]),
+                ]),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "XACK s g 1-0 3-0 9-0").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g alice STREAMS s 0").await),
+            vec!["2-0"]
+        );
+        let pending = run_ok(&mut db, "XPENDING s g - + 10 bob").await;
+        match pending {
+            RespDataType::Arrays(Some(entries)) => {
+                assert_eq!(entries.len(), 1);
+                match &entries[0] {
+                    RespDataType::Arrays(Some(entry)) => {
+                        assert_eq!(entry[0], RespDataType::bulk_strings("4-0"));
+                        assert_eq!(entry[1], RespDataType::bulk_strings("bob"));
+                        assert_eq!(entry[3], RespDataType::integers(1));
+                    }
+                    other => panic!("expected an entry, got {:?}", other),
+                }
+            }
+            other => panic!("expected an array, got {:?}", other),
+        }
+
+        // a deleted entry stays pending, without its fields
+        run_ok(&mut db, "XDEL s 2").await;
+        assert_eq!(
+            run_ok(&mut db, "XREADGROUP GROUP g alice STREAMS s 0").await,
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("s"),
+                RespDataType::arrays(vec![RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("2-0"),
+                    RespDataType::empty_arrays(),
+                ])]),
+            ])])
+        );
+
+        run_ok(&mut db, "XADD s 5 n 5").await;
+        run_ok(&mut db, "XREADGROUP GROUP g carol NOACK STREAMS s >").await;
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g carol STREAMS s 0").await),
+            Vec::<String>::new()
+        );
+
+        for (line, error) in &[
+            (
+                "XREADGROUP GROUP nope alice STREAMS s >",
+                "NOGROUP No such key 's' or consumer group 'nope' in XREADGROUP with GROUP option",
+            ),
+            ("XREADGROUP STREAMS s >", "ERR Missing GROUP option for XREADGROUP"),
+            (
+                "XREAD GROUP g alice STREAMS s 0",
+                "ERR The GROUP option is only supported by XREADGROUP. You called XREAD instead.",
+            ),
+            (
+                "XREAD STREAMS s >",
+                "ERR The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option.",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim() {
+        let mut db = Db::new();
+        for ms in 1..=4 {
+            run_ok(&mut db, &format!("XADD s {} n {}", ms, ms)).await;
+        }
+        run_ok(&mut db, "XGROUP CREATE s g 0").await;
+        run_ok(&mut db, "XREADGROUP GROUP g alice STREAMS s >").await;
+
+        // entries delivered just now are not idle enough
+        assert_eq!(
+            ids(run_ok(&mut db, "XCLAIM s g bob 60000 1-0").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(run_ok(&mut db, "XCLAIM s g bob 0 1-0 2-0").await),
+            vec!["1-0", "2-0"]
+        );
+        assert_eq!(
+            run_ok(
+                &mut db,
+                "XCLAIM s g bob 0 3-0 JUSTID IDLE 120000 RETRYCOUNT 7"
+            )
+            .await,
+            RespDataType::arrays(vec![RespDataType::bulk_strings("3-0")])
+        );
+        let entries = run_ok(&mut db, "XPENDING s g IDLE 60000 - + 10").await;
+        match entries {
+            RespDataType::Arrays(Some(entries)) => {
+                assert_eq!(entries.len(), 1);
+                match &entries[0] {
+                    RespDataType::Arrays(Some(entry)) => {
+                        assert_eq!(entry[0], RespDataType::bulk_strings("3-0"));
+                        assert_eq!(entry[1], RespDataType::bulk_strings("bob"));
+                        assert_eq!(entry[3], RespDataType::integers(7));
+                    }
+                    other => panic!("expected an entry, got {:?}", other),
+                }
+            }
+            other => panic!("expected an array, got {:?}", other),
+        }
+        assert_eq!(
+            read_ids(run_ok(&mut db, "XREADGROUP GROUP g bob STREAMS s 0").await),
+            vec!["1-0", "2-0", "3-0"]
+        );
+
+        // deleted entries are dropped from the pending entries list instead
+        run_ok(&mut db, "XDEL s 2").await;
+        let reply = run_ok(&mut db, "XAUTOCLAIM s g carol 0 0 COUNT 2").await;
+        match reply {
+            RespDataType::Arrays(Some(reply)) => {
+                assert_eq!(reply[0], RespDataType::bulk_strings("4-0"));
+                assert_eq!(ids(reply[1].clone()), vec!["1-0", "3-0"]);
+                assert_eq!(
+                    reply[2],
+                    RespDataType::arrays(vec![RespDataType::bulk_strings("2-0")])
+                );
+            }
+            other => panic!("expected an array, got {:?}", other),
+        }
+        assert_eq!(
+            run_ok(&mut db, "XAUTOCLAIM s g carol 0 (3-0 JUSTID").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("0-0"),
+                RespDataType::arrays(vec![RespDataType::bulk_strings("4-0")]),
+                RespDataType::arrays(vec![]),
+            ])
+        );
+
+        let consumers = run_ok(&mut db, "XINFO CONSUMERS s g").await;
+        match consumers {
+            RespDataType::Arrays(Some(consumers)) => {
+                let pending = consumers
+                    .iter()
+                    .map(|x| (field(x, "name"), field(x, "pending")))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    pending,
+                    vec![
+                        (
+                            RespDataType::bulk_strings("alice"),
+                            RespDataType::integers(0)
+                        ),
+                        (RespDataType::bulk_strings("bob"), RespDataType::integers(0)),
+                        (
+                            RespDataType::bulk_strings("carol"),
+                            RespDataType::integers(3)
+                        ),
+                    ]
+                );
+            }
+            other => panic!("expected an array, got {:?}", other),
+        }
+
+        // FORCE makes an entry pending that was not
+        run_ok(&mut db, "XACK s g 1").await;
+        assert_eq!(
+            run_ok(&mut db, "XCLAIM s g dave 0 1-0 FORCE JUSTID").await,
+            RespDataType::arrays(vec![RespDataType::bulk_strings("1-0")])
+        );
+        for (line, error) in &[
+            (
+                "XCLAIM s nope bob 0 1-0",
+                "NOGROUP No such key 's' or consumer group 'nope'",
+            ),
+            (
+                "XCLAIM s g bob soon 1-0",
+                "ERR Invalid min-idle-time argument for XCLAIM",
+            ),
+            (
+                "XCLAIM s g bob 0 1-0 NOW",
+                "ERR Unrecognized XCLAIM option 'NOW'",
+            ),
+            ("XAUTOCLAIM s g bob 0 0 COUNT 0", "ERR COUNT must be > 0"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_xinfo_stream() {
+        let mut db = Db::new();
+        for ms in 1..=3 {
+            run_ok(&mut db, &format!("XADD s {} n {}", ms, ms)).await;
+        }
+        run_ok(&mut db, "XGROUP CREATE s g 0").await;
+        run_ok(&mut db, "XREADGROUP GROUP g alice COUNT 1 STREAMS s >").await;
+        run_ok(&mut db, "XDEL s 2").await;
+
+        let info = run_ok(&mut db, "XINFO STREAM s").await;
+        assert_eq!(field(&info, "length"), RespDataType::integers(2));
+        assert_eq!(
+            field(&info, "last-generated-id"),
+            RespDataType::bulk_strings("3-0")
+        );
+        assert_eq!(
+            field(&info, "max-deleted-entry-id"),
+            RespDataType::bulk_strings("2-0")
+        );
+        assert_eq!(field(&info, "entries-added"), RespDataType::integers(3));
+        assert_eq!(field(&info, "groups"), RespDataType::integers(1));
+        assert_eq!(
+            ids(RespDataType::arrays(vec![field(&info, "last-entry")])),
+            vec!["3-0"]
+        );
+
+        let info = run_ok(&mut db, "XINFO STREAM s FULL COUNT 1").await;
+        assert_eq!(ids(field(&info, "entries")), vec!["1-0"]);
+        let group = match field(&info, "groups") {
+            RespDataType::Arrays(Some(mut groups)) => groups.remove(0),
+            other => panic!("expected an array, got {:?}", other),
+        };
+        assert_eq!(field(&group, "pel-count"), RespDataType::integers(1));
+        assert_eq!(field(&group, "entries-read"), RespDataType::integers(1));
+        // with a deleted entry after the last one delivered, the lag is unknown
+        assert_eq!(field(&group, "lag"), RespDataType::empty_bulk_strings());
+
+        assert_eq!(
+            run_err(&mut db, "XINFO STREAM missing").await,
+            "ERR no such key"
+        );
+        assert_eq!(
+            run_err(&mut db, "XINFO CONSUMERS s nope").await,
+            "NOGROUP No such consumer group 'nope' for key name 's'"
+        );
+    }
+}
@@ -0,0 +1,977 @@
+use super::{Arguments, Command, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{Key, RedisDataType, RedisDataTypeWithTTL, RespDataType},
+    db::Db,
+    util::{self, BoxFuture, GenericError},
+};
+use bytes::Bytes;
+use std::{convert::TryInto, time::Duration};
+use tokio::time::Instant;
+
+/// Same as `proto-max-bulk-len`, the size no string may grow past.
+pub(super) const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+const NOT_AN_INTEGER: &str = "ERR value is not an integer or out of range";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    /// `EX`/`PX`, relative to when the command runs
+    In(Duration),
+    /// `EXAT`/`PXAT`, as a unix timestamp in milliseconds
+    At(u64),
+    /// `KEEPTTL`
+    Keep,
+    /// `PERSIST`, only for `GETEX`
+    Persist,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// `NX`
+    NotExists,
+    /// `XX`
+    Exists,
+}
+
+#[derive(Debug, Clone)]
+pub struct Set {
+    pub key: Key,
+    pub value: RespDataType,
+    pub expiry: Option<Expiry>,
+    pub condition: Option<Condition>,
+    /// `GET`, reply with the previous value instead of `OK`
+    pub get: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Get {
+    pub key: Key,
+}
+
+/// `SETNX`, which is `SET NX` replying with an integer.
+#[derive(Debug, Clone)]
+pub struct SetNx {
+    pub set: Set,
+}
+
+/// `INCR`, `DECR`, `INCRBY` and `DECRBY`.
+#[derive(Debug, Clone)]
+pub struct IncrBy {
+    pub key: Key,
+    pub delta: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncrByFloat {
+    pub key: Key,
+    pub delta: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Append {
+    pub key: Key,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct Strlen {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetRange {
+    pub key: Key,
+    pub start: i64,
+    pub end: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetRange {
+    pub key: Key,
+    pub offset: usize,
+    pub value: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct MGet {
+    pub keys: Vec<Key>,
+}
+
+/// `MSET` and `MSETNX`.
+#[derive(Debug, Clone)]
+pub struct MSet {
+    pub pairs: Vec<(Key, Bytes)>,
+    /// `MSETNX`, only set anything if none of the keys exist
+    pub not_exists: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetDel {
+    pub key: Key,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetEx {
+    pub key: Key,
+    pub expiry: Option<Expiry>,
+}
+
+/// Parses the argument of `EX`, `PX`, `EXAT` or `PXAT` into an expiry.
+fn parse_expiry(option: &str, args: &mut Arguments, command: &str) -> util::Result<Expiry> {
+    let invalid =
+        || -> GenericError { format!("ERR invalid expire time in '{}' command", command).into() };
+    let time = args.next_integer()?;
+    if time <= 0 {
+        return Err(invalid());
+    }
+    let millis = if option == "ex" || option == "exat" {
+        time.checked_mul(1000).ok_or_else(invalid)?
+    } else {
+        time
+    } as u64;
+    Ok(if option.ends_with("at") {
+        Expiry::At(millis)
+    } else {
+        Expiry::In(Duration::from_millis(millis))
+    })
+}
+
+impl Expiry {
+    /// The deadline of a key getting this expiry, given the deadline it had so far.
+    fn deadline(self, current: Option<Instant>) -> Option<Instant> {
+        match self {
+            Expiry::In(time) => Some(Instant::now() + time),
+            Expiry::At(unix_millis) => Some(util::instant_from_unix_millis(unix_millis)),
+            Expiry::Keep => current,
+            Expiry::Persist => None,
+        }
+    }
+}
+
+impl Set {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Set> {
+        let key = args.next_key()?;
+        let value = RespDataType::BulkStrings(Some(args.next_bytes()?));
+        let mut set = Set {
+            key,
+            value,
+            expiry: None,
+            condition: None,
+            get: false,
+        };
+
+        while !args.is_empty() {
+            let option = args.next_keyword()?;
+            match option.as_str() {
+                "nx" | "xx" if set.condition.is_none() => {
+                    set.condition = Some(if option == "nx" {
+                        Condition::NotExists
+                    } else {
+                        Condition::Exists
+                    });
+                }
+                "get" => set.get = true,
+                "keepttl" if set.expiry.is_none() => set.expiry = Some(Expiry::Keep),
+                "ex" | "px" | "exat" | "pxat" if set.expiry.is_none() && !args.is_empty() => {
+                    set.expiry = Some(parse_expiry(&option, &mut args, "set")?);
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+
+        Ok(set)
+    }
+
+    /// `SETEX` and `PSETEX`, taking the expiry before the value.
+    pub(super) fn parse_with_expiry(mut args: Arguments, unit: &str) -> util::Result<Set> {
+        args.expect_len(3)?;
+        let key = args.next_key()?;
+        let command = if unit == "ex" { "setex" } else { "psetex" };
+        let expiry = parse_expiry(unit, &mut args, command)?;
+        let value = RespDataType::BulkStrings(Some(args.next_bytes()?));
+        Ok(Set {
+            key,
+            value,
+            expiry: Some(expiry),
+            condition: None,
+            get: false,
+        })
+    }
+}
+
+impl Get {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Get> {
+        args.expect_len(1)?;
+        Ok(Get {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl SetNx {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SetNx> {
+        args.expect_len(2)?;
+        Ok(SetNx {
+            set: Set {
+                key: args.next_key()?,
+                value: RespDataType::BulkStrings(Some(args.next_bytes()?)),
+                expiry: None,
+                condition: Some(Condition::NotExists),
+                get: false,
+            },
+        })
+    }
+}
+
+impl IncrBy {
+    /// `INCR`/`DECR` when `delta` is given, `INCRBY`/`DECRBY` otherwise.
+    pub(super) fn parse(mut args: Arguments, delta: Option<i64>) -> util::Result<IncrBy> {
+        args.expect_len(if delta.is_some() { 1 } else { 2 })?;
+        let key = args.next_key()?;
+        let delta = match delta {
+            Some(delta) => delta,
+            None => args.next_integer()?,
+        };
+        Ok(IncrBy { key, delta })
+    }
+
+    pub(super) fn parse_decrement(mut args: Arguments) -> util::Result<IncrBy> {
+        args.expect_len(2)?;
+        let key = args.next_key()?;
+        let delta = args
+            .next_integer()?
+            .checked_neg()
+            .ok_or("ERR decrement would overflow")?;
+        Ok(IncrBy { key, delta })
+    }
+}
+
+impl IncrByFloat {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<IncrByFloat> {
+        args.expect_len(2)?;
+        Ok(IncrByFloat {
+            key: args.next_key()?,
+            delta: args.next_float()?,
+        })
+    }
+}
+
+impl Append {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Append> {
+        args.expect_len(2)?;
+        Ok(Append {
+            key: args.next_key()?,
+            value: args.next_bytes()?,
+        })
+    }
+}
+
+impl Strlen {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<Strlen> {
+        args.expect_len(1)?;
+        Ok(Strlen {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl GetRange {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GetRange> {
+        args.expect_len(3)?;
+        Ok(GetRange {
+            key: args.next_key()?,
+            start: args.next_integer()?,
+            end: args.next_integer()?,
+        })
+    }
+}
+
+impl SetRange {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<SetRange> {
+        args.expect_len(3)?;
+        let key = args.next_key()?;
+        let offset = args
+            .next_integer()?
+            .try_into()
+            .map_err(|_| "ERR offset is out of range")?;
+        let value = args.next_bytes()?;
+        Ok(SetRange { key, offset, value })
+    }
+}
+
+impl MGet {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<MGet> {
+        if args.is_empty() {
+            return Err(args.wrong_arity());
+        }
+        let mut keys = vec![];
+        while !args.is_empty() {
+            keys.push(args.next_key()?);
+        }
+        Ok(MGet { keys })
+    }
+}
+
+impl MSet {
+    pub(super) fn parse(mut args: Arguments, not_exists: bool) -> util::Result<MSet> {
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(args.wrong_arity());
+        }
+        let mut pairs = vec![];
+        while !args.is_empty() {
+            pairs.push((args.next_key()?, args.next_bytes()?));
+        }
+        Ok(MSet { pairs, not_exists })
+    }
+}
+
+impl GetDel {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GetDel> {
+        args.expect_len(1)?;
+        Ok(GetDel {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl GetEx {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<GetEx> {
+        let key = args.next_key()?;
+        let mut expiry = None;
+        while !args.is_empty() {
+            let option = args.next_keyword()?;
+            match option.as_str() {
+                "persist" if expiry.is_none() => expiry = Some(Expiry::Persist),
+                "ex" | "px" | "exat" | "pxat" if expiry.is_none() && !args.is_empty() => {
+                    expiry = Some(parse_expiry(&option, &mut args, "getex")?);
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(GetEx { key, expiry })
+    }
+}
+
+/// Looks up a string, `None` if the key does not exist.
+pub(super) fn get_string(db: &mut Db, key: &Key) -> util::Result<Option<Bytes>> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(entry) => entry
+            .value()
+            .as_string()
+            .map(Some)
+            .ok_or_else(|| WRONG_TYPE.into()),
+    }
+}
+
+/// Stores a string value, keeping the time to live of the key if it already exists.
+pub(super) fn put_string(db: &mut Db, key: &Key, value: RedisDataType) {
+    match db.get_mut(key) {
+        Some(entry) => {
+            *entry.value_mut() = value;
+            db.signal_modified(key);
+        }
+        None => {
+            db.insert(key.clone(), RedisDataTypeWithTTL::Infinite(value));
+        }
+    }
+}
+
+impl<'a> Command<'a, Db> for Set {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let old = context.get(&self.key);
+            let old_value = match old.map(|x| x.value()) {
+                Some(value) => match value.as_string() {
+                    Some(s) => Some(s),
+                    None if self.get => return Err(WRONG_TYPE.into()),
+                    None => None,
+                },
+                None => None,
+            };
+            let old_deadline = old.and_then(|x| x.deadline());
+            let exists = old.is_some();
+            let reply = |done: bool| {
+                if self.get {
+                    old_value
+                        .clone()
+                        .map(RespDataType::bulk_strings)
+                        .unwrap_or_else(RespDataType::empty_bulk_strings)
+                } else if done {
+                    RespDataType::simple_strings("OK")
+                } else {
+                    RespDataType::empty_bulk_strings()
+                }
+            };
+
+            match self.condition {
+                Some(Condition::NotExists) if exists => return Ok(reply(false)),
+                Some(Condition::Exists) if !exists => return Ok(reply(false)),
+                _ => {}
+            }
+
+            let value: RedisDataType = self.value.clone().try_into()?;
+            let deadline = self.expiry.and_then(|x| x.deadline(old_deadline));
+            context.insert(self.key.clone(), RedisDataTypeWithTTL::new(value, deadline));
+            Ok(reply(true))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Get {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(get_string(context, &self.key)?
+                .map(RespDataType::bulk_strings)
+                .unwrap_or_else(RespDataType::empty_bulk_strings))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SetNx {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let reply = self.set.execute(context).await?;
+            Ok(RespDataType::integers(
+                (reply != RespDataType::empty_bulk_strings()) as i64,
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for IncrBy {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = match context.get(&self.key).map(|x| x.value()) {
+                None => 0,
+                Some(RedisDataType::Integers(n)) => *n,
+                Some(RedisDataType::Strings(s)) => util::parse_integer(s).ok_or(NOT_AN_INTEGER)?,
+                Some(_) => return Err(WRONG_TYPE.into()),
+            };
+            let value = current
+                .checked_add(self.delta)
+                .ok_or("ERR increment or decrement would overflow")?;
+            put_string(context, &self.key, RedisDataType::Integers(value));
+            Ok(RespDataType::integers(value))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for IncrByFloat {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = match get_string(context, &self.key)? {
+                None => 0.0,
+                Some(s) => util::parse_float(&s).ok_or("ERR value is not a valid float")?,
+            };
+            let value = current + self.delta;
+            if !value.is_finite() {
+                return Err("ERR increment would produce NaN or Infinity".into());
+            }
+            let value = Bytes::from(util::format_double(value));
+            put_string(context, &self.key, RedisDataType::Strings(value.clone()));
+            Ok(RespDataType::bulk_strings(value))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Append {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = match get_string(context, &self.key)? {
+                None => self.value.clone(),
+                Some(current) => {
+                    if current.len() + self.value.len() > MAX_STRING_LEN {
+                        return Err(
+                            "ERR string exceeds maximum allowed size (proto-max-bulk-len)".into(),
+                        );
+                    }
+                    let mut value = Vec::with_capacity(current.len() + self.value.len());
+                    value.extend_from_slice(&current);
+                    value.extend_from_slice(&self.value);
+                    value.into()
+                }
+            };
+            let len = value.len();
+            put_string(context, &self.key, RedisDataType::Strings(value));
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for Strlen {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_string(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GetRange {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = get_string(context, &self.key)?.unwrap_or_default();
+            let len = value.len() as i64;
+            let (mut start, mut end) = (self.start, self.end);
+            if start < 0 && end < 0 && start > end {
+                return Ok(RespDataType::bulk_strings(""));
+            }
+            if start < 0 {
+                start += len;
+            }
+            if end < 0 {
+                end += len;
+            }
+            start = start.max(0);
+            end = end.max(0).min(len - 1);
+            if len == 0 || start > end {
+                return Ok(RespDataType::bulk_strings(""));
+            }
+            Ok(RespDataType::bulk_strings(
+                value.slice(start as usize..end as usize + 1),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for SetRange {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let current = get_string(context, &self.key)?;
+            if self.value.is_empty() {
+                // nothing to write, and a missing key is not created
+                let len = current.map_or(0, |x| x.len());
+                return Ok(RespDataType::integers(len as i64));
+            }
+            if self.offset + self.value.len() > MAX_STRING_LEN {
+                return Err("ERR string exceeds maximum allowed size (proto-max-bulk-len)".into());
+            }
+
+            let mut value = current.map(|x| x.to_vec()).unwrap_or_default();
+            if value.len() < self.offset + self.value.len() {
+                // zero-padded when writing past the end
+                value.resize(self.offset + self.value.len(), 0);
+            }
+            value[self.offset..self.offset + self.value.len()].copy_from_slice(&self.value);
+            let len = value.len();
+            put_string(context, &self.key, RedisDataType::Strings(value.into()));
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for MGet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(RespDataType::arrays(
+                self.keys
+                    .iter()
+                    .map(|key| {
+                        // keys holding something else than a string are reported as missing
+                        context
+                            .get(key)
+                            .and_then(|x| x.value().as_string())
+                            .map(RespDataType::bulk_strings)
+                            .unwrap_or_else(RespDataType::empty_bulk_strings)
+                    })
+                    .collect(),
+            ))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for MSet {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            if self.not_exists && self.pairs.iter().any(|(key, _)| context.get(key).is_some()) {
+                return Ok(RespDataType::integers(0));
+            }
+            for (key, value) in &self.pairs {
+                context.insert(
+                    key.clone(),
+                    RedisDataTypeWithTTL::Infinite(RedisDataType::Strings(value.clone())),
+                );
+            }
+            Ok(if self.not_exists {
+                RespDataType::integers(1)
+            } else {
+                RespDataType::simple_strings("OK")
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GetDel {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            Ok(match get_string(context, &self.key)? {
+                Some(value) => {
+                    context.remove(&self.key);
+                    RespDataType::bulk_strings(value)
+                }
+                None => RespDataType::empty_bulk_strings(),
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for GetEx {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let value = match get_string(context, &self.key)? {
+                Some(value) => value,
+                None => return Ok(RespDataType::empty_bulk_strings()),
+            };
+            if let Some(expiry) = self.expiry {
+                let current = context.get(&self.key).and_then(|x| x.deadline());
+                context.set_deadline(&self.key, expiry.deadline(current));
+            }
+            Ok(RespDataType::bulk_strings(value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GetRange, IncrBy, IncrByFloat, SetRange};
+    use crate::{
+        command::{
+            test_util::{run_err, run_ok},
+            Command,
+        },
+        data_type::RespDataType,
+        db::Db,
+    };
+
+    #[tokio::test]
+    async fn test_set_conditions() {
+        let mut db = Db::new();
+        let ok = RespDataType::simple_strings("OK");
+        let nil = RespDataType::empty_bulk_strings();
+
+        assert_eq!(run_ok(&mut db, "SET lock a XX").await, nil);
+        assert_eq!(run_ok(&mut db, "GET lock").await, nil);
+        assert_eq!(run_ok(&mut db, "SET lock a NX PX 30000").await, ok);
+        assert_eq!(run_ok(&mut db, "SET lock b NX").await, nil);
+        assert_eq!(
+            run_ok(&mut db, "GET lock").await,
+            RespDataType::bulk_strings("a")
+        );
+        assert_eq!(run_ok(&mut db, "SET lock c xx").await, ok);
+        assert_eq!(
+            run_ok(&mut db, "GET lock").await,
+            RespDataType::bulk_strings("c")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_get() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "SET key a GET").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "SET key b GET").await,
+            RespDataType::bulk_strings("a")
+        );
+        // GET replies with the old value even when NX prevents the write
+        assert_eq!(
+            run_ok(&mut db, "SET key c NX GET").await,
+            RespDataType::bulk_strings("b")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET key").await,
+            RespDataType::bulk_strings("b")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_expiry() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET key a EX 100").await;
+        let deadline = db.get(&"key".into()).and_then(|x| x.deadline());
+        assert!(deadline.is_some());
+
+        run_ok(&mut db, "SET key b KEEPTTL").await;
+        assert_eq!(db.get(&"key".into()).and_then(|x| x.deadline()), deadline);
+        run_ok(&mut db, "SET key c").await;
+        assert_eq!(db.get(&"key".into()).and_then(|x| x.deadline()), None);
+
+        run_ok(&mut db, "SET key d PXAT 1").await;
+        assert_eq!(
+            run_ok(&mut db, "GET key").await,
+            RespDataType::empty_bulk_strings()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_invalid_options() {
+        let mut db = Db::new();
+        for line in &[
+            "SET key a NX XX",
+            "SET key a EX 10 PX 10",
+            "SET key a KEEPTTL EX 10",
+            "SET key a EX",
+            "SET key a FOO",
+        ] {
+            assert_eq!(run_err(&mut db, line).await, "ERR syntax error");
+        }
+        for line in &[
+            "SET key a EX 0",
+            "SET key a PX -1",
+            "SET key a EX 9223372036854775807",
+        ] {
+            assert_eq!(
+                run_err(&mut db, line).await,
+                "ERR invalid expire time in 'set' command"
+            );
+        }
+        assert_eq!(
+            run_err(&mut db, "SET key").await,
+            "ERR wrong number of arguments for 'set' command"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_counters() {
+        let mut db = Db::new();
+        assert_eq!(run_ok(&mut db, "INCR n").await, RespDataType::integers(1));
+        assert_eq!(
+            run_ok(&mut db, "INCRBY n 10").await,
+            RespDataType::integers(11)
+        );
+        assert_eq!(run_ok(&mut db, "DECR n").await, RespDataType::integers(10));
+        assert_eq!(
+            run_ok(&mut db, "DECRBY n 20").await,
+            RespDataType::integers(-10)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET n").await,
+            RespDataType::bulk_strings("-10")
+        );
+        assert_eq!(
+            run_ok(&mut db, "APPEND n 5").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "INCR n").await,
+            RespDataType::integers(-104)
+        );
+
+        run_ok(&mut db, "SET n 9223372036854775807").await;
+        assert_eq!(
+            run_err(&mut db, "INCR n").await,
+            "ERR increment or decrement would overflow"
+        );
+        for value in &["abc", "' 1'", "01", "+1", "1.5", "''"] {
+            run_ok(&mut db, &format!("SET n {}", value)).await;
+            assert_eq!(
+                run_err(&mut db, "INCR n").await,
+                "ERR value is not an integer or out of range"
+            );
+        }
+        assert_eq!(
+            run_err(&mut db, "INCRBY n x").await,
+            "ERR value is not an integer or out of range"
+        );
+        assert_eq!(
+            run_err(&mut db, "DECRBY n -9223372036854775808").await,
+            "ERR decrement would overflow"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_incrbyfloat() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET f 10.50").await;
+        assert_eq!(
+            run_ok(&mut db, "INCRBYFLOAT f 0.1").await,
+            RespDataType::bulk_strings("10.6")
+        );
+        assert_eq!(
+            run_ok(&mut db, "INCRBYFLOAT f -5").await,
+            RespDataType::bulk_strings("5.6")
+        );
+        run_ok(&mut db, "SET f 5.0e3").await;
+        assert_eq!(
+            run_ok(&mut db, "INCRBYFLOAT f 2.0e2").await,
+            RespDataType::bulk_strings("5200")
+        );
+        run_ok(&mut db, "INCR i").await;
+        assert_eq!(
+            run_ok(&mut db, "INCRBYFLOAT i 1.5").await,
+            RespDataType::bulk_strings("2.5")
+        );
+        assert_eq!(
+            run_err(&mut db, "INCRBYFLOAT f nan").await,
+            "ERR value is not a valid float"
+        );
+        assert_eq!(
+            run_err(&mut db, "INCRBYFLOAT f inf").await,
+            "ERR increment would produce NaN or Infinity"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ranges() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET s \"This is a string\"").await;
+        for (start, end, expected) in &[
+            (0, 3, "This"),
+            (-3, -1, "ing"),
+            (0, -1, "This is a string"),
+            (10, 100, "string"),
+            (5, 3, ""),
+            (-1, -5, ""),
+            (-100, 3, "This"),
+        ] {
+            let command = GetRange {
+                key: "s".into(),
+                start: *start,
+                end: *end,
+            };
+            assert_eq!(
+                command.execute(&mut db).await.unwrap(),
+                RespDataType::bulk_strings(*expected)
+            );
+        }
+
+        let command = SetRange {
+            key: "sparse".into(),
+            offset: 5,
+            value: "abc".into(),
+        };
+        assert_eq!(
+            command.execute(&mut db).await.unwrap(),
+            RespDataType::integers(8)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET sparse").await,
+            RespDataType::bulk_strings(&b"\0\0\0\0\0abc"[..])
+        );
+        assert_eq!(
+            run_ok(&mut db, "SETRANGE sparse 1 xy").await,
+            RespDataType::integers(8)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET sparse").await,
+            RespDataType::bulk_strings(&b"\0xy\0\0abc"[..])
+        );
+        assert_eq!(
+            run_ok(&mut db, "SETRANGE missing 10 ''").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "GET missing").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_err(&mut db, "SETRANGE sparse -1 x").await,
+            "ERR offset is out of range"
+        );
+        assert_eq!(
+            run_err(&mut db, "SETRANGE sparse 536870911 xy").await,
+            "ERR string exceeds maximum allowed size (proto-max-bulk-len)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multi_key() {
+        let mut db = Db::new();
+        assert_eq!(
+            run_ok(&mut db, "MSET a 1 b 2").await,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            run_ok(&mut db, "MGET a b c").await,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("1"),
+                RespDataType::bulk_strings("2"),
+                RespDataType::empty_bulk_strings(),
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "MSETNX c 3 a 4").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "MSETNX c 3 d 4").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_err(&mut db, "MSET a 1 b").await,
+            "ERR wrong number of arguments for 'mset' command"
+        );
+        assert_eq!(
+            run_ok(&mut db, "SETNX a 5").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "SETNX e 5").await,
+            RespDataType::integers(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_getdel_getex() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SETEX k 100 v").await;
+        assert!(db.get(&"k".into()).and_then(|x| x.deadline()).is_some());
+        assert_eq!(
+            run_ok(&mut db, "GETEX k PERSIST").await,
+            RespDataType::bulk_strings("v")
+        );
+        assert_eq!(db.get(&"k".into()).and_then(|x| x.deadline()), None);
+        run_ok(&mut db, "GETEX k PX 100000").await;
+        assert!(db.get(&"k".into()).and_then(|x| x.deadline()).is_some());
+        assert_eq!(
+            run_err(&mut db, "GETEX k EX 10 PERSIST").await,
+            "ERR syntax error"
+        );
+        assert_eq!(
+            run_err(&mut db, "PSETEX k 0 v").await,
+            "ERR invalid expire time in 'psetex' command"
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "GETDEL k").await,
+            RespDataType::bulk_strings("v")
+        );
+        assert_eq!(
+            run_ok(&mut db, "GETDEL k").await,
+            RespDataType::empty_bulk_strings()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_strings_commands_keep_ttl() {
+        let mut db = Db::new();
+        run_ok(&mut db, "SET k 1 EX 100").await;
+        for command in &[
+            IncrBy {
+                key: "k".into(),
+                delta: 1,
+            }
+            .execute(&mut db)
+            .await,
+            IncrByFloat {
+                key: "k".into(),
+                delta: 1.5,
+            }
+            .execute(&mut db)
+            .await,
+        ] {
+            assert!(command.is_ok());
+        }
+        run_ok(&mut db, "APPEND k 0").await;
+        run_ok(&mut db, "SETRANGE k 0 9").await;
+        assert_eq!(
+            run_ok(&mut db, "GET k").await,
+            RespDataType::bulk_strings("9.50")
+        );
+        assert!(db.get(&"k".into()).and_then(|x| x.deadline()).is_some());
+        assert_eq!(run_ok(&mut db, "STRLEN k").await, RespDataType::integers(4));
+    }
+}
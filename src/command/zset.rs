@@ -0,0 +1,1298 @@
+use super::{list, Arguments, BlockingCommand, Command, SYNTAX_ERROR, WRONG_TYPE};
+use crate::{
+    data_type::{
+        Key, LexBound, LexRange, RedisDataType, RedisDataTypeWithTTL, RespDataType, ScoreRange,
+        SortedSet,
+    },
+    db::Db,
+    util::{self, BoxFuture},
+};
+use bytes::Bytes;
+use std::{collections::HashMap, ops::Range, time::Duration};
+
+/// `ZADD` and `ZINCRBY`.
+#[derive(Debug, Clone)]
+pub struct ZAdd {
+    pub key: Key,
+    pub pairs: Vec<(f64, Bytes)>,
+    /// `NX`, only add new members
+    pub nx: bool,
+    /// `XX`, only update existing members
+    pub xx: bool,
+    /// `GT`, only update scores to greater ones
+    pub gt: bool,
+    /// `LT`, only update scores to lesser ones
+    pub lt: bool,
+    /// `CH`, reply with the number of members added or updated rather than added
+    pub ch: bool,
+    /// `INCR`, add to the score and reply with the new score
+    pub incr: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZRem {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZCard {
+    pub key: Key,
+}
+
+/// `ZSCORE` and `ZMSCORE`.
+#[derive(Debug, Clone)]
+pub struct ZScore {
+    pub key: Key,
+    pub members: Vec<Bytes>,
+    /// `ZMSCORE`, replying with an array
+    pub multiple: bool,
+}
+
+/// `ZRANK` and `ZREVRANK`.
+#[derive(Debug, Clone)]
+pub struct ZRank {
+    pub key: Key,
+    pub member: Bytes,
+    pub rev: bool,
+    pub with_score: bool,
+}
+
+/// `ZCOUNT` and `ZLEXCOUNT`.
+#[derive(Debug, Clone)]
+pub struct ZCount {
+    pub key: Key,
+    /// Either a score or a lex range
+    pub by: RangeBy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RangeBy {
+    /// Inclusive, possibly negative, ranks
+    Rank(i64, i64),
+    Score(ScoreRange),
+    Lex(LexRange),
+}
+
+/// `ZRANGE`, `ZRANGESTORE` and the older `ZREVRANGE`, `ZRANGEBYSCORE`,
+/// `ZREVRANGEBYSCORE`, `ZRANGEBYLEX` and `ZREVRANGEBYLEX`.
+#[derive(Debug, Clone)]
+pub struct ZRange {
+    pub key: Key,
+    pub by: RangeBy,
+    /// Highest scores first
+    pub rev: bool,
+    /// `LIMIT offset count`, a negative count meaning all the rest
+    pub limit: Option<(i64, i64)>,
+    pub with_scores: bool,
+    /// `ZRANGESTORE`, store the range there and reply with its size
+    pub destination: Option<Key>,
+}
+
+/// `ZPOPMIN` and `ZPOPMAX`.
+#[derive(Debug, Clone)]
+pub struct ZPop {
+    pub key: Key,
+    pub min: bool,
+    pub count: Option<usize>,
+}
+
+/// `BZPOPMIN` and `BZPOPMAX`.
+#[derive(Debug, Clone)]
+pub struct BZPop {
+    pub keys: Vec<Key>,
+    pub min: bool,
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZSetOperation {
+    Union,
+    Inter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf and -inf add up to 0 rather than NaN
+            Aggregate::Sum => Some(a + b).filter(|x| !x.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// `ZUNIONSTORE` and `ZINTERSTORE`.
+#[derive(Debug, Clone)]
+pub struct ZStore {
+    pub operation: ZSetOperation,
+    pub destination: Key,
+    pub keys: Vec<Key>,
+    pub weights: Vec<f64>,
+    pub aggregate: Aggregate,
+}
+
+/// Looks up a sorted set, `None` if the key does not exist.
+pub(super) fn get_zset<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a SortedSet>> {
+    match db.get(key).map(|x| x.value()) {
+        None => Ok(None),
+        Some(RedisDataType::SortedSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+fn get_zset_mut<'a>(db: &'a mut Db, key: &Key) -> util::Result<Option<&'a mut SortedSet>> {
+    match db.get_mut(key).map(|x| x.value_mut()) {
+        None => Ok(None),
+        Some(RedisDataType::SortedSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(WRONG_TYPE.into()),
+    }
+}
+
+/// Looks up a sorted set to write to, creating it if needed.
+fn get_or_create_zset<'a>(db: &'a mut Db, key: &Key) -> util::Result<&'a mut SortedSet> {
+    if get_zset(db, key)?.is_none() {
+        db.insert(
+            key.clone(),
+            RedisDataTypeWithTTL::Infinite(RedisDataType::SortedSet(SortedSet::new())),
+        );
+    }
+    Ok(get_zset_mut(db, key)?.expect("the sorted set exists"))
+}
+
+/// Removes the sorted set at `key` if it has no members left.
+fn remove_if_empty(db: &mut Db, key: &Key) {
+    if matches!(get_zset(db, key), Ok(Some(zset)) if zset.is_empty()) {
+        db.remove(key);
+    }
+}
+
+/// Stores a sorted set at `destination`, or removes it if the set is empty, returning the
+/// size of the set.
+pub(super) fn store(db: &mut Db, destination: &Key, zset: SortedSet) -> usize {
+    let len = zset.len();
+    if zset.is_empty() {
+        db.remove(destination);
+    } else {
+        db.insert(
+            destination.clone(),
+            RedisDataTypeWithTTL::Infinite(RedisDataType::SortedSet(zset)),
+        );
+    }
+    len
+}
+
+/// Pops up to `count` members with the lowest or highest scores.
+fn pop(db: &mut Db, key: &Key, min: bool, count: usize) -> util::Result<Vec<(Bytes, f64)>> {
+    let zset = match get_zset_mut(db, key)? {
+        Some(zset) => zset,
+        None => return Ok(vec![]),
+    };
+    let count = count.min(zset.len());
+    let mut popped = if min {
+        zset.range(0, count.saturating_sub(1))
+    } else {
+        zset.range(zset.len() - count, zset.len() - 1)
+    };
+    if count == 0 {
+        popped.clear();
+    }
+    if !min {
+        popped.reverse();
+    }
+    for (member, _) in &popped {
+        zset.remove(member);
+    }
+    if !popped.is_empty() {
+        db.signal_modified(key);
+    }
+    remove_if_empty(db, key);
+    Ok(popped)
+}
+
+fn scored_reply(members: Vec<(Bytes, f64)>, with_scores: bool) -> RespDataType {
+    RespDataType::arrays(
+        members
+            .into_iter()
+            .flat_map(|(member, score)| {
+                let score = if with_scores {
+                    Some(RespDataType::doubles(score))
+                } else {
+                    None
+                };
+                std::iter::once(RespDataType::bulk_strings(member)).chain(score)
+            })
+            .collect(),
+    )
+}
+
+/// Parses a score bound such as `1.5`, `(1.5` or `-inf`, along with whether it is
+/// exclusive.
+fn parse_score_bound(arg: &[u8]) -> Option<(f64, bool)> {
+    match arg.strip_prefix(b"(") {
+        Some(arg) => util::parse_float(arg).map(|x| (x, true)),
+        None => util::parse_float(arg).map(|x| (x, false)),
+    }
+}
+
+fn parse_score_range(min: &[u8], max: &[u8]) -> util::Result<ScoreRange> {
+    let error = "ERR min or max is not a float";
+    let (min, min_exclusive) = parse_score_bound(min).ok_or(error)?;
+    let (max, max_exclusive) = parse_score_bound(max).ok_or(error)?;
+    Ok(ScoreRange {
+        min,
+        max,
+        min_exclusive,
+        max_exclusive,
+    })
+}
+
+fn parse_lex_bound(arg: &Bytes) -> Option<LexBound> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Some(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Some(LexBound::Max),
+        Some(b'[') => Some(LexBound::Inclusive(arg.slice(1..))),
+        Some(b'(') => Some(LexBound::Exclusive(arg.slice(1..))),
+        _ => None,
+    }
+}
+
+fn parse_lex_range(min: &Bytes, max: &Bytes) -> util::Result<LexRange> {
+    let error = "ERR min or max not valid string range item";
+    Ok(LexRange {
+        min: parse_lex_bound(min).ok_or(error)?,
+        max: parse_lex_bound(max).ok_or(error)?,
+    })
+}
+
+fn parse_rank(arg: &[u8]) -> util::Result<i64> {
+    util::parse_integer(arg).ok_or_else(|| "ERR value is not an integer or out of range".into())
+}
+
+fn parse_members(args: &mut Arguments) -> util::Result<Vec<Bytes>> {
+    if args.is_empty() {
+        return Err(args.wrong_arity());
+    }
+    let mut members = vec![];
+    while !args.is_empty() {
+        members.push(args.next_bytes()?);
+    }
+    Ok(members)
+}
+
+impl ZAdd {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<ZAdd> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let mut zadd = ZAdd {
+            key: args.next_key()?,
+            pairs: vec![],
+            nx: false,
+            xx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+        };
+        while let Some(option) = args.peek_keyword() {
+            match option.as_str() {
+                "nx" => zadd.nx = true,
+                "xx" => zadd.xx = true,
+                "gt" => zadd.gt = true,
+                "lt" => zadd.lt = true,
+                "ch" => zadd.ch = true,
+                "incr" => zadd.incr = true,
+                _ => break,
+            }
+            args.next_keyword()?;
+        }
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(SYNTAX_ERROR.into());
+        }
+        if zadd.nx && zadd.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".into());
+        }
+        if (zadd.nx && (zadd.gt || zadd.lt)) || (zadd.gt && zadd.lt) {
+            return Err("ERR GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+        if zadd.incr && args.len() > 2 {
+            return Err("ERR INCR option supports a single increment-element pair".into());
+        }
+        while !args.is_empty() {
+            zadd.pairs.push((args.next_float()?, args.next_bytes()?));
+        }
+        Ok(zadd)
+    }
+
+    pub(super) fn parse_zincrby(mut args: Arguments) -> util::Result<ZAdd> {
+        args.expect_len(3)?;
+        let key = args.next_key()?;
+        Ok(ZAdd {
+            key,
+            pairs: vec![(args.next_float()?, args.next_bytes()?)],
+            nx: false,
+            xx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: true,
+        })
+    }
+}
+
+impl ZRem {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<ZRem> {
+        let key = args.next_key()?;
+        Ok(ZRem {
+            key,
+            members: parse_members(&mut args)?,
+        })
+    }
+}
+
+impl ZCard {
+    pub(super) fn parse(mut args: Arguments) -> util::Result<ZCard> {
+        args.expect_len(1)?;
+        Ok(ZCard {
+            key: args.next_key()?,
+        })
+    }
+}
+
+impl ZScore {
+    pub(super) fn parse(mut args: Arguments, multiple: bool) -> util::Result<ZScore> {
+        if !multiple {
+            args.expect_len(2)?;
+        }
+        let key = args.next_key()?;
+        Ok(ZScore {
+            key,
+            members: parse_members(&mut args)?,
+            multiple,
+        })
+    }
+}
+
+impl ZRank {
+    pub(super) fn parse(mut args: Arguments, rev: bool) -> util::Result<ZRank> {
+        if args.len() < 2 || args.len() > 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let member = args.next_bytes()?;
+        let with_score = match args.is_empty() {
+            true => false,
+            false if args.next_keyword()? == "withscore" => true,
+            false => return Err(SYNTAX_ERROR.into()),
+        };
+        Ok(ZRank {
+            key,
+            member,
+            rev,
+            with_score,
+        })
+    }
+}
+
+impl ZCount {
+    pub(super) fn parse(mut args: Arguments, lex: bool) -> util::Result<ZCount> {
+        args.expect_len(3)?;
+        let key = args.next_key()?;
+        let (min, max) = (args.next_bytes()?, args.next_bytes()?);
+        let by = if lex {
+            RangeBy::Lex(parse_lex_range(&min, &max)?)
+        } else {
+            RangeBy::Score(parse_score_range(&min, &max)?)
+        };
+        Ok(ZCount { key, by })
+    }
+}
+
+/// How a `ZRANGE` family command reads its range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+impl ZRange {
+    /// Parses `ZRANGE` and `ZRANGESTORE`, where the kind of range and its direction are
+    /// options.
+    pub(super) fn parse(mut args: Arguments, store: bool) -> util::Result<ZRange> {
+        if args.len() < 3 + store as usize {
+            return Err(args.wrong_arity());
+        }
+        let destination = if store { Some(args.next_key()?) } else { None };
+        let key = args.next_key()?;
+        let (start, stop) = (args.next_bytes()?, args.next_bytes()?);
+        let (mut kind, mut rev, mut limit, mut with_scores) = (RangeKind::Rank, false, None, false);
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "byscore" => kind = RangeKind::Score,
+                "bylex" => kind = RangeKind::Lex,
+                "rev" => rev = true,
+                "limit" if args.len() >= 2 => {
+                    limit = Some((args.next_integer()?, args.next_integer()?))
+                }
+                "withscores" if !store => with_scores = true,
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        ZRange::new(key, destination, kind, rev, start, stop, limit, with_scores)
+    }
+
+    /// Parses the older commands, where the kind of range and its direction are part of
+    /// the command.
+    pub(super) fn parse_legacy(
+        mut args: Arguments,
+        kind: RangeKind,
+        rev: bool,
+    ) -> util::Result<ZRange> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let (start, stop) = (args.next_bytes()?, args.next_bytes()?);
+        let (mut limit, mut with_scores) = (None, false);
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "limit" if kind != RangeKind::Rank && args.len() >= 2 => {
+                    limit = Some((args.next_integer()?, args.next_integer()?))
+                }
+                "withscores" if kind != RangeKind::Lex => with_scores = true,
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        ZRange::new(key, None, kind, rev, start, stop, limit, with_scores)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        key: Key,
+        destination: Option<Key>,
+        kind: RangeKind,
+        rev: bool,
+        start: Bytes,
+        stop: Bytes,
+        limit: Option<(i64, i64)>,
+        with_scores: bool,
+    ) -> util::Result<ZRange> {
+        if limit.is_some() && kind == RangeKind::Rank {
+            return Err(
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                    .into(),
+            );
+        }
+        if with_scores && kind == RangeKind::Lex {
+            return Err(
+                "ERR syntax error, WITHSCORES not supported in combination with BYLEX".into(),
+            );
+        }
+        // reversed score and lex ranges are given highest first
+        let (min, max) = if rev {
+            (&stop, &start)
+        } else {
+            (&start, &stop)
+        };
+        let by = match kind {
+            RangeKind::Rank => RangeBy::Rank(parse_rank(&start)?, parse_rank(&stop)?),
+            RangeKind::Score => RangeBy::Score(parse_score_range(min, max)?),
+            RangeKind::Lex => RangeBy::Lex(parse_lex_range(min, max)?),
+        };
+        Ok(ZRange {
+            key,
+            by,
+            rev,
+            limit,
+            with_scores,
+            destination,
+        })
+    }
+
+    /// The members in the range, in the order of the reply.
+    fn members(&self, zset: &SortedSet) -> Vec<(Bytes, f64)> {
+        let len = zset.len();
+        let ranks = |ranks: Option<(usize, usize)>| match ranks {
+            Some((first, last)) => first..last + 1,
+            None => 0..0,
+        };
+        let mut range: Range<usize> = match &self.by {
+            RangeBy::Rank(start, stop) => {
+                let range = list::range(*start, *stop, len);
+                if self.rev {
+                    // ranks count from the highest score
+                    len - range.end..len - range.start
+                } else {
+                    range
+                }
+            }
+            RangeBy::Score(range) => ranks(zset.score_ranks(range)),
+            RangeBy::Lex(range) => ranks(zset.lex_ranks(range)),
+        };
+
+        if let Some((offset, count)) = self.limit {
+            if offset < 0 {
+                return vec![];
+            }
+            let offset = (offset as usize).min(range.len());
+            let count = if count < 0 {
+                range.len()
+            } else {
+                count as usize
+            };
+            let count = count.min(range.len() - offset);
+            range = if self.rev {
+                range.end - offset - count..range.end - offset
+            } else {
+                range.start + offset..range.start + offset + count
+            };
+        }
+        if range.is_empty() {
+            return vec![];
+        }
+        let mut members = zset.range(range.start, range.end - 1);
+        if self.rev {
+            members.reverse();
+        }
+        members
+    }
+}
+
+impl ZPop {
+    pub(super) fn parse(mut args: Arguments, min: bool) -> util::Result<ZPop> {
+        if args.is_empty() || args.len() > 2 {
+            return Err(args.wrong_arity());
+        }
+        let key = args.next_key()?;
+        let count = if args.is_empty() {
+            None
+        } else {
+            Some(args.next_positive()?)
+        };
+        Ok(ZPop { key, min, count })
+    }
+}
+
+impl BZPop {
+    pub(super) fn parse(mut args: Arguments, min: bool) -> util::Result<BZPop> {
+        if args.len() < 2 {
+            return Err(args.wrong_arity());
+        }
+        let mut keys = vec![];
+        while args.len() > 1 {
+            keys.push(args.next_key()?);
+        }
+        Ok(BZPop {
+            keys,
+            min,
+            timeout: args.next_timeout()?,
+        })
+    }
+}
+
+impl ZStore {
+    pub(super) fn parse(mut args: Arguments, operation: ZSetOperation) -> util::Result<ZStore> {
+        if args.len() < 3 {
+            return Err(args.wrong_arity());
+        }
+        let destination = args.next_key()?;
+        let count = args.next_integer()?;
+        if count < 1 {
+            return Err(format!(
+                "ERR at least 1 input key is needed for '{}' command",
+                args.command
+            )
+            .into());
+        }
+        if count as usize > args.len() {
+            return Err(SYNTAX_ERROR.into());
+        }
+        let mut keys = vec![];
+        for _ in 0..count {
+            keys.push(args.next_key()?);
+        }
+
+        let mut weights = vec![1.0; keys.len()];
+        let mut aggregate = Aggregate::Sum;
+        while !args.is_empty() {
+            match args.next_keyword()?.as_str() {
+                "weights" if args.len() >= keys.len() => {
+                    for weight in weights.iter_mut() {
+                        *weight = util::parse_float(&args.next_bytes()?)
+                            .ok_or("ERR weight value is not a float")?;
+                    }
+                }
+                "aggregate" if !args.is_empty() => {
+                    aggregate = match args.next_keyword()?.as_str() {
+                        "sum" => Aggregate::Sum,
+                        "min" => Aggregate::Min,
+                        "max" => Aggregate::Max,
+                        _ => return Err(SYNTAX_ERROR.into()),
+                    }
+                }
+                _ => return Err(SYNTAX_ERROR.into()),
+            }
+        }
+        Ok(ZStore {
+            operation,
+            destination,
+            keys,
+            weights,
+            aggregate,
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZAdd {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let null_reply = || {
+                if self.incr {
+                    RespDataType::empty_bulk_strings()
+                } else {
+                    RespDataType::integers(0)
+                }
+            };
+            let zset = match get_zset(context, &self.key)? {
+                None if self.xx => return Ok(null_reply()),
+                zset => zset,
+            };
+            if self.incr {
+                let (delta, member) = &self.pairs[0];
+                let current = zset.and_then(|x| x.score(member)).unwrap_or(0.0);
+                if (current + delta).is_nan() {
+                    return Err("ERR resulting score is not a number (NaN)".into());
+                }
+            }
+
+            let zset = get_or_create_zset(context, &self.key)?;
+            let (mut added, mut updated, mut score) = (0, 0, None);
+            for (new, member) in &self.pairs {
+                let current = zset.score(member);
+                let new = match current {
+                    Some(current) if self.incr => current + new,
+                    _ => *new,
+                };
+                match current {
+                    None if self.xx => continue,
+                    None => added += 1,
+                    Some(_) if self.nx => continue,
+                    Some(current) if self.gt && new <= current => continue,
+                    Some(current) if self.lt && new >= current => continue,
+                    Some(current) if current != new => updated += 1,
+                    Some(_) => {}
+                }
+                zset.insert(member.clone(), new);
+                score = Some(new);
+            }
+            if added > 0 || updated > 0 {
+                context.signal_modified(&self.key);
+            }
+            remove_if_empty(context, &self.key);
+            if added > 0 {
+                context.signal_ready(&self.key);
+            }
+
+            Ok(if self.incr {
+                score.map_or_else(null_reply, RespDataType::doubles)
+            } else if self.ch {
+                RespDataType::integers(added + updated)
+            } else {
+                RespDataType::integers(added)
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZRem {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = match get_zset_mut(context, &self.key)? {
+                Some(zset) => zset,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            let removed = self
+                .members
+                .iter()
+                .filter(|member| zset.remove(member))
+                .count();
+            if removed > 0 {
+                context.signal_modified(&self.key);
+            }
+            remove_if_empty(context, &self.key);
+            Ok(RespDataType::integers(removed as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZCard {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let len = get_zset(context, &self.key)?.map_or(0, |x| x.len());
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZScore {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = get_zset(context, &self.key)?;
+            let mut scores = self.members.iter().map(|member| {
+                zset.and_then(|x| x.score(member))
+                    .map(RespDataType::doubles)
+                    .unwrap_or_else(RespDataType::empty_bulk_strings)
+            });
+            Ok(if self.multiple {
+                RespDataType::arrays(scores.collect())
+            } else {
+                scores.next().expect("a member")
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZRank {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = get_zset(context, &self.key)?;
+            let (rank, score) = match zset.and_then(|x| Some((x.rank(&self.member)?, x))) {
+                Some((rank, zset)) => {
+                    let rank = if self.rev {
+                        zset.len() - 1 - rank
+                    } else {
+                        rank
+                    };
+                    (rank, zset.score(&self.member).expect("a member"))
+                }
+                None if self.with_score => return Ok(RespDataType::empty_arrays()),
+                None => return Ok(RespDataType::empty_bulk_strings()),
+            };
+            Ok(if self.with_score {
+                RespDataType::arrays(vec![
+                    RespDataType::integers(rank as i64),
+                    RespDataType::doubles(score),
+                ])
+            } else {
+                RespDataType::integers(rank as i64)
+            })
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZCount {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let zset = match get_zset(context, &self.key)? {
+                Some(zset) => zset,
+                None => return Ok(RespDataType::integers(0)),
+            };
+            let ranks = match &self.by {
+                RangeBy::Score(range) => zset.score_ranks(range),
+                RangeBy::Lex(range) => zset.lex_ranks(range),
+                RangeBy::Rank(..) => unreachable!("counts are by score or lex"),
+            };
+            let count = ranks.map_or(0, |(first, last)| last - first + 1);
+            Ok(RespDataType::integers(count as i64))
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZRange {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let members = get_zset(context, &self.key)?
+                .map(|x| self.members(x))
+                .unwrap_or_default();
+            match &self.destination {
+                Some(destination) => {
+                    let mut zset = SortedSet::new();
+                    for (member, score) in members {
+                        zset.insert(member, score);
+                    }
+                    let len = store(context, destination, zset);
+                    Ok(RespDataType::integers(len as i64))
+                }
+                None => Ok(scored_reply(members, self.with_scores)),
+            }
+        })
+    }
+}
+
+impl<'a> Command<'a, Db> for ZPop {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let popped = pop(context, &self.key, self.min, self.count.unwrap_or(1))?;
+            Ok(scored_reply(popped, true))
+        })
+    }
+}
+
+impl BlockingCommand for BZPop {
+    fn keys(&self) -> Vec<Key> {
+        self.keys.clone()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn try_serve(&self, db: &mut Db) -> util::Result<Option<RespDataType>> {
+        for key in &self.keys {
+            if let Some((member, score)) = pop(db, key, self.min, 1)?.pop() {
+                return Ok(Some(RespDataType::arrays(vec![
+                    RespDataType::bulk_strings(key.clone()),
+                    RespDataType::bulk_strings(member),
+                    RespDataType::doubles(score),
+                ])));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Command<'a, Db> for ZStore {
+    fn execute(&'a self, context: &'a mut Db) -> BoxFuture<'a, util::Result<RespDataType>> {
+        Box::pin(async move {
+            let mut inputs = vec![];
+            for key in &self.keys {
+                // plain sets count as sorted sets with all scores 1
+                let members = match context.get(key).map(|x| x.value()) {
+                    None => vec![],
+                    Some(RedisDataType::SortedSet(zset)) => zset
+                        .iter()
+                        .map(|(member, score)| (member.clone(), score))
+                        .collect(),
+                    Some(RedisDataType::Set(set)) => set.iter().map(|x| (x, 1.0)).collect(),
+                    Some(_) => return Err(WRONG_TYPE.into()),
+                };
+                inputs.push(members);
+            }
+
+            let weighted = |score: f64, weight: f64| {
+                // 0 times inf is 0 rather than NaN
+                Some(score * weight).filter(|x| !x.is_nan()).unwrap_or(0.0)
+            };
+            let mut scores = HashMap::<Bytes, f64>::new();
+            for (i, (members, weight)) in inputs.into_iter().zip(&self.weights).enumerate() {
+                match self.operation {
+                    ZSetOperation::Union => {
+                        for (member, score) in members {
+                            let score = weighted(score, *weight);
+                            let score = match scores.get(&member) {
+                                Some(current) => self.aggregate.apply(*current, score),
+                                None => score,
+                            };
+                            scores.insert(member, score);
+                        }
+                    }
+                    ZSetOperation::Inter if i == 0 => {
+                        scores = members
+                            .into_iter()
+                            .map(|(member, score)| (member, weighted(score, *weight)))
+                            .collect();
+                    }
+                    ZSetOperation::Inter => {
+                        let members = members.into_iter().collect::<HashMap<_, _>>();
+                        scores = scores
+                            .into_iter()
+                            .filter_map(|(member, current)| {
+                                let score = weighted(*members.get(&member)?, *weight);
+                                Some((member, self.aggregate.apply(current, score)))
+                            })
+                            .collect();
+                    }
+                }
+            }
+
+            let mut zset = SortedSet::new();
+            for (member, score) in scores {
+                zset.insert(member, score);
+            }
+            let len = store(context, &self.destination, zset);
+            Ok(RespDataType::integers(len as i64))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::test_util::{run_err, run_ok},
+        data_type::RespDataType,
+        db::Db,
+        util,
+    };
+
+    /// An array reply, with scores as the strings RESP2 clients get.
+    fn strings(reply: RespDataType) -> Vec<String> {
+        match reply {
+            RespDataType::Arrays(Some(values)) => values
+                .into_iter()
+                .map(|x| match x {
+                    RespDataType::BulkStrings(Some(x)) => String::from_utf8(x.to_vec()).unwrap(),
+                    RespDataType::Doubles(x) => util::format_double(x),
+                    RespDataType::Integers(x) => x.to_string(),
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect(),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    async fn leaderboard(db: &mut Db) {
+        run_ok(db, "ZADD board 100 ann 80 bob 120 cid 80 dan 95 eve").await;
+    }
+
+    #[tokio::test]
+    async fn test_zadd() {
+        let mut db = Db::new();
+        leaderboard(&mut db).await;
+        assert_eq!(
+            run_ok(&mut db, "ZCARD board").await,
+            RespDataType::integers(5)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board 10 ann 1 zed").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board CH 20 ann 1 zed 2 yan").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board NX 30 ann").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board XX 5 nobody").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board GT CH 10 ann 30 bob").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board LT CH 10 ann").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZSCORE board ann").await,
+            RespDataType::doubles(10.0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board INCR 5 ann").await,
+            RespDataType::doubles(15.0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD board INCR GT -1 ann").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZINCRBY board 2.5 new").await,
+            RespDataType::doubles(2.5)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZMSCORE board new nobody").await,
+            RespDataType::arrays(vec![
+                RespDataType::doubles(2.5),
+                RespDataType::empty_bulk_strings()
+            ])
+        );
+        assert_eq!(
+            run_ok(&mut db, "TYPE board").await,
+            RespDataType::simple_strings("zset")
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZREM board ann bob nobody").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZADD missing XX 1 a").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS missing").await,
+            RespDataType::integers(0)
+        );
+
+        run_ok(&mut db, "ZADD inf +inf a").await;
+        for (line, error) in &[
+            (
+                "ZADD board NX XX 1 a",
+                "ERR XX and NX options at the same time are not compatible",
+            ),
+            (
+                "ZADD board GT LT 1 a",
+                "ERR GT, LT, and/or NX options at the same time are not compatible",
+            ),
+            (
+                "ZADD board INCR 1 a 2 b",
+                "ERR INCR option supports a single increment-element pair",
+            ),
+            ("ZADD board 1 a 2", "ERR syntax error"),
+            ("ZADD board one a", "ERR value is not a valid float"),
+            (
+                "ZINCRBY inf -inf a",
+                "ERR resulting score is not a number (NaN)",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ranks() {
+        let mut db = Db::new();
+        leaderboard(&mut db).await;
+        // equal scores are ordered by member
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board 0 -1").await),
+            vec!["bob", "dan", "eve", "ann", "cid"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZRANK board dan").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZREVRANK board dan").await,
+            RespDataType::integers(3)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZREVRANK board cid WITHSCORE").await),
+            vec!["0", "120"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZRANK board nobody").await,
+            RespDataType::empty_bulk_strings()
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZREVRANGE board 0 1 WITHSCORES").await),
+            vec!["cid", "120", "ann", "100"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board -2 -1 REV").await),
+            vec!["dan", "bob"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board 3 100").await),
+            vec!["ann", "cid"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_score_ranges() {
+        let mut db = Db::new();
+        leaderboard(&mut db).await;
+        assert_eq!(
+            run_ok(&mut db, "ZCOUNT board 80 100").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZCOUNT board (80 (100").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZCOUNT board -inf +inf").await,
+            RespDataType::integers(5)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGEBYSCORE board (80 +inf WITHSCORES").await),
+            vec!["eve", "95", "ann", "100", "cid", "120"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZREVRANGEBYSCORE board 100 -inf LIMIT 1 2").await),
+            vec!["eve", "dan"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board 90 inf BYSCORE LIMIT 1 -1").await),
+            vec!["ann", "cid"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board +inf 90 BYSCORE REV LIMIT 0 1").await),
+            vec!["cid"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE board 200 300 BYSCORE").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE missing 0 -1").await),
+            Vec::<String>::new()
+        );
+
+        assert_eq!(
+            run_ok(&mut db, "ZRANGESTORE top board 100 +inf BYSCORE").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE top 0 -1 WITHSCORES").await),
+            vec!["ann", "100", "cid", "120"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZRANGESTORE top board 1000 +inf BYSCORE").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS top").await,
+            RespDataType::integers(0)
+        );
+
+        for (line, error) in &[
+            ("ZCOUNT board a 1", "ERR min or max is not a float"),
+            (
+                "ZRANGE board 0 1 LIMIT 0 1",
+                "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX",
+            ),
+            (
+                "ZRANGE board - + BYLEX WITHSCORES",
+                "ERR syntax error, WITHSCORES not supported in combination with BYLEX",
+            ),
+            ("ZRANGE board a b", "ERR value is not an integer or out of range"),
+            ("ZRANGEBYSCORE board 0 1 REV", "ERR syntax error"),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lex_ranges() {
+        let mut db = Db::new();
+        run_ok(&mut db, "ZADD words 0 apple 0 banana 0 cherry 0 date").await;
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGEBYLEX words [b (d").await),
+            vec!["banana", "cherry"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZREVRANGEBYLEX words + (b LIMIT 0 2").await),
+            vec!["date", "cherry"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE words [c + BYLEX").await),
+            vec!["cherry", "date"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZLEXCOUNT words - +").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            run_err(&mut db, "ZLEXCOUNT words a +").await,
+            "ERR min or max not valid string range item"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pop() {
+        let mut db = Db::new();
+        leaderboard(&mut db).await;
+        assert_eq!(
+            strings(run_ok(&mut db, "ZPOPMIN board").await),
+            vec!["bob", "80"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZPOPMAX board 2").await),
+            vec!["cid", "120", "ann", "100"]
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZPOPMIN board 0").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZPOPMIN board 10").await),
+            vec!["dan", "80", "eve", "95"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS board").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZPOPMAX board").await),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            run_err(&mut db, "ZPOPMIN board -1").await,
+            "ERR value is out of range, must be positive"
+        );
+
+        // without anything to wait for, the blocking variants reply right away
+        run_ok(&mut db, "ZADD z 1 a 2 b").await;
+        assert_eq!(
+            strings(run_ok(&mut db, "BZPOPMAX missing z 0").await),
+            vec!["z", "b", "2"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "BZPOPMIN missing 0").await,
+            RespDataType::empty_arrays()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store() {
+        let mut db = Db::new();
+        run_ok(&mut db, "ZADD a 1 x 2 y 3 z").await;
+        run_ok(&mut db, "ZADD b 10 y 20 z 30 w").await;
+        run_ok(&mut db, "SADD s z w").await;
+
+        assert_eq!(
+            run_ok(&mut db, "ZUNIONSTORE out 2 a b").await,
+            RespDataType::integers(4)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE out 0 -1 WITHSCORES").await),
+            vec!["x", "1", "y", "12", "z", "23", "w", "30"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZINTERSTORE out 2 a b WEIGHTS 2 1 AGGREGATE MAX").await,
+            RespDataType::integers(2)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE out 0 -1 WITHSCORES").await),
+            vec!["y", "10", "z", "20"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZINTERSTORE out 3 a b s AGGREGATE MIN").await,
+            RespDataType::integers(1)
+        );
+        assert_eq!(
+            strings(run_ok(&mut db, "ZRANGE out 0 -1 WITHSCORES").await),
+            vec!["z", "1"]
+        );
+        assert_eq!(
+            run_ok(&mut db, "ZINTERSTORE out 2 a missing").await,
+            RespDataType::integers(0)
+        );
+        assert_eq!(
+            run_ok(&mut db, "EXISTS out").await,
+            RespDataType::integers(0)
+        );
+
+        run_ok(&mut db, "SET str v").await;
+        for (line, error) in &[
+            (
+                "ZUNIONSTORE out 0 a",
+                "ERR at least 1 input key is needed for 'zunionstore' command",
+            ),
+            ("ZUNIONSTORE out 3 a b", "ERR syntax error"),
+            ("ZUNIONSTORE out 2 a b WEIGHTS 1", "ERR syntax error"),
+            (
+                "ZUNIONSTORE out 2 a b WEIGHTS 1 x",
+                "ERR weight value is not a float",
+            ),
+            (
+                "ZUNIONSTORE out 2 a str",
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+        ] {
+            assert_eq!(run_err(&mut db, line).await, *error);
+        }
+    }
+}
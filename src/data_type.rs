@@ -1,24 +1,167 @@
 #![allow(dead_code)]
 
-use crate::{util, util::BoxFuture};
-use std::convert::{TryFrom, TryInto};
+use crate::{
+    codec::{Decoder, Encoder, RespCodec},
+    util,
+    util::BoxFuture,
+};
+use bytes::{Bytes, BytesMut};
+use std::{
+    borrow::Borrow,
+    collections::VecDeque,
+    convert::{TryFrom, TryInto},
+    fmt,
+    pin::Pin,
+};
 use tokio::prelude::*;
-use tokio::time;
+use tokio::{io, time};
+
+pub mod geohash;
+mod hash;
+mod hyperloglog;
+mod set;
+mod stream;
+mod table;
+mod zset;
+pub use geohash::{GeoHashBits, GeoShape};
+pub use hash::Hash;
+pub use hyperloglog::HyperLogLog;
+pub use set::Set;
+pub use stream::{ConsumerGroup, Fields, Stream, StreamId};
+pub use table::Table;
+pub use zset::{LexBound, LexRange, ScoreRange, SortedSet};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RespDataType {
     SimpleStrings(Vec<u8>),
     Errors(Vec<u8>),
     Integers(i64),
-    BulkStrings(Option<Vec<u8>>),
+    BulkStrings(Option<Bytes>),
     Arrays(Option<Vec<RespDataType>>),
+    // RESP3 only
+    Null,
+    Doubles(f64),
+    Booleans(bool),
+    BigNumbers(Vec<u8>),
+    VerbatimStrings([u8; 3], Vec<u8>),
+    Maps(Vec<(RespDataType, RespDataType)>),
+    Sets(Vec<RespDataType>),
+    Attributes(Vec<(RespDataType, RespDataType)>),
+    Pushes(Vec<RespDataType>),
+    BlobErrors(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl TryFrom<i64> for ProtocolVersion {
+    type Error = util::GenericError;
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            2 => Ok(ProtocolVersion::Resp2),
+            3 => Ok(ProtocolVersion::Resp3),
+            _ => Err("NOPROTO unsupported protocol version".into()),
+        }
+    }
+}
+
+impl From<ProtocolVersion> for i64 {
+    fn from(value: ProtocolVersion) -> Self {
+        match value {
+            ProtocolVersion::Resp2 => 2,
+            ProtocolVersion::Resp3 => 3,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RedisDataType {
-    Strings(Vec<u8>),
+    Strings(Bytes),
     Integers(i64),
     Array(Vec<RedisDataType>),
+    List(VecDeque<Bytes>),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl RedisDataType {
+    /// The name `TYPE` replies with.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            RedisDataType::Strings(_) | RedisDataType::Integers(_) => "string",
+            RedisDataType::Array(_) | RedisDataType::List(_) => "list",
+            RedisDataType::Hash(_) => "hash",
+            RedisDataType::Set(_) => "set",
+            RedisDataType::SortedSet(_) => "zset",
+            RedisDataType::Stream(_) => "stream",
+        }
+    }
+
+    /// The value as a string, for the types stored as one. Integers are a compact
+    /// encoding of strings holding a decimal number.
+    pub fn as_string(&self) -> Option<Bytes> {
+        match self {
+            RedisDataType::Strings(x) => Some(x.clone()),
+            RedisDataType::Integers(n) => Some(n.to_string().into()),
+            _ => None,
+        }
+    }
+}
+
+/// A key of the keyspace: an arbitrary, binary-safe byte string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key(Bytes);
+
+impl Key {
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+}
+
+impl From<Bytes> for Key {
+    fn from(value: Bytes) -> Self {
+        Key(value)
+    }
+}
+
+impl From<&'static str> for Key {
+    fn from(value: &'static str) -> Self {
+        Key(Bytes::from(value))
+    }
+}
+
+impl From<Key> for Bytes {
+    fn from(value: Key) -> Self {
+        value.0
+    }
+}
+
+impl Borrow<[u8]> for Key {
+    fn borrow(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl TryFrom<RespDataType> for Key {
+    type Error = util::GenericError;
+    fn try_from(value: RespDataType) -> Result<Self, Self::Error> {
+        match value {
+            RespDataType::BulkStrings(Some(x)) => Ok(Key(x)),
+            _ => Err("key is not bulk string".into()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -27,12 +170,106 @@ pub enum RedisDataTypeWithTTL {
     Finite(RedisDataType, time::Instant),
 }
 
+impl RedisDataTypeWithTTL {
+    pub fn new(value: RedisDataType, deadline: Option<time::Instant>) -> RedisDataTypeWithTTL {
+        match deadline {
+            Some(deadline) => RedisDataTypeWithTTL::Finite(value, deadline),
+            None => RedisDataTypeWithTTL::Infinite(value),
+        }
+    }
+
+    pub fn value(&self) -> &RedisDataType {
+        match self {
+            RedisDataTypeWithTTL::Infinite(value) | RedisDataTypeWithTTL::Finite(value, _) => value,
+        }
+    }
+
+    pub fn value_mut(&mut self) -> &mut RedisDataType {
+        match self {
+            RedisDataTypeWithTTL::Infinite(value) | RedisDataTypeWithTTL::Finite(value, _) => value,
+        }
+    }
+
+    pub fn into_value(self) -> RedisDataType {
+        match self {
+            RedisDataTypeWithTTL::Infinite(value) | RedisDataTypeWithTTL::Finite(value, _) => value,
+        }
+    }
+
+    pub fn deadline(&self) -> Option<time::Instant> {
+        match self {
+            RedisDataTypeWithTTL::Infinite(_) => None,
+            RedisDataTypeWithTTL::Finite(_, deadline) => Some(*deadline),
+        }
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<time::Instant>) {
+        let value = std::mem::replace(self.value_mut(), RedisDataType::Integers(0));
+        *self = RedisDataTypeWithTTL::new(value, deadline);
+    }
+
+    pub fn is_expired(&self, now: time::Instant) -> bool {
+        matches!(self.deadline(), Some(deadline) if now > deadline)
+    }
+}
+
+/// A stream entry the way stream commands reply with it: its ID, then its fields and
+/// values in one array.
+pub fn stream_entry(id: StreamId, fields: &Fields) -> RespDataType {
+    RespDataType::arrays(vec![
+        RespDataType::bulk_strings(id.to_string()),
+        RespDataType::arrays(
+            fields
+                .iter()
+                .flat_map(|(field, value)| {
+                    vec![
+                        RespDataType::bulk_strings(field.clone()),
+                        RespDataType::bulk_strings(value.clone()),
+                    ]
+                })
+                .collect(),
+        ),
+    ])
+}
+
 impl TryFrom<RedisDataType> for RespDataType {
     type Error = util::GenericError;
     fn try_from(value: RedisDataType) -> Result<Self, Self::Error> {
         match value {
             RedisDataType::Strings(x) => Ok(RespDataType::bulk_strings(x)),
             RedisDataType::Integers(n) => Ok(RespDataType::Integers(n)),
+            RedisDataType::List(list) => Ok(RespDataType::arrays(
+                list.into_iter().map(RespDataType::bulk_strings).collect(),
+            )),
+            RedisDataType::Hash(hash) => Ok(RespDataType::maps(
+                hash.iter()
+                    .map(|(field, value)| {
+                        (
+                            RespDataType::bulk_strings(field.clone()),
+                            RespDataType::bulk_strings(value.clone()),
+                        )
+                    })
+                    .collect(),
+            )),
+            RedisDataType::Set(set) => Ok(RespDataType::sets(
+                set.iter().map(RespDataType::bulk_strings).collect(),
+            )),
+            RedisDataType::SortedSet(zset) => Ok(RespDataType::arrays(
+                zset.iter()
+                    .flat_map(|(member, score)| {
+                        vec![
+                            RespDataType::bulk_strings(member.clone()),
+                            RespDataType::doubles(score),
+                        ]
+                    })
+                    .collect(),
+            )),
+            RedisDataType::Stream(stream) => Ok(RespDataType::arrays(
+                stream
+                    .range(..)
+                    .map(|(id, fields)| stream_entry(id, fields))
+                    .collect(),
+            )),
             RedisDataType::Array(a) => Ok(RespDataType::arrays(
                 a.into_iter()
                     .map(|x| x.try_into())
@@ -72,8 +309,8 @@ impl RespDataType {
         RespDataType::Integers(num)
     }
 
-    pub fn bulk_strings<S: AsRef<[u8]>>(str: S) -> RespDataType {
-        RespDataType::BulkStrings(Some(str.as_ref().to_owned()))
+    pub fn bulk_strings<S: Into<Bytes>>(str: S) -> RespDataType {
+        RespDataType::BulkStrings(Some(str.into()))
     }
 
     pub fn empty_bulk_strings() -> RespDataType {
@@ -88,6 +325,46 @@ impl RespDataType {
         RespDataType::Arrays(None)
     }
 
+    pub fn null() -> RespDataType {
+        RespDataType::Null
+    }
+
+    pub fn doubles(num: f64) -> RespDataType {
+        RespDataType::Doubles(num)
+    }
+
+    pub fn booleans(value: bool) -> RespDataType {
+        RespDataType::Booleans(value)
+    }
+
+    pub fn big_numbers<S: AsRef<[u8]>>(str: S) -> RespDataType {
+        RespDataType::BigNumbers(str.as_ref().to_owned())
+    }
+
+    pub fn verbatim_strings<S: AsRef<[u8]>>(format: [u8; 3], str: S) -> RespDataType {
+        RespDataType::VerbatimStrings(format, str.as_ref().to_owned())
+    }
+
+    pub fn maps(vec: Vec<(RespDataType, RespDataType)>) -> RespDataType {
+        RespDataType::Maps(vec)
+    }
+
+    pub fn sets(vec: Vec<RespDataType>) -> RespDataType {
+        RespDataType::Sets(vec)
+    }
+
+    pub fn attributes(vec: Vec<(RespDataType, RespDataType)>) -> RespDataType {
+        RespDataType::Attributes(vec)
+    }
+
+    pub fn pushes(vec: Vec<RespDataType>) -> RespDataType {
+        RespDataType::Pushes(vec)
+    }
+
+    pub fn blob_errors<S: AsRef<[u8]>>(str: S) -> RespDataType {
+        RespDataType::BlobErrors(str.as_ref().to_owned())
+    }
+
     pub fn tag(&self) -> u8 {
         match self {
             Self::SimpleStrings(_) => b'+',
@@ -95,12 +372,74 @@ impl RespDataType {
             Self::Integers(_) => b':',
             Self::BulkStrings(_) => b'$',
             Self::Arrays(_) => b'*',
+            Self::Null => b'_',
+            Self::Doubles(_) => b',',
+            Self::Booleans(_) => b'#',
+            Self::BigNumbers(_) => b'(',
+            Self::VerbatimStrings(_, _) => b'=',
+            Self::Maps(_) => b'%',
+            Self::Sets(_) => b'~',
+            Self::Attributes(_) => b'|',
+            Self::Pushes(_) => b'>',
+            Self::BlobErrors(_) => b'!',
+        }
+    }
+
+    /// Rewrites a reply so that it can be sent to a client speaking `protocol`.
+    ///
+    /// Commands build their replies with RESP2 nulls and RESP3 aggregates freely; RESP2
+    /// clients get the RESP3 types flattened the same way redis does (maps become flat
+    /// arrays, doubles become bulk strings, ...), and RESP3 clients get the single `_` null.
+    pub fn into_protocol(self, protocol: ProtocolVersion) -> RespDataType {
+        let convert_all = |vec: Vec<RespDataType>| {
+            vec.into_iter()
+                .map(|x| x.into_protocol(protocol))
+                .collect::<Vec<_>>()
+        };
+        let convert_pairs = |vec: Vec<(RespDataType, RespDataType)>| {
+            vec.into_iter()
+                .map(|(k, v)| (k.into_protocol(protocol), v.into_protocol(protocol)))
+                .collect::<Vec<_>>()
+        };
+
+        match (protocol, self) {
+            (_, Self::Arrays(Some(arr))) => Self::Arrays(Some(convert_all(arr))),
+            (ProtocolVersion::Resp3, Self::BulkStrings(None))
+            | (ProtocolVersion::Resp3, Self::Arrays(None)) => Self::Null,
+            (ProtocolVersion::Resp3, Self::Maps(map)) => Self::Maps(convert_pairs(map)),
+            (ProtocolVersion::Resp3, Self::Attributes(map)) => Self::Attributes(convert_pairs(map)),
+            (ProtocolVersion::Resp3, Self::Sets(set)) => Self::Sets(convert_all(set)),
+            (ProtocolVersion::Resp3, Self::Pushes(arr)) => Self::Pushes(convert_all(arr)),
+            (ProtocolVersion::Resp2, Self::Null) => Self::BulkStrings(None),
+            (ProtocolVersion::Resp2, Self::Doubles(num)) => {
+                Self::bulk_strings(util::format_double(num))
+            }
+            (ProtocolVersion::Resp2, Self::Booleans(value)) => Self::Integers(value as i64),
+            (ProtocolVersion::Resp2, Self::BigNumbers(str))
+            | (ProtocolVersion::Resp2, Self::VerbatimStrings(_, str)) => {
+                Self::BulkStrings(Some(str.into()))
+            }
+            (ProtocolVersion::Resp2, Self::Maps(map))
+            | (ProtocolVersion::Resp2, Self::Attributes(map)) => Self::Arrays(Some(
+                convert_pairs(map)
+                    .into_iter()
+                    .flat_map(|(k, v)| vec![k, v])
+                    .collect(),
+            )),
+            (ProtocolVersion::Resp2, Self::Sets(arr))
+            | (ProtocolVersion::Resp2, Self::Pushes(arr)) => Self::Arrays(Some(convert_all(arr))),
+            (ProtocolVersion::Resp2, Self::BlobErrors(err)) => Self::Errors(
+                err.into_iter()
+                    .map(|x| if x == b'\r' || x == b'\n' { b' ' } else { x })
+                    .collect(),
+            ),
+            (_, other) => other,
         }
     }
 }
 
 impl RespDataType {
-    pub fn into_bulk_strings(self) -> util::Result<Option<Vec<u8>>> {
+    pub fn into_bulk_strings(self) -> util::Result<Option<Bytes>> {
         match self {
             RespDataType::BulkStrings(x) => Ok(x),
             _ => Err("expected bulk strings".into()),
@@ -127,118 +466,40 @@ impl RespDataType {
         sink: &'a mut S,
     ) -> BoxFuture<'a, crate::util::Result<()>> {
         Box::pin(async move {
-            sink.write_u8(self.tag()).await?;
-            match self {
-                Self::SimpleStrings(str) => {
-                    sink.write(str).await?;
-                    sink.write(b"\r\n").await?;
-                }
-                Self::Errors(err) => {
-                    sink.write(err).await?;
-                    sink.write(b"\r\n").await?;
-                }
-                Self::Integers(num) => {
-                    sink.write(num.to_string().as_bytes()).await?;
-                    sink.write(b"\r\n").await?;
-                }
-                Self::BulkStrings(str) => {
-                    match str {
-                        Some(str) => {
-                            sink.write(str.len().to_string().as_bytes()).await?;
-                            sink.write(b"\r\n").await?;
-                            sink.write(str).await?;
-                        }
-                        None => {
-                            sink.write(b"-1").await?;
-                        }
-                    }
-                    sink.write(b"\r\n").await?;
-                }
-                Self::Arrays(Some(arr)) => {
-                    sink.write(arr.len().to_string().as_bytes()).await?;
-                    sink.write(b"\r\n").await?;
-                    for elem in arr {
-                        elem.serialize(sink).await?;
-                    }
-                }
-
-                Self::Arrays(None) => {
-                    sink.write(b"-1").await?;
-                    sink.write(b"\r\n").await?;
-                }
-            };
-
+            let mut buf = BytesMut::new();
+            RespCodec::default().encode(self, &mut buf)?;
+            sink.write_all(&buf).await?;
             Ok(())
         })
     }
 
+    /// Reads a single frame from `source`, leaving anything buffered after it in place.
     pub fn deserialize<S: AsyncBufRead + Unpin + Send + Sync>(
         source: &mut S,
-    ) -> BoxFuture<crate::util::Result<RespDataType>> {
-        async fn expect_new_line<S: AsyncBufRead + std::marker::Unpin>(
-            source: &mut S,
-        ) -> crate::util::Result<()> {
-            let mut newline: [u8; 2] = [0; 2];
-            source.read_exact(&mut newline).await?;
-            if &newline != b"\r\n" {
-                Err(format!("expected <CR> <LF>, got {:?}", newline).into())
-            } else {
-                Ok(())
-            }
-        }
-
+    ) -> BoxFuture<'_, crate::util::Result<RespDataType>> {
         Box::pin(async move {
-            match source.read_u8().await? {
-                b'+' => {
-                    let mut buf = String::new();
-                    source.read_line(&mut buf).await?;
-                    let buf = crate::util::strip_trailing_newline(&buf).to_owned();
-                    Ok(RespDataType::SimpleStrings(buf.into_bytes()))
-                }
-                b'-' => {
-                    let mut buf = String::new();
-                    source.read_line(&mut buf).await?;
-                    let buf = crate::util::strip_trailing_newline(&buf).to_owned();
-                    Ok(RespDataType::Errors(buf.into_bytes()))
-                }
-                b':' => {
-                    let mut buf = String::new();
-                    source.read_line(&mut buf).await?;
-                    let buf = crate::util::strip_trailing_newline(&buf).to_owned();
-                    let num = buf.parse()?;
-                    Ok(RespDataType::Integers(num))
+            let mut codec = RespCodec::default();
+            let mut buf = BytesMut::new();
+            loop {
+                let filled = tokio::future::poll_fn(|cx| {
+                    Pin::new(&mut *source).poll_fill_buf(cx).map_ok(|x| {
+                        buf.extend_from_slice(x);
+                        x.len()
+                    })
+                })
+                .await?;
+                if filled == 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
                 }
-                b'$' => {
-                    let mut buf = String::new();
-                    source.read_line(&mut buf).await?;
-                    let buf = crate::util::strip_trailing_newline(&buf).to_owned();
-                    let n = buf.parse::<i64>()?;
-                    Ok(RespDataType::BulkStrings(if n >= 0 {
-                        let mut str = vec![0; n as usize];
-                        source.read_exact(&mut str).await?;
-                        expect_new_line(source).await?;
-                        Some(str)
-                    } else {
-                        None
-                    }))
-                }
-                b'*' => {
-                    let mut buf = String::new();
-                    source.read_line(&mut buf).await?;
-                    let buf = crate::util::strip_trailing_newline(&buf).to_owned();
-                    let n = buf.parse::<i64>()?;
-                    Ok(RespDataType::Arrays(if n >= 0 {
-                        let mut vec = Vec::with_capacity(n as usize);
-                        for _ in 0..n {
-                            let deser = Self::deserialize(source).await?;
-                            vec.push(deser);
-                        }
-                        Some(vec)
-                    } else {
-                        None
-                    }))
+
+                match codec.decode(&mut buf)? {
+                    Some(frame) => {
+                        // the unused tail necessarily comes from the last chunk
+                        Pin::new(&mut *source).consume(filled - buf.len());
+                        return Ok(frame);
+                    }
+                    None => Pin::new(&mut *source).consume(filled),
                 }
-                x => Err(format!("unknown message tag '{}'", x).into()),
             }
         })
     }
@@ -246,7 +507,7 @@ impl RespDataType {
 
 #[cfg(test)]
 mod tests {
-    use super::RespDataType;
+    use super::{ProtocolVersion, RespDataType};
     use crate::util::Result;
     use bytes::BytesMut;
     use tokio::io::BufStream;
@@ -255,7 +516,7 @@ mod tests {
     async fn assert_idempotent(data: RespDataType, expected: &str) -> Result<()> {
         let (mut client, server) = tokio::io::duplex(4096);
         let mut server = BufStream::new(server);
-        client.write(expected.as_bytes()).await?;
+        client.write_all(expected.as_bytes()).await?;
         let server_read_data = RespDataType::deserialize(&mut server).await?;
         assert_eq!(data, server_read_data);
 
@@ -269,6 +530,22 @@ mod tests {
         Ok(())
     }
 
+    async fn assert_deserialize(input: &str, expected: RespDataType) -> Result<()> {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let mut server = BufStream::new(server);
+        client.write_all(input.as_bytes()).await?;
+        assert_eq!(RespDataType::deserialize(&mut server).await?, expected);
+        Ok(())
+    }
+
+    fn inline(args: &[&'static str]) -> RespDataType {
+        RespDataType::arrays(
+            args.iter()
+                .map(|x| RespDataType::bulk_strings(*x))
+                .collect(),
+        )
+    }
+
     #[tokio::test]
     async fn test_simple_strings() -> Result<()> {
         assert_idempotent(RespDataType::simple_strings("OK"), "+OK\r\n").await?;
@@ -364,4 +641,154 @@ mod tests {
         .await?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_inline_commands() -> Result<()> {
+        assert_deserialize("PING\r\n", inline(&["PING"])).await?;
+        assert_deserialize("PING\n", inline(&["PING"])).await?;
+        assert_deserialize("SET foo bar\r\n", inline(&["SET", "foo", "bar"])).await?;
+        assert_deserialize("  SET   foo\tbar  \r\n", inline(&["SET", "foo", "bar"])).await?;
+        assert_deserialize("\r\n\r\nGET foo\r\n", inline(&["GET", "foo"])).await?;
+        assert_deserialize(
+            "SET \"hello world\" 'it\\'s'\r\n",
+            inline(&["SET", "hello world", "it's"]),
+        )
+        .await?;
+        assert_deserialize(
+            "ECHO \"a\\x41\\n\\\"b\\\"\"\r\n",
+            inline(&["ECHO", "aA\n\"b\""]),
+        )
+        .await?;
+        assert_deserialize("SET \"\" ''\r\n", inline(&["SET", "", ""])).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_inline_unbalanced_quotes() -> Result<()> {
+        for input in &["SET \"foo bar\r\n", "SET 'foo\r\n", "SET \"foo\"bar\r\n"] {
+            let (mut client, server) = tokio::io::duplex(4096);
+            let mut server = BufStream::new(server);
+            client.write_all(input.as_bytes()).await?;
+            assert!(RespDataType::deserialize(&mut server).await.is_err());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resp3_scalars() -> Result<()> {
+        assert_idempotent(RespDataType::null(), "_\r\n").await?;
+        assert_idempotent(RespDataType::booleans(true), "#t\r\n").await?;
+        assert_idempotent(RespDataType::booleans(false), "#f\r\n").await?;
+        assert_idempotent(RespDataType::doubles(1.23), ",1.23\r\n").await?;
+        assert_idempotent(RespDataType::doubles(10.0), ",10\r\n").await?;
+        assert_idempotent(RespDataType::doubles(f64::INFINITY), ",inf\r\n").await?;
+        assert_idempotent(RespDataType::doubles(f64::NEG_INFINITY), ",-inf\r\n").await?;
+        assert_idempotent(
+            RespDataType::big_numbers("3492890328409238509324850943850943825024385"),
+            "(3492890328409238509324850943850943825024385\r\n",
+        )
+        .await?;
+        assert_idempotent(
+            RespDataType::verbatim_strings(*b"txt", "Some string"),
+            "=15\r\ntxt:Some string\r\n",
+        )
+        .await?;
+        assert_idempotent(
+            RespDataType::blob_errors("SYNTAX invalid syntax"),
+            "!21\r\nSYNTAX invalid syntax\r\n",
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resp3_aggregates() -> Result<()> {
+        assert_idempotent(
+            RespDataType::maps(vec![
+                (
+                    RespDataType::simple_strings("first"),
+                    RespDataType::integers(1),
+                ),
+                (
+                    RespDataType::simple_strings("second"),
+                    RespDataType::integers(2),
+                ),
+            ]),
+            "%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n",
+        )
+        .await?;
+        assert_idempotent(
+            RespDataType::sets(vec![
+                RespDataType::simple_strings("a"),
+                RespDataType::integers(1),
+            ]),
+            "~2\r\n+a\r\n:1\r\n",
+        )
+        .await?;
+        assert_idempotent(
+            RespDataType::pushes(vec![
+                RespDataType::simple_strings("pubsub"),
+                RespDataType::simple_strings("message"),
+            ]),
+            ">2\r\n+pubsub\r\n+message\r\n",
+        )
+        .await?;
+        assert_idempotent(
+            RespDataType::attributes(vec![(
+                RespDataType::simple_strings("key-popularity"),
+                RespDataType::maps(vec![
+                    (
+                        RespDataType::bulk_strings("a"),
+                        RespDataType::doubles(0.1923),
+                    ),
+                    (
+                        RespDataType::bulk_strings("b"),
+                        RespDataType::doubles(0.0012),
+                    ),
+                ]),
+            )]),
+            "|1\r\n+key-popularity\r\n%2\r\n$1\r\na\r\n,0.1923\r\n$1\r\nb\r\n,0.0012\r\n",
+        )
+        .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_protocol() {
+        let reply = RespDataType::arrays(vec![
+            RespDataType::maps(vec![(
+                RespDataType::bulk_strings("score"),
+                RespDataType::doubles(1.5),
+            )]),
+            RespDataType::empty_bulk_strings(),
+            RespDataType::sets(vec![RespDataType::booleans(true)]),
+        ]);
+
+        assert_eq!(
+            reply.clone().into_protocol(ProtocolVersion::Resp2),
+            RespDataType::arrays(vec![
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("score"),
+                    RespDataType::bulk_strings("1.5"),
+                ]),
+                RespDataType::empty_bulk_strings(),
+                RespDataType::arrays(vec![RespDataType::integers(1)]),
+            ])
+        );
+        assert_eq!(
+            reply.into_protocol(ProtocolVersion::Resp3),
+            RespDataType::arrays(vec![
+                RespDataType::maps(vec![(
+                    RespDataType::bulk_strings("score"),
+                    RespDataType::doubles(1.5),
+                )]),
+                RespDataType::null(),
+                RespDataType::sets(vec![RespDataType::booleans(true)]),
+            ])
+        );
+        assert_eq!(
+            RespDataType::empty_arrays().into_protocol(ProtocolVersion::Resp3),
+            RespDataType::null()
+        );
+    }
 }
@@ -0,0 +1,318 @@
+//! Geohashes the way redis computes them, interleaving the bits of the longitude and the
+//! latitude so that points close to each other get close scores in a sorted set.
+
+pub const LONG_MIN: f64 = -180.0;
+pub const LONG_MAX: f64 = 180.0;
+/// The latitudes the Web Mercator projection covers.
+pub const LAT_MIN: f64 = -85.051_128_78;
+pub const LAT_MAX: f64 = 85.051_128_78;
+
+/// Bits for each of the longitude and the latitude, 52 in all.
+pub const STEP_MAX: u32 = 26;
+
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+/// Half the circumference of the earth in the Mercator projection.
+const MERCATOR_MAX: f64 = 20_037_726.37;
+
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// A geohash with `step` bits for each coordinate, the longitude bits being the odd ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeoHashBits {
+    pub bits: u64,
+    pub step: u32,
+}
+
+/// The cell a geohash stands for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoArea {
+    pub longitude: (f64, f64),
+    pub latitude: (f64, f64),
+}
+
+/// A shape to search in, with sizes in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+/// Whether a longitude and a latitude can be indexed.
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&longitude) && (LAT_MIN..=LAT_MAX).contains(&latitude)
+}
+
+/// Spreads the 32 bits of `x` to the even bits of the result.
+fn spread(x: u32) -> u64 {
+    (0..32).fold(0, |result, i| result | ((x as u64 >> i) & 1) << (2 * i))
+}
+
+/// Gathers the even bits of `x`.
+fn squash(x: u64) -> u32 {
+    (0..32).fold(0, |result, i| result | (((x >> (2 * i)) & 1) as u32) << i)
+}
+
+impl GeoHashBits {
+    fn encode_in(longitude: f64, latitude: f64, lat_range: (f64, f64), step: u32) -> Self {
+        let lat_offset = (latitude - lat_range.0) / (lat_range.1 - lat_range.0);
+        let long_offset = (longitude - LONG_MIN) / (LONG_MAX - LONG_MIN);
+        let scale = (1u64 << step) as f64;
+        let bits = spread((lat_offset * scale) as u32) | spread((long_offset * scale) as u32) << 1;
+        GeoHashBits { bits, step }
+    }
+
+    /// The geohash of a valid position.
+    pub fn encode(longitude: f64, latitude: f64, step: u32) -> GeoHashBits {
+        GeoHashBits::encode_in(longitude, latitude, (LAT_MIN, LAT_MAX), step)
+    }
+
+    /// The geohash kept as the score of a member.
+    pub fn from_score(score: f64) -> GeoHashBits {
+        GeoHashBits {
+            bits: score as u64,
+            step: STEP_MAX,
+        }
+    }
+
+    pub fn score(self) -> f64 {
+        self.bits as f64
+    }
+
+    pub fn area(self) -> GeoArea {
+        let scale = (1u64 << self.step) as f64;
+        let (lat, long) = (squash(self.bits) as f64, squash(self.bits >> 1) as f64);
+        let (lat_scale, long_scale) = (LAT_MAX - LAT_MIN, LONG_MAX - LONG_MIN);
+        GeoArea {
+            longitude: (
+                LONG_MIN + long / scale * long_scale,
+                LONG_MIN + (long + 1.0) / scale * long_scale,
+            ),
+            latitude: (
+                LAT_MIN + lat / scale * lat_scale,
+                LAT_MIN + (lat + 1.0) / scale * lat_scale,
+            ),
+        }
+    }
+
+    /// The center of the cell, as the longitude and the latitude.
+    pub fn decode(self) -> (f64, f64) {
+        let area = self.area();
+        let longitude = (area.longitude.0 + area.longitude.1) / 2.0;
+        let latitude = (area.latitude.0 + area.latitude.1) / 2.0;
+        (
+            longitude.clamp(LONG_MIN, LONG_MAX),
+            latitude.clamp(LAT_MIN, LAT_MAX),
+        )
+    }
+
+    /// The standard 11 characters geohash, for latitudes from -90 to 90 rather than the
+    /// ones of the Mercator projection.
+    pub fn to_base32(self) -> String {
+        let (longitude, latitude) = self.decode();
+        let standard = GeoHashBits::encode_in(longitude, latitude, (-90.0, 90.0), STEP_MAX);
+        (0..11)
+            .map(|i| {
+                let index = match i {
+                    10 => 0,
+                    _ => (standard.bits >> (52 - (i + 1) * 5)) & 0x1f,
+                };
+                BASE32[index as usize] as char
+            })
+            .collect()
+    }
+
+    /// The neighbouring cell, `dx` cells to the east and `dy` cells to the north.
+    fn neighbor(self, dx: i8, dy: i8) -> GeoHashBits {
+        let shift = 64 - self.step * 2;
+        let mv = |bits: u64, mask: u64, d: i8| {
+            let (own, other) = (bits & mask, bits & !mask);
+            let zz = !mask >> shift;
+            let own = match d {
+                0 => return bits,
+                d if d > 0 => own.wrapping_add(zz + 1),
+                _ => (own | zz).wrapping_sub(zz + 1),
+            };
+            (own & (mask >> shift)) | other
+        };
+        let bits = mv(self.bits, 0xaaaa_aaaa_aaaa_aaaa, dx);
+        GeoHashBits {
+            bits: mv(bits, 0x5555_5555_5555_5555, dy),
+            step: self.step,
+        }
+    }
+
+    /// The scores of the members in the cell, from inclusive to exclusive.
+    pub fn score_range(self) -> (f64, f64) {
+        let shift = (STEP_MAX - self.step) * 2;
+        (
+            (self.bits << shift) as f64,
+            ((self.bits + 1) << shift) as f64,
+        )
+    }
+}
+
+/// The distance between two positions in meters, using the haversine formula.
+pub fn distance(long1: f64, lat1: f64, long2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let v = ((long2.to_radians() - long1.to_radians()) / 2.0).sin();
+    if v == 0.0 {
+        return EARTH_RADIUS * (lat2 - lat1).abs();
+    }
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let a = u * u + lat1.cos() * lat2.cos() * v * v;
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+impl GeoShape {
+    /// The distance from the center to a position, `None` if it is not in the shape.
+    pub fn distance(self, center: (f64, f64), position: (f64, f64)) -> Option<f64> {
+        let (long1, lat1) = center;
+        let (long2, lat2) = position;
+        match self {
+            GeoShape::Radius(radius) => {
+                Some(distance(long1, lat1, long2, lat2)).filter(|x| *x <= radius)
+            }
+            GeoShape::Box { width, height } => {
+                if EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs() > height / 2.0
+                    || distance(long2, lat2, long1, lat2) > width / 2.0
+                {
+                    return None;
+                }
+                Some(distance(long1, lat1, long2, lat2))
+            }
+        }
+    }
+
+    /// The longitudes and latitudes bounding the shape around `center`.
+    fn bounding_box(self, center: (f64, f64)) -> GeoArea {
+        let (longitude, latitude) = center;
+        let (width, height) = match self {
+            GeoShape::Radius(radius) => (radius, radius),
+            GeoShape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+        let lat_delta = (height / EARTH_RADIUS).to_degrees();
+        let long_delta =
+            |latitude: f64| (width / EARTH_RADIUS / latitude.to_radians().cos()).to_degrees();
+        // the longitudes spread the most on the side closer to a pole
+        let long_delta = match latitude < 0.0 {
+            true => long_delta(latitude - lat_delta),
+            false => long_delta(latitude + lat_delta),
+        };
+        GeoArea {
+            longitude: (longitude - long_delta, longitude + long_delta),
+            latitude: (latitude - lat_delta, latitude + lat_delta),
+        }
+    }
+
+    /// The cells to look for members of the shape in: the one of the center and its
+    /// neighbours, big enough to cover the shape, leaving out the ones it does not reach.
+    pub fn search_areas(self, center: (f64, f64)) -> Vec<GeoHashBits> {
+        let (longitude, latitude) = center;
+        let bounds = self.bounding_box(center);
+        let radius = match self {
+            GeoShape::Radius(radius) => radius,
+            GeoShape::Box { width, height } => (width / 2.0).hypot(height / 2.0),
+        };
+
+        let mut step = estimate_step(radius, latitude);
+        let neighbors = |hash: GeoHashBits| {
+            [
+                (0, 0),
+                (0, 1),
+                (0, -1),
+                (1, 0),
+                (-1, 0),
+                (1, 1),
+                (-1, 1),
+                (1, -1),
+                (-1, -1),
+            ]
+            .iter()
+            .map(|(dx, dy)| ((*dx, *dy), hash.neighbor(*dx, *dy)))
+            .collect::<Vec<_>>()
+        };
+        let mut hash = GeoHashBits::encode(longitude, latitude, step);
+        let (north, south) = (hash.neighbor(0, 1).area(), hash.neighbor(0, -1).area());
+        let (east, west) = (hash.neighbor(1, 0).area(), hash.neighbor(-1, 0).area());
+        // the neighbours may fall short of the shape at the edges of the cell
+        if step > 1
+            && (north.latitude.1 < bounds.latitude.1
+                || south.latitude.0 > bounds.latitude.0
+                || east.longitude.1 < bounds.longitude.1
+                || west.longitude.0 > bounds.longitude.0)
+        {
+            step -= 1;
+            hash = GeoHashBits::encode(longitude, latitude, step);
+        }
+
+        let area = hash.area();
+        let mut areas: Vec<GeoHashBits> = vec![];
+        for ((dx, dy), neighbor) in neighbors(hash) {
+            let useless = step >= 2
+                && ((dy < 0 && area.latitude.0 < bounds.latitude.0)
+                    || (dy > 0 && area.latitude.1 > bounds.latitude.1)
+                    || (dx < 0 && area.longitude.0 < bounds.longitude.0)
+                    || (dx > 0 && area.longitude.1 > bounds.longitude.1));
+            // huge shapes can make neighbours the same cell
+            if !useless && !areas.contains(&neighbor) {
+                areas.push(neighbor);
+            }
+        }
+        areas
+    }
+}
+
+/// The greatest step whose cells, with their neighbours, cover `radius` meters.
+fn estimate_step(radius: f64, latitude: f64) -> u32 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+    let (mut range, mut step) = (radius, 1i32);
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    // make sure the radius is covered in most cases
+    step -= 2;
+    // cells get narrower toward the poles
+    if !(-66.0..=66.0).contains(&latitude) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&latitude) {
+            step -= 1;
+        }
+    }
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{distance, GeoHashBits, GeoShape, STEP_MAX};
+
+    #[test]
+    fn test_geohash() {
+        let palermo = GeoHashBits::encode(13.361389, 38.115556, STEP_MAX);
+        assert_eq!(palermo.bits, 3_479_099_956_230_698);
+        assert_eq!(palermo.to_base32(), "sqc8b49rny0");
+        let (longitude, latitude) = palermo.decode();
+        assert!((longitude - 13.361_389_338_970_184).abs() < 1e-12);
+        assert!((latitude - 38.115_556_395_496_3).abs() < 1e-12);
+
+        let catania = GeoHashBits::encode(15.087269, 37.502669, STEP_MAX);
+        assert_eq!(catania.bits, 3_479_447_370_796_909);
+        assert_eq!(catania.to_base32(), "sqdtr74hyu0");
+        let distance = distance(longitude, latitude, 15.087269, 37.502669);
+        assert!((distance - 166_274.15).abs() < 1.0, "{}", distance);
+
+        // every cell of the search covers part of the shape, and together they hold the
+        // positions in it
+        let shape = GeoShape::Radius(200_000.0);
+        let areas = shape.search_areas((15.0, 37.0));
+        assert!(!areas.is_empty() && areas.len() <= 9);
+        for hash in &[palermo, catania] {
+            assert!(areas.iter().any(|area| {
+                let (min, max) = area.score_range();
+                hash.score() >= min && hash.score() < max
+            }));
+        }
+    }
+}
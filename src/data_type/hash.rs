@@ -0,0 +1,116 @@
+use super::Table;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+use tokio::time::Instant;
+
+/// The value of a hash: fields mapped to values, each field with an optional deadline.
+///
+/// Expired fields are only dropped by [`Hash::expire`], which the keyspace calls whenever
+/// the hash is looked up, so that the hash never shows them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: Table<Bytes>,
+    deadlines: HashMap<Bytes, Instant>,
+    /// The fields with a deadline, soonest first
+    by_deadline: BTreeSet<(Instant, Bytes)>,
+}
+
+impl Hash {
+    pub fn new() -> Hash {
+        Hash::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields.get(field)
+    }
+
+    /// Mutable access to a value, keeping the deadline of its field.
+    pub fn get_mut(&mut self, field: &[u8]) -> Option<&mut Bytes> {
+        self.fields.get_mut(field)
+    }
+
+    pub fn contains_key(&self, field: &[u8]) -> bool {
+        self.fields.contains_key(field)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        self.fields.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        self.fields.keys()
+    }
+
+    /// A field and its value, picked at random.
+    pub fn random(&self) -> Option<(&Bytes, &Bytes)> {
+        self.fields.random()
+    }
+
+    /// A page of fields and their values, see [`Table::scan`].
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&Bytes, &Bytes)>) {
+        self.fields.scan(cursor, count)
+    }
+
+    /// Sets a field, returning the value it replaced. Like a write to a key, this drops
+    /// the deadline of the field.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        self.set_deadline(&field, None);
+        self.fields.insert(field, value)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        self.set_deadline(field, None);
+        self.fields.remove(field)
+    }
+
+    pub fn deadline(&self, field: &[u8]) -> Option<Instant> {
+        self.deadlines.get(field).copied()
+    }
+
+    /// Sets or clears the deadline of a field, returning whether the field exists.
+    pub fn set_deadline(&mut self, field: &[u8], deadline: Option<Instant>) -> bool {
+        let (field, exists) = match self.fields.get_key_value(field) {
+            Some((field, _)) => (field.clone(), true),
+            None => (Bytes::copy_from_slice(field), false),
+        };
+        if let Some(old) = self.deadlines.remove(&field) {
+            self.by_deadline.remove(&(old, field.clone()));
+        }
+        if let (true, Some(deadline)) = (exists, deadline) {
+            self.deadlines.insert(field.clone(), deadline);
+            self.by_deadline.insert((deadline, field));
+        }
+        exists
+    }
+
+    /// The soonest deadline of a field, if any has one.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.by_deadline
+            .iter()
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+
+    /// Removes the fields expired by `now`, returning how many there were.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let mut expired = 0;
+        while let Some((deadline, field)) = self.by_deadline.iter().next().cloned() {
+            if now <= deadline {
+                break;
+            }
+            self.by_deadline.remove(&(deadline, field.clone()));
+            self.deadlines.remove(&field);
+            self.fields.remove(&field);
+            expired += 1;
+        }
+        expired
+    }
+}
@@ -0,0 +1,356 @@
+//! HyperLogLog in the same binary format as redis, so the strings it is kept in can be
+//! moved between the two.
+//!
+//! A 16 bytes header, `HYLL`, the encoding, 3 unused bytes and the cached cardinality
+//! in little endian, whose most significant bit marks it stale, comes before the
+//! registers. Dense registers are 6 bits each, least significant bits first. Sparse
+//! ones are run-length encoded with three opcodes:
+//!
+//! * `00xxxxxx`: `xxxxxx + 1` registers set to 0
+//! * `01xxxxxx yyyyyyyy`: `xxxxxxyyyyyyyy + 1` registers set to 0
+//! * `1vvvvvxx`: `xx + 1` registers set to `vvvvv + 1`
+
+const MAGIC: &[u8] = b"HYLL";
+const HEADER_LEN: usize = 16;
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// Bits of the hash that pick the register.
+const P: u32 = 14;
+/// Bits of the hash left to count the zeros of.
+const Q: u32 = 64 - P;
+const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const DENSE_LEN: usize = HEADER_LEN + REGISTERS * REGISTER_BITS / 8;
+
+const SPARSE_VAL_MAX_VALUE: u8 = 32;
+const SPARSE_VAL_MAX_LEN: usize = 4;
+const SPARSE_ZERO_MAX_LEN: usize = 64;
+const SPARSE_XZERO_MAX_LEN: usize = 16384;
+/// Same as `hll-sparse-max-bytes`, past which the sparse encoding gets promoted.
+const SPARSE_MAX_BYTES: usize = 3000;
+
+const SEED: u64 = 0xadc8_3b19;
+
+/// A HyperLogLog with its registers unpacked, one per byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+    dense: bool,
+    /// The cardinality as last counted, `None` once stale
+    cached: Option<u64>,
+}
+
+impl HyperLogLog {
+    pub fn new() -> HyperLogLog {
+        HyperLogLog {
+            registers: vec![0; REGISTERS],
+            dense: false,
+            cached: Some(0),
+        }
+    }
+
+    /// Decodes a HyperLogLog string, `None` if it is not a valid one.
+    pub fn from_bytes(bytes: &[u8]) -> Option<HyperLogLog> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return None;
+        }
+        let mut card = [0; 8];
+        card.copy_from_slice(&bytes[8..HEADER_LEN]);
+        let cached = match card[7] & 0x80 {
+            0 => Some(u64::from_le_bytes(card)),
+            _ => None,
+        };
+        let data = &bytes[HEADER_LEN..];
+        let (registers, dense) = match bytes[4] {
+            DENSE if bytes.len() == DENSE_LEN => (decode_dense(data), true),
+            SPARSE => (decode_sparse(data)?, false),
+            _ => return None,
+        };
+        Some(HyperLogLog {
+            registers,
+            dense,
+            cached,
+        })
+    }
+
+    /// Encodes the HyperLogLog, sparse for as long as it fits.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let sparse = match self.dense {
+            true => None,
+            false => encode_sparse(&self.registers),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(if sparse.is_some() { SPARSE } else { DENSE });
+        bytes.extend_from_slice(&[0; 3]);
+        match self.cached {
+            Some(card) => bytes.extend_from_slice(&card.to_le_bytes()),
+            None => bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x80]),
+        }
+        match sparse {
+            Some(data) => bytes.extend(data),
+            None => bytes.extend(encode_dense(&self.registers)),
+        }
+        bytes
+    }
+
+    pub fn is_dense(&self) -> bool {
+        self.dense
+    }
+
+    /// Adds an element, returning whether any register changed.
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let (index, count) = pattern(element);
+        if self.registers[index] >= count {
+            return false;
+        }
+        self.registers[index] = count;
+        if count > SPARSE_VAL_MAX_VALUE {
+            self.dense = true;
+        }
+        self.cached = None;
+        true
+    }
+
+    /// Merges in the registers of `other`, so that it counts the union of both.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        self.dense |= other.dense;
+        self.cached = None;
+    }
+
+    /// The estimated cardinality, from the cache if it is not stale.
+    pub fn count(&mut self) -> u64 {
+        if let Some(card) = self.cached {
+            return card;
+        }
+        let card = estimate(&self.registers);
+        self.cached = Some(card);
+        card
+    }
+
+    /// Whether the cached cardinality is still valid.
+    pub fn is_cached(&self) -> bool {
+        self.cached.is_some()
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> HyperLogLog {
+        HyperLogLog::new()
+    }
+}
+
+/// MurmurHash2, 64-bit version by Austin Appleby, reading blocks in little endian like
+/// redis does on every platform.
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut blocks = key.chunks_exact(8);
+    for block in &mut blocks {
+        let mut block_bytes = [0; 8];
+        block_bytes.copy_from_slice(block);
+        let mut k = u64::from_le_bytes(block_bytes);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// The register of an element, and the length of the run of zeros in its hash plus one.
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, SEED);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    // the sentinel bit keeps the count within Q + 1
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn decode_dense(data: &[u8]) -> Vec<u8> {
+    (0..REGISTERS)
+        .map(|i| {
+            let (byte, bit) = (i * REGISTER_BITS / 8, i * REGISTER_BITS % 8);
+            let low = data[byte] as u16 >> bit;
+            let high = data.get(byte + 1).map_or(0, |x| (*x as u16) << (8 - bit));
+            (low | high) as u8 & REGISTER_MAX
+        })
+        .collect()
+}
+
+fn encode_dense(registers: &[u8]) -> Vec<u8> {
+    let mut data = vec![0u8; DENSE_LEN - HEADER_LEN];
+    for (i, register) in registers.iter().enumerate() {
+        let (byte, bit) = (i * REGISTER_BITS / 8, i * REGISTER_BITS % 8);
+        let value = (*register as u16) << bit;
+        data[byte] |= value as u8;
+        if let Some(next) = data.get_mut(byte + 1) {
+            *next |= (value >> 8) as u8;
+        }
+    }
+    data
+}
+
+fn decode_sparse(data: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut data = data.iter();
+    while let Some(opcode) = data.next() {
+        let (value, len) = match opcode >> 6 {
+            0b00 => (0, (opcode & 0x3f) as usize + 1),
+            0b01 => (
+                0,
+                (((opcode & 0x3f) as usize) << 8 | *data.next()? as usize) + 1,
+            ),
+            _ => (((opcode >> 2) & 0x1f) + 1, (opcode & 0x03) as usize + 1),
+        };
+        if registers.len() + len > REGISTERS {
+            return None;
+        }
+        registers.resize(registers.len() + len, value);
+    }
+    match registers.len() {
+        REGISTERS => Some(registers),
+        _ => None,
+    }
+}
+
+/// Encodes the registers as sparse, `None` if they need the dense encoding.
+fn encode_sparse(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut data = vec![];
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|x| **x == value).count();
+        i += run;
+        if value > SPARSE_VAL_MAX_VALUE {
+            return None;
+        }
+        let mut left = run;
+        while left > 0 {
+            let len = match value {
+                0 if left > SPARSE_ZERO_MAX_LEN => {
+                    let len = left.min(SPARSE_XZERO_MAX_LEN);
+                    data.push(0x40 | ((len - 1) >> 8) as u8);
+                    data.push((len - 1) as u8);
+                    len
+                }
+                0 => {
+                    data.push((left - 1) as u8);
+                    left
+                }
+                _ => {
+                    let len = left.min(SPARSE_VAL_MAX_LEN);
+                    data.push(0x80 | (value - 1) << 2 | (len - 1) as u8);
+                    len
+                }
+            };
+            left -= len;
+        }
+        if HEADER_LEN + data.len() > SPARSE_MAX_BYTES {
+            return None;
+        }
+    }
+    Some(data)
+}
+
+/// The cardinality estimate of redis, from "New cardinality estimation algorithms for
+/// HyperLogLog sketches" by Otmar Ertl.
+fn estimate(registers: &[u8]) -> u64 {
+    let m = REGISTERS as f64;
+    let mut histogram = [0u32; Q as usize + 2];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for count in histogram[1..=Q as usize].iter().rev() {
+        z += *count as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    let alpha_inf = 0.5 / std::f64::consts::LN_2;
+    (alpha_inf * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HyperLogLog;
+
+    #[test]
+    fn test_encoding() {
+        let mut hll = HyperLogLog::new();
+        // every register in a single XZERO opcode
+        assert_eq!(
+            hll.to_bytes(),
+            b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        );
+        assert!(hll.add(b"a"));
+        assert!(!hll.add(b"a"));
+        assert!(!hll.is_cached());
+        let decoded = HyperLogLog::from_bytes(&hll.to_bytes()).expect("a sparse string");
+        assert_eq!(decoded, hll);
+
+        for i in 0..10_000 {
+            hll.add(i.to_string().as_bytes());
+        }
+        assert!(hll.to_bytes().len() > 12_000);
+        let mut decoded = HyperLogLog::from_bytes(&hll.to_bytes()).expect("a dense string");
+        assert!(decoded.is_dense());
+        let count = decoded.count();
+        assert!((9_800..=10_200).contains(&count), "{}", count);
+
+        assert_eq!(HyperLogLog::from_bytes(b"HYLL"), None);
+        assert_eq!(
+            HyperLogLog::from_bytes(b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f"),
+            None
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use super::Table;
+use crate::util;
+use bytes::Bytes;
+
+/// Sets of integers are kept as an intset up to this many members.
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// The value of a set, encoded like redis does: a sorted array of integers while all the
+/// members are integers and there are few of them, a hash table otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Set {
+    Ints(Vec<i64>),
+    Members(Table<()>),
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set::Ints(vec![])
+    }
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    /// The name `OBJECT ENCODING` would reply with.
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::Ints(_) => "intset",
+            Set::Members(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::Ints(ints) => ints.len(),
+            Set::Members(members) => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::Ints(ints) => {
+                util::parse_integer(member).is_some_and(|x| ints.binary_search(&x).is_ok())
+            }
+            Set::Members(members) => members.contains_key(member),
+        }
+    }
+
+    /// Adds a member, returning whether it was not there already.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Set::Ints(ints) = self {
+            if let Some(n) = util::parse_integer(&member) {
+                match ints.binary_search(&n) {
+                    Ok(_) => return false,
+                    Err(_) if ints.len() >= MAX_INTSET_ENTRIES => {}
+                    Err(index) => {
+                        ints.insert(index, n);
+                        return true;
+                    }
+                }
+            }
+            self.convert();
+        }
+        match self {
+            Set::Members(members) => members.insert(member, ()).is_none(),
+            Set::Ints(_) => unreachable!("converted above"),
+        }
+    }
+
+    /// Removes a member, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::Ints(ints) => match util::parse_integer(member).map(|x| ints.binary_search(&x)) {
+                Some(Ok(index)) => {
+                    ints.remove(index);
+                    true
+                }
+                _ => false,
+            },
+            Set::Members(members) => members.remove(member).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match self {
+            Set::Ints(ints) => Box::new(ints.iter().map(|x| Bytes::from(x.to_string()))),
+            Set::Members(members) => Box::new(members.keys().cloned()),
+        }
+    }
+
+    /// A member picked at random.
+    pub fn random(&self) -> Option<Bytes> {
+        if self.is_empty() {
+            return None;
+        }
+        match self {
+            Set::Ints(ints) => Some(
+                ints[util::random() as usize % ints.len()]
+                    .to_string()
+                    .into(),
+            ),
+            Set::Members(members) => members.random().map(|(member, _)| member.clone()),
+        }
+    }
+
+    /// A page of members, see [`Table::scan`]. An intset is small enough to go through
+    /// in one go, like redis does.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        match self {
+            Set::Ints(_) => (0, self.iter().collect()),
+            Set::Members(members) => {
+                let (cursor, page) = members.scan(cursor, count);
+                (cursor, page.into_iter().map(|(x, _)| x.clone()).collect())
+            }
+        }
+    }
+
+    /// Removes a member picked at random.
+    pub fn pop(&mut self) -> Option<Bytes> {
+        let member = self.random()?;
+        self.remove(&member);
+        Some(member)
+    }
+
+    /// Switches to the hash table encoding, for members that do not fit an intset.
+    fn convert(&mut self) {
+        if let Set::Ints(ints) = self {
+            let mut members = Table::new();
+            for n in ints.iter() {
+                members.insert(n.to_string().into(), ());
+            }
+            *self = Set::Members(members);
+        }
+    }
+}
+
+impl std::iter::FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Set {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Set, MAX_INTSET_ENTRIES};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_encoding() {
+        let mut set: Set = ["3", "1", "2", "-5"]
+            .iter()
+            .map(|x| Bytes::from(*x))
+            .collect();
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set, Set::Ints(vec![-5, 1, 2, 3]));
+        assert!(set.contains(b"2"));
+        // only the canonical form of a number is a member
+        assert!(!set.contains(b"02"));
+        assert!(set.insert("007".into()));
+        assert_eq!(set.encoding(), "hashtable");
+        assert!(set.contains(b"2") && set.contains(b"007"));
+        assert_eq!(set.len(), 5);
+
+        let mut set: Set = (0..MAX_INTSET_ENTRIES)
+            .map(|x| Bytes::from(x.to_string()))
+            .collect();
+        assert_eq!(set.encoding(), "intset");
+        assert!(!set.insert("0".into()));
+        assert!(set.insert("-1".into()));
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), MAX_INTSET_ENTRIES + 1);
+    }
+
+    #[test]
+    fn test_remove_and_random() {
+        let mut set: Set = ["a", "b", "c", "d"]
+            .iter()
+            .map(|x| Bytes::from(*x))
+            .collect();
+        assert!(set.remove(b"a"));
+        assert!(!set.remove(b"a"));
+        assert!(set.remove(b"d"));
+        assert_eq!(set, ["c", "b"].iter().map(|x| Bytes::from(*x)).collect());
+        for _ in 0..20 {
+            let member = set.random().expect("a member");
+            assert!(member == "b" || member == "c");
+        }
+        while let Some(member) = set.pop() {
+            assert!(!set.contains(&member));
+        }
+        assert!(set.is_empty());
+        assert_eq!(set.random(), None);
+    }
+}
@@ -0,0 +1,456 @@
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::RangeBounds,
+};
+
+/// The field-value pairs of a stream entry, in the order they were added.
+pub type Fields = Vec<(Bytes, Bytes)>;
+
+/// The ID of a stream entry: a millisecond timestamp and a sequence number among the
+/// entries of the same millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` with `missing_seq` as its sequence number.
+    pub fn parse(input: &[u8], missing_seq: u64) -> Option<StreamId> {
+        let parse = |x: &[u8]| -> Option<u64> {
+            if x.is_empty() || !x.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            std::str::from_utf8(x).ok()?.parse().ok()
+        };
+        match input.iter().position(|x| *x == b'-') {
+            Some(i) => Some(StreamId::new(parse(&input[..i])?, parse(&input[i + 1..])?)),
+            None => Some(StreamId::new(parse(input)?, missing_seq)),
+        }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The greatest ID smaller than this one.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// An entry delivered to a consumer and not acknowledged yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingEntry {
+    pub consumer: Bytes,
+    /// When the entry was last delivered, as a unix timestamp in milliseconds
+    pub delivery_time: i64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consumer {
+    /// When the consumer last tried to read or claim, as a unix timestamp in milliseconds
+    pub seen_time: i64,
+    /// When the consumer last read or claimed an entry, `None` if it never did
+    pub active_time: Option<i64>,
+    /// The IDs of its pending entries
+    pub pending: BTreeSet<StreamId>,
+}
+
+/// A consumer group of a stream: how far it read, and which of its consumers has which
+/// entry pending.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumerGroup {
+    /// The ID of the last entry delivered to the group
+    pub last_id: StreamId,
+    /// How many entries of the stream the group read, `None` when it cannot tell
+    pub entries_read: Option<u64>,
+    /// The pending entries list of the group, or PEL
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Bytes, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId, entries_read: Option<u64>) -> ConsumerGroup {
+        ConsumerGroup {
+            last_id,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    /// Looks up a consumer, creating it if needed, and notes that it was seen at `now`.
+    pub fn consumer(&mut self, name: &Bytes, now: i64) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.clone())
+            .or_insert_with(|| Consumer {
+                seen_time: now,
+                active_time: None,
+                pending: BTreeSet::new(),
+            });
+        consumer.seen_time = now;
+        consumer
+    }
+
+    /// Creates a consumer, returning whether it did not exist already.
+    pub fn create_consumer(&mut self, name: &Bytes, now: i64) -> bool {
+        let created = !self.consumers.contains_key(name);
+        self.consumer(name, now);
+        created
+    }
+
+    /// Deletes a consumer along with its pending entries, returning how many it had.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Gives a pending entry to `consumer`, creating it if needed, with its delivery
+    /// time set to `delivery_time`.
+    pub fn assign(&mut self, id: StreamId, consumer: &Bytes, delivery_time: i64, now: i64) {
+        let pending = self.pending.entry(id).or_insert_with(|| PendingEntry {
+            consumer: consumer.clone(),
+            delivery_time,
+            delivery_count: 0,
+        });
+        let previous = std::mem::replace(&mut pending.consumer, consumer.clone());
+        pending.delivery_time = delivery_time;
+        if let Some(previous) = self.consumers.get_mut(&previous) {
+            previous.pending.remove(&id);
+        }
+        let consumer = self.consumer(consumer, now);
+        consumer.active_time = Some(now);
+        consumer.pending.insert(id);
+    }
+
+    /// Acknowledges a pending entry, returning whether it was pending.
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(pending) => {
+                if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+                    consumer.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// The value of a stream: an append-only log of entries ordered by ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    groups: BTreeMap<Bytes, ConsumerGroup>,
+    /// The ID of the last entry ever added, which new IDs must be greater than even once
+    /// that entry is deleted
+    last_id: StreamId,
+    /// The greatest ID deleted with `XDEL`
+    max_deleted_id: StreamId,
+    /// The number of entries ever added
+    entries_added: u64,
+}
+
+impl Stream {
+    pub fn new() -> Stream {
+        Stream::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    /// The ID for an entry added at `unix_millis`, `None` once every ID is used up. IDs
+    /// keep growing even if the clock goes backwards.
+    pub fn next_id(&self, unix_millis: u64) -> Option<StreamId> {
+        Stream::next_id_after(self.last_id, unix_millis)
+    }
+
+    /// The ID for an entry added at `unix_millis` to a stream whose last ID is
+    /// `last_id`.
+    pub fn next_id_after(last_id: StreamId, unix_millis: u64) -> Option<StreamId> {
+        if unix_millis > last_id.ms {
+            Some(StreamId::new(unix_millis, 0))
+        } else {
+            last_id.next()
+        }
+    }
+
+    /// Appends an entry, returning whether its ID was greater than the last one. The
+    /// entry is not added otherwise.
+    pub fn insert(&mut self, id: StreamId, fields: Fields) -> bool {
+        if id <= self.last_id {
+            return false;
+        }
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+        true
+    }
+
+    /// Deletes an entry, returning whether it was there.
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Fields> {
+        self.entries.get(&id)
+    }
+
+    /// The entries within `range`, iterable from either end.
+    pub fn range<R: RangeBounds<StreamId>>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (StreamId, &Fields)> {
+        self.entries.range(range).map(|(id, fields)| (*id, fields))
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.range(..).next()
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.range(..).next_back()
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Bytes, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Adds a consumer group, returning whether there was none by that name.
+    pub fn create_group(&mut self, name: Bytes, group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Whether an entry from `id` on was deleted, which makes counting the entries
+    /// after `id` impossible without walking them.
+    fn has_tombstones_from(&self, id: StreamId) -> bool {
+        !self.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && self.max_deleted_id >= id
+            && self.max_deleted_id <= self.last_id
+    }
+
+    /// How many entries were added up to and including `id`, when it can be told
+    /// without walking the stream, following `streamEstimateDistanceFromFirstEverEntry`.
+    pub fn entries_read_until(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if self.is_empty() && id >= self.last_id {
+            return Some(self.entries_added);
+        }
+        if id == self.last_id {
+            return Some(self.entries_added);
+        } else if id > self.last_id {
+            return None;
+        }
+        let first_id = self.first_entry().map_or(StreamId::MIN, |(id, _)| id);
+        // without deletions inside the stream, the entries before the first one are
+        // all the ones trimmed
+        if self.max_deleted_id == StreamId::MIN || self.max_deleted_id < first_id {
+            let trimmed = self.entries_added - self.len() as u64;
+            if id < first_id {
+                return Some(trimmed);
+            } else if id == first_id {
+                return Some(trimmed + 1);
+            }
+        }
+        None
+    }
+
+    /// How many entries the group has yet to read, `None` when it cannot be told.
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let entries_read = match group.entries_read {
+            Some(read) if !self.has_tombstones_from(group.last_id) => read,
+            _ => self.entries_read_until(group.last_id)?,
+        };
+        Some(self.entries_added.saturating_sub(entries_read))
+    }
+
+    /// Delivers up to `count` entries after the last one delivered to a group, making
+    /// them pending for `consumer` unless `no_ack`. Returns the IDs delivered.
+    pub fn deliver(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        no_ack: bool,
+        now: i64,
+    ) -> Vec<StreamId> {
+        let last_id = match self.groups.get(group) {
+            Some(group) => group.last_id,
+            None => return vec![],
+        };
+        let ids = self
+            .entries
+            .range(last_id..)
+            .map(|(id, _)| *id)
+            .filter(|id| *id > last_id)
+            .take(count)
+            .collect::<Vec<_>>();
+        for id in &ids {
+            let has_tombstones = self.has_tombstones_from(*id);
+            let entries_read = self.entries_read_until(*id);
+            let group = self.groups.get_mut(group).expect("the group exists");
+            group.entries_read = match group.entries_read {
+                Some(read) if !has_tombstones => Some(read + 1),
+                _ => entries_read,
+            };
+            group.last_id = *id;
+            if !no_ack {
+                group.assign(*id, consumer, now, now);
+                let pending = group.pending.get_mut(id).expect("the entry is pending");
+                pending.delivery_count = 1;
+            }
+        }
+        let group = self.groups.get_mut(group).expect("the group exists");
+        let consumer = group.consumer(consumer, now);
+        if !ids.is_empty() {
+            consumer.active_time = Some(now);
+        }
+        ids
+    }
+
+    /// Deletes the oldest entries while `trim` holds for them, at most `limit` of them,
+    /// returning how many were deleted.
+    pub fn trim(&mut self, limit: usize, mut trim: impl FnMut(&Stream, StreamId) -> bool) -> usize {
+        let mut removed = 0;
+        while let Some((id, _)) = self.first_entry() {
+            if removed == limit || !trim(self, id) {
+                break;
+            }
+            self.entries.remove(&id);
+            removed += 1;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Stream, StreamId};
+
+    #[test]
+    fn test_ids() {
+        let mut stream = Stream::new();
+        assert_eq!(stream.next_id(5), Some(StreamId::new(5, 0)));
+        assert!(!stream.insert(StreamId::MIN, vec![]));
+        assert!(stream.insert(StreamId::new(5, 0), vec![]));
+        assert!(!stream.insert(StreamId::new(5, 0), vec![]));
+        // the clock going backwards does not go back on IDs
+        assert_eq!(stream.next_id(3), Some(StreamId::new(5, 1)));
+        assert_eq!(stream.next_id(6), Some(StreamId::new(6, 0)));
+
+        assert!(stream.insert(StreamId::new(7, 2), vec![]));
+        assert!(stream.remove(StreamId::new(7, 2)));
+        assert_eq!(stream.last_id(), StreamId::new(7, 2));
+        assert_eq!(stream.max_deleted_id(), StreamId::new(7, 2));
+        assert!(!stream.insert(StreamId::new(7, 1), vec![]));
+        assert_eq!(stream.entries_added(), 2);
+
+        assert!(stream.insert(StreamId::MAX, vec![]));
+        assert_eq!(stream.next_id(u64::MAX), None);
+
+        assert_eq!(StreamId::parse(b"12-3", 0), Some(StreamId::new(12, 3)));
+        assert_eq!(
+            StreamId::parse(b"12", u64::MAX),
+            Some(StreamId::new(12, u64::MAX))
+        );
+        assert_eq!(StreamId::parse(b"12-", 0), None);
+        assert_eq!(StreamId::parse(b"-1", 0), None);
+        assert_eq!(StreamId::new(3, 0).prev(), Some(StreamId::new(2, u64::MAX)));
+        assert_eq!(StreamId::MIN.prev(), None);
+        assert_eq!(StreamId::new(1, 2).to_string(), "1-2");
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut stream = Stream::new();
+        for ms in 1..=10 {
+            stream.insert(StreamId::new(ms, 0), vec![]);
+        }
+        assert_eq!(stream.trim(usize::MAX, |stream, _| stream.len() > 7), 3);
+        assert_eq!(stream.first_entry().unwrap().0, StreamId::new(4, 0));
+        assert_eq!(stream.trim(2, |_, id| id < StreamId::new(9, 0)), 2);
+        assert_eq!(stream.len(), 5);
+        assert_eq!(
+            stream
+                .range(StreamId::new(7, 0)..)
+                .rev()
+                .map(|(id, _)| id.ms)
+                .collect::<Vec<_>>(),
+            vec![10, 9, 8, 7]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::util;
+use bytes::Bytes;
+use std::{borrow::Borrow, collections::HashMap, hash::Hash};
+
+/// The hash table of hashes, sets and the keyspace: entries are kept in an array, with a
+/// map of where each name is in it.
+///
+/// Removing an entry moves the last one into its place, which gives constant time random
+/// picks and a cursor walking the array in a stable order, see [`Table::scan`].
+#[derive(Debug, Clone)]
+pub struct Table<V, K = Bytes> {
+    entries: Vec<(K, V)>,
+    /// Position of each name in `entries`
+    index: HashMap<K, usize>,
+}
+
+impl<V, K> Default for Table<V, K> {
+    fn default() -> Table<V, K> {
+        Table {
+            entries: vec![],
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<V, K: Hash + Eq + Clone> Table<V, K> {
+    pub fn new() -> Table<V, K> {
+        Table::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains_key<Q>(&self, name: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.index.contains_key(name)
+    }
+
+    pub fn get<Q>(&self, name: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(name).map(|(_, value)| value)
+    }
+
+    pub fn get_key_value<Q>(&self, name: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (name, value) = &self.entries[*self.index.get(name)?];
+        Some((name, value))
+    }
+
+    pub fn get_mut<Q>(&mut self, name: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = *self.index.get(name)?;
+        Some(&mut self.entries[index].1)
+    }
+
+    /// Sets the value of `name`, returning the one it replaced.
+    pub fn insert(&mut self, name: K, value: V) -> Option<V> {
+        if let Some(current) = self.get_mut(&name) {
+            return Some(std::mem::replace(current, value));
+        }
+        self.index.insert(name.clone(), self.entries.len());
+        self.entries.push((name, value));
+        None
+    }
+
+    pub fn remove<Q>(&mut self, name: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.index.remove(name)?;
+        let (_, value) = self.entries.swap_remove(index);
+        if let Some((moved, _)) = self.entries.get(index) {
+            self.index.insert(moved.clone(), index);
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(name, value)| (name, value))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index.clear();
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.iter().map(|(name, _)| name)
+    }
+
+    /// An entry picked at random.
+    pub fn random(&self) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let (name, value) = &self.entries[util::random() as usize % self.len()];
+        Some((name, value))
+    }
+
+    /// Pages through the entries with a cursor, the way `SCAN` and friends do: every
+    /// entry present for the whole scan is returned, some maybe more than once.
+    ///
+    /// The array is walked from its end, the cursor being how many entries are left to
+    /// go through, 0 to start. This holds up to changes in between calls, as entries are
+    /// only ever added at the end, and only moved down from there into the place of a
+    /// removed one. Returns the cursor for the next call, 0 once done, and the page.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(&K, &V)>) {
+        let end = match cursor {
+            0 => self.len(),
+            cursor => cursor.min(self.len() as u64) as usize,
+        };
+        let start = end.saturating_sub(count.max(1));
+        let page = self.entries[start..end]
+            .iter()
+            .rev()
+            .map(|(name, value)| (name, value))
+            .collect();
+        (start as u64, page)
+    }
+}
+
+impl<V: PartialEq, K: Hash + Eq + Clone> PartialEq for Table<V, K> {
+    fn eq(&self, other: &Table<V, K>) -> bool {
+        // the order of the entries is not part of the table
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(name, value)| other.get(name) == Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Table;
+    use bytes::Bytes;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut table = Table::new();
+        for (i, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            assert_eq!(table.insert(Bytes::from(*name), i), None);
+        }
+        assert_eq!(table.insert("b".into(), 10), Some(1));
+        assert_eq!(table.remove(&b"a"[..]), Some(0));
+        assert_eq!(table.remove(&b"a"[..]), None);
+        assert_eq!(table.get(&b"d"[..]), Some(&3));
+        assert_eq!(table.get(&b"b"[..]), Some(&10));
+        assert_eq!(table.len(), 3);
+
+        let mut other = Table::new();
+        for (name, value) in &[("c", 2), ("d", 3), ("b", 10)] {
+            other.insert(Bytes::from(*name), *value);
+        }
+        assert_eq!(table, other);
+    }
+
+    #[test]
+    fn test_scan_while_changing() {
+        let mut table = Table::new();
+        for i in 0..100 {
+            table.insert(Bytes::from(i.to_string()), ());
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut removed = 0;
+        loop {
+            let (next, page) = table.scan(cursor, 7);
+            seen.extend(page.into_iter().map(|(name, _)| name.clone()));
+            // entries come and go in between pages, some of them not seen yet
+            table.remove(removed.to_string().as_bytes());
+            table.insert(Bytes::from(format!("new:{}", removed)), ());
+            removed += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+        for i in removed..100 {
+            assert!(
+                seen.contains(i.to_string().as_bytes()),
+                "{} was not seen",
+                i
+            );
+        }
+    }
+}
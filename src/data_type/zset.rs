@@ -0,0 +1,493 @@
+use crate::util;
+use bytes::Bytes;
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Levels of the skiplist, enough for 4^32 members.
+const MAX_LEVEL: usize = 32;
+/// The chance for a node to make it to the next level.
+const LEVEL_PROBABILITY: f64 = 0.25;
+/// The head node, before every member.
+const HEAD: usize = 0;
+
+/// A range of scores, such as `(1 +inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: f64,
+    pub max: f64,
+    pub min_exclusive: bool,
+    pub max_exclusive: bool,
+}
+
+impl ScoreRange {
+    fn above_min(&self, score: f64) -> bool {
+        if self.min_exclusive {
+            score > self.min
+        } else {
+            score >= self.min
+        }
+    }
+
+    fn below_max(&self, score: f64) -> bool {
+        if self.max_exclusive {
+            score < self.max
+        } else {
+            score <= self.max
+        }
+    }
+}
+
+/// One end of a range of members, such as `[a` or `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    /// `-`, before every member
+    Min,
+    /// `+`, after every member
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// A range of members, for members all having the same score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl LexRange {
+    fn above_min(&self, member: &[u8]) -> bool {
+        match &self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(min) => member >= &min[..],
+            LexBound::Exclusive(min) => member > &min[..],
+        }
+    }
+
+    fn below_max(&self, member: &[u8]) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(max) => member <= &max[..],
+            LexBound::Exclusive(max) => member < &max[..],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Level {
+    forward: Option<usize>,
+    /// The number of nodes `forward` skips over, counting itself
+    span: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    /// Whether the node comes before `(score, member)`.
+    fn is_before(&self, score: f64, member: &[u8]) -> bool {
+        match self.score.partial_cmp(&score) {
+            Some(Ordering::Less) => true,
+            Some(Ordering::Equal) => &self.member[..] < member,
+            _ => false,
+        }
+    }
+}
+
+/// The skiplist of redis, ordering members by score then by member, with the spans of
+/// the links kept so that ranks can be found in O(log n). Nodes live in an arena and
+/// link to each other by index.
+#[derive(Debug, Clone, PartialEq)]
+struct SkipList {
+    nodes: Vec<Node>,
+    /// Slots of removed nodes, for reuse
+    free: Vec<usize>,
+    len: usize,
+    /// The number of levels in use
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> SkipList {
+        let head = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: vec![],
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+impl SkipList {
+    fn random_level() -> usize {
+        let mut level = 1;
+        while level < MAX_LEVEL
+            && ((util::random() & 0xffff) as f64) < LEVEL_PROBABILITY * 0xffff as f64
+        {
+            level += 1;
+        }
+        level
+    }
+
+    /// The last node at each level before `(score, member)`, along with its rank.
+    fn find_before(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i + 1 == self.level { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !self.nodes[next].is_before(score, member) {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Inserts a member, which must not be in the list already.
+    fn insert(&mut self, score: f64, member: Bytes) {
+        let (mut update, mut rank) = self.find_before(score, &member);
+        let level = SkipList::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            levels: vec![
+                Level {
+                    forward: None,
+                    span: 0,
+                };
+                level
+            ],
+        };
+        let x = match self.free.pop() {
+            Some(x) => {
+                self.nodes[x] = node;
+                x
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let before = self.nodes[update[i]].levels[i];
+            self.nodes[x].levels[i] = Level {
+                forward: before.forward,
+                span: before.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: Some(x),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &before) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[before].levels[i].span += 1;
+        }
+
+        self.len += 1;
+    }
+
+    /// Removes a member, returning whether it was in the list.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.find_before(score, member);
+        let x = match self.nodes[update[0]].levels[0].forward {
+            Some(x) if self.nodes[x].score == score && &self.nodes[x].member[..] == member => x,
+            _ => return false,
+        };
+
+        for (i, &before) in update.iter().enumerate().take(self.level) {
+            let removed = self.nodes[x].levels.get(i).copied();
+            let before = &mut self.nodes[before].levels[i];
+            match removed {
+                Some(removed) if before.forward == Some(x) => {
+                    before.span = before.span + removed.span - 1;
+                    before.forward = removed.forward;
+                }
+                _ => before.span -= 1,
+            }
+        }
+        while self.level > 1 && self.nodes[HEAD].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+
+        self.nodes[x].member = Bytes::new();
+        self.nodes[x].levels.clear();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// The node at a 1-based rank.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if traversed + self.nodes[x].levels[i].span > rank {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == rank {
+                return Some(x).filter(|x| *x != HEAD);
+            }
+        }
+        None
+    }
+
+    /// The 1-based rank of the first node matching `matches`, which has to hold for all
+    /// the nodes after one it holds for.
+    fn first_rank(&self, matches: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if matches(&self.nodes[next]) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        Some(rank + 1).filter(|rank| *rank <= self.len)
+    }
+
+    /// The 1-based rank of the last node matching `matches`, which has to hold for all
+    /// the nodes before one it holds for.
+    fn last_rank(&self, matches: impl Fn(&Node) -> bool) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                if !matches(&self.nodes[next]) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        Some(rank).filter(|rank| *rank > 0)
+    }
+}
+
+/// The value of a sorted set: members with a score, ordered by score then by member.
+/// Members map to their score for lookups, and are ordered in a skiplist for rank and
+/// range queries.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    list: SkipList,
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds a member or updates its score, returning whether it was not there already.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.get(&member[..]).copied() {
+            Some(current) if current == score => false,
+            Some(current) => {
+                self.list.remove(current, &member);
+                self.list.insert(score, member.clone());
+                self.scores.insert(member, score);
+                false
+            }
+            None => {
+                self.list.insert(score, member.clone());
+                self.scores.insert(member, score);
+                true
+            }
+        }
+    }
+
+    /// Removes a member, returning whether it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based rank of a member, lowest score first.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        let rank = self.list.last_rank(|node| {
+            node.is_before(score, member) || (node.score == score && &node.member[..] == member)
+        })?;
+        Some(rank - 1)
+    }
+
+    /// The members with 0-based ranks in `start..=stop`, lowest score first.
+    pub fn range(&self, start: usize, stop: usize) -> Vec<(Bytes, f64)> {
+        let mut members = vec![];
+        let mut x = match self.list.by_rank(start + 1) {
+            Some(x) => x,
+            None => return members,
+        };
+        for _ in start..=stop {
+            let node = &self.list.nodes[x];
+            members.push((node.member.clone(), node.score));
+            x = match node.levels[0].forward {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        members
+    }
+
+    /// The 0-based ranks of the first and last members with a score in `range`.
+    pub fn score_ranks(&self, range: &ScoreRange) -> Option<(usize, usize)> {
+        let first = self.list.first_rank(|node| range.above_min(node.score))?;
+        let last = self.list.last_rank(|node| range.below_max(node.score))?;
+        Some((first - 1, last - 1)).filter(|_| first <= last)
+    }
+
+    /// The 0-based ranks of the first and last members in `range`.
+    pub fn lex_ranks(&self, range: &LexRange) -> Option<(usize, usize)> {
+        let first = self.list.first_rank(|node| range.above_min(&node.member))?;
+        let last = self.list.last_rank(|node| range.below_max(&node.member))?;
+        Some((first - 1, last - 1)).filter(|_| first <= last)
+    }
+
+    /// All the members, lowest score first.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, f64)> {
+        let mut x = self.list.nodes[HEAD].levels[0].forward;
+        std::iter::from_fn(move || {
+            let node = &self.list.nodes[x?];
+            x = node.levels[0].forward;
+            Some((&node.member, node.score))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LexBound, LexRange, ScoreRange, SortedSet};
+    use bytes::Bytes;
+
+    fn members(range: Vec<(Bytes, f64)>) -> Vec<String> {
+        range
+            .into_iter()
+            .map(|(member, _)| String::from_utf8(member.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ranks_and_ranges() {
+        let mut zset = SortedSet::new();
+        // enough members for the list to grow a few levels
+        for i in 0..1000 {
+            assert!(zset.insert(format!("m{:04}", i).into(), (i / 2) as f64));
+        }
+        for i in (0..1000).step_by(3) {
+            assert!(zset.remove(format!("m{:04}", i).as_bytes()));
+        }
+        assert!(!zset.remove(b"m0000"));
+        assert!(!zset.insert("m0001".into(), 0.0));
+
+        let expected = (0..1000)
+            .filter(|i| i % 3 != 0)
+            .map(|i| format!("m{:04}", i))
+            .collect::<Vec<_>>();
+        assert_eq!(zset.len(), expected.len());
+        assert_eq!(members(zset.range(0, zset.len() - 1)), expected);
+        for (rank, member) in expected.iter().enumerate() {
+            assert_eq!(zset.rank(member.as_bytes()), Some(rank));
+        }
+        assert_eq!(zset.rank(b"m0000"), None);
+        assert_eq!(members(zset.range(10, 12)), expected[10..=12].to_vec());
+        assert!(zset.iter().map(|(_, score)| score).is_sorted());
+
+        // m0001 moved ahead of everything, m0002 now scores 1
+        assert!(!zset.insert("m0001".into(), -1.0));
+        assert_eq!(zset.rank(b"m0001"), Some(0));
+        assert_eq!(zset.rank(b"m0002"), Some(1));
+
+        let range = ScoreRange {
+            min: 1.0,
+            max: 2.0,
+            min_exclusive: false,
+            max_exclusive: true,
+        };
+        let (first, last) = zset.score_ranks(&range).unwrap();
+        assert_eq!(members(zset.range(first, last)), vec!["m0002"]);
+        let range = ScoreRange {
+            min: 1000.0,
+            max: f64::INFINITY,
+            min_exclusive: false,
+            max_exclusive: false,
+        };
+        assert_eq!(zset.score_ranks(&range), None);
+    }
+
+    #[test]
+    fn test_lex_ranges() {
+        let mut zset = SortedSet::new();
+        for member in &["a", "b", "c", "d", "e"] {
+            zset.insert(Bytes::from(*member), 0.0);
+        }
+        let range = |min, max| LexRange { min, max };
+        let ranks = zset.lex_ranks(&range(
+            LexBound::Exclusive("a".into()),
+            LexBound::Inclusive("c".into()),
+        ));
+        assert_eq!(ranks, Some((1, 2)));
+        assert_eq!(
+            zset.lex_ranks(&range(LexBound::Min, LexBound::Max)),
+            Some((0, 4))
+        );
+        assert_eq!(
+            zset.lex_ranks(&range(
+                LexBound::Inclusive("c".into()),
+                LexBound::Exclusive("c".into())
+            )),
+            None
+        );
+        assert_eq!(zset.lex_ranks(&range(LexBound::Max, LexBound::Min)), None);
+    }
+}
@@ -0,0 +1,510 @@
+use crate::{
+    data_type::{Key, RedisDataType, RedisDataTypeWithTTL, Table},
+    util,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::Instant;
+
+mod blocking;
+mod watching;
+
+/// Keys sampled per round of the active expiry cycle.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// The active expiry cycle keeps going while more than this percentage of the sampled
+/// keys turn out to be expired.
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
+/// The time budget is only checked every this many rounds, the clock being slow to read.
+const ACTIVE_EXPIRE_ROUNDS_PER_TIME_CHECK: u64 = 16;
+
+/// Counters on expired keys, reported by `INFO`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ExpiryStats {
+    /// Keys removed because they expired, lazily or by the active cycle
+    pub expired_keys: u64,
+    /// Hash fields removed because they expired
+    pub expired_subkeys: u64,
+    /// Estimated percentage of the keys with a deadline that are expired, as of the
+    /// last active cycle
+    pub expired_stale_perc: f64,
+    /// Active cycles that stopped because they ran out of time
+    pub expired_time_cap_reached_count: u64,
+    /// Time spent in active cycles
+    pub expire_cycle_time: Duration,
+}
+
+/// A set of keys to pick from at random.
+#[derive(Debug, Default)]
+struct KeySample {
+    keys: Vec<Key>,
+    /// Position of each key in `keys`
+    index: HashMap<Key, usize>,
+}
+
+impl KeySample {
+    fn insert(&mut self, key: &Key) {
+        if !self.index.contains_key(key) {
+            self.index.insert(key.clone(), self.keys.len());
+            self.keys.push(key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(index) = self.index.remove(key) {
+            self.keys.swap_remove(index);
+            if let Some(moved) = self.keys.get(index) {
+                self.index.insert(moved.clone(), index);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn random(&self) -> Key {
+        self.keys[util::random() as usize % self.keys.len()].clone()
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.index.clear();
+    }
+}
+
+/// The keyspace.
+///
+/// Entries whose deadline has passed are treated as absent by every accessor, and are
+/// removed from the map the first time they are looked up. Keys nobody looks up again
+/// are removed by [`Db::active_expire_cycle`]. Hash fields with a deadline expire the
+/// same way, with [`Db::active_expire_fields`] for the background part.
+#[derive(Debug, Default)]
+pub struct Db {
+    entries: Table<RedisDataTypeWithTTL, Key>,
+    /// Keys with a deadline, for the active expiry cycle to sample from
+    volatile: KeySample,
+    /// Hashes that had fields with a deadline at some point, some may not anymore
+    volatile_hashes: KeySample,
+    stats: ExpiryStats,
+    blocked: blocking::Blocked,
+    watched: watching::Watched,
+}
+
+impl Db {
+    pub fn new() -> Db {
+        Db::default()
+    }
+
+    fn track(&mut self, key: &Key, entry: &RedisDataTypeWithTTL) {
+        match entry.deadline() {
+            Some(_) => self.volatile.insert(key),
+            None => self.volatile.remove(key),
+        }
+        match entry.value() {
+            RedisDataType::Hash(hash) if hash.next_deadline().is_some() => {
+                self.volatile_hashes.insert(key)
+            }
+            _ => self.volatile_hashes.remove(key),
+        }
+    }
+
+    fn untrack(&mut self, key: &Key) {
+        self.volatile.remove(key);
+        self.volatile_hashes.remove(key);
+    }
+
+    /// Removes `key` if it has expired by `now`, returning whether it did. A hash left
+    /// without fields once its expired fields are removed is removed as well.
+    fn expire_if_needed_at(&mut self, key: &Key, now: Instant) -> bool {
+        match self.entries.get(key) {
+            Some(entry) if entry.is_expired(now) => {
+                self.entries.remove(key);
+                self.untrack(key);
+                self.watched.touch(key);
+                self.stats.expired_keys += 1;
+                true
+            }
+            Some(_) => {
+                self.expire_fields_at(key, now);
+                !self.entries.contains_key(key)
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the expired fields of the hash at `key`, returning how many there were.
+    fn expire_fields_at(&mut self, key: &Key, now: Instant) -> usize {
+        let hash = match self.entries.get_mut(key).map(|x| x.value_mut()) {
+            Some(RedisDataType::Hash(hash)) => hash,
+            _ => return 0,
+        };
+        match hash.next_deadline() {
+            Some(deadline) if now > deadline => {}
+            _ => return 0,
+        }
+        let expired = hash.expire(now);
+        let (empty, volatile) = (hash.is_empty(), hash.next_deadline().is_some());
+        self.stats.expired_subkeys += expired as u64;
+        self.watched.touch(key);
+        if empty {
+            self.entries.remove(key);
+            self.untrack(key);
+        } else if !volatile {
+            self.volatile_hashes.remove(key);
+        }
+        expired
+    }
+
+    /// Removes `key` if it has expired, returning whether it did.
+    fn expire_if_needed(&mut self, key: &Key) -> bool {
+        self.expire_if_needed_at(key, Instant::now())
+    }
+
+    pub fn get(&mut self, key: &Key) -> Option<&RedisDataTypeWithTTL> {
+        self.expire_if_needed(key);
+        self.entries.get(key)
+    }
+
+    /// Shared access to a live entry, for looking at several at once. Unlike [`Db::get`],
+    /// this leaves an expired entry in place.
+    pub fn peek(&self, key: &Key) -> Option<&RedisDataTypeWithTTL> {
+        let now = Instant::now();
+        self.entries.get(key).filter(|x| !x.is_expired(now))
+    }
+
+    pub fn contains_key(&mut self, key: &Key) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Mutable access to an entry, to be followed by [`Db::signal_modified`] if it does
+    /// get changed. Its deadline is changed with [`Db::set_deadline`] instead, which keeps
+    /// track of the keys that have one.
+    pub fn get_mut(&mut self, key: &Key) -> Option<&mut RedisDataTypeWithTTL> {
+        self.expire_if_needed(key);
+        self.entries.get_mut(key)
+    }
+
+    /// Sets or clears the deadline of a live key, returning whether the key exists.
+    pub fn set_deadline(&mut self, key: &Key, deadline: Option<Instant>) -> bool {
+        self.expire_if_needed(key);
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                entry.set_deadline(deadline);
+                self.watched.touch(key);
+                match deadline {
+                    Some(_) => self.volatile.insert(key),
+                    None => self.volatile.remove(key),
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets or clears the deadline of a field of the hash at `key`, returning whether the
+    /// field exists.
+    pub fn set_field_deadline(
+        &mut self,
+        key: &Key,
+        field: &[u8],
+        deadline: Option<Instant>,
+    ) -> bool {
+        self.expire_if_needed(key);
+        let hash = match self.entries.get_mut(key).map(|x| x.value_mut()) {
+            Some(RedisDataType::Hash(hash)) => hash,
+            _ => return false,
+        };
+        let exists = hash.set_deadline(field, deadline);
+        if exists {
+            self.watched.touch(key);
+            if deadline.is_some() {
+                self.volatile_hashes.insert(key);
+            }
+        }
+        exists
+    }
+
+    /// Removes an entry, returning it if it was live.
+    pub fn remove(&mut self, key: &Key) -> Option<RedisDataTypeWithTTL> {
+        let now = Instant::now();
+        self.untrack(key);
+        let entry = self.entries.remove(key);
+        if entry.is_some() {
+            self.watched.touch(key);
+        }
+        entry.filter(|x| !x.is_expired(now))
+    }
+
+    /// Inserts an entry, returning the live entry it replaced if any.
+    pub fn insert(
+        &mut self,
+        key: Key,
+        entry: RedisDataTypeWithTTL,
+    ) -> Option<RedisDataTypeWithTTL> {
+        let now = Instant::now();
+        self.track(&key, &entry);
+        self.watched.touch(&key);
+        if matches!(
+            entry.value(),
+            RedisDataType::List(_) | RedisDataType::SortedSet(_) | RedisDataType::Stream(_)
+        ) {
+            // written whole, e.g. by RENAME, which serves clients blocked on the key
+            self.signal_ready(&key);
+        }
+        self.entries
+            .insert(key, entry)
+            .filter(|x| !x.is_expired(now))
+    }
+
+    /// The number of keys, counting the expired ones not removed yet the way redis does.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The number of keys with a deadline, including the expired ones not removed yet.
+    pub fn volatile_len(&self) -> usize {
+        self.volatile.len()
+    }
+
+    pub fn clear(&mut self) {
+        // only the keys that go away change
+        for key in self.watched.keys() {
+            if self.contains_key(&key) {
+                self.watched.touch(&key);
+            }
+        }
+        self.entries.clear();
+        self.volatile.clear();
+        self.volatile_hashes.clear();
+    }
+
+    /// Picks a live key at random, dropping the expired keys it comes across.
+    pub fn random_key(&mut self) -> Option<Key> {
+        let now = Instant::now();
+        while let Some((key, _)) = self.entries.random() {
+            let key = key.clone();
+            if !self.expire_if_needed_at(&key, now) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    pub fn expiry_stats(&self) -> ExpiryStats {
+        self.stats
+    }
+
+    /// Removes expired keys nobody looks up, the way redis does: repeatedly sample a few
+    /// keys with a deadline and remove the expired ones, for as long as enough of them
+    /// are expired for another round to be worth it, and at most for `budget`.
+    ///
+    /// Keys are considered expired as of `now`. Returns the number of keys removed.
+    pub fn active_expire_cycle(&mut self, now: Instant, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut rounds = 0;
+        let (mut total_sampled, mut total_expired) = (0, 0);
+        while !self.volatile.is_empty() {
+            rounds += 1;
+            let samples = self.volatile.len().min(ACTIVE_EXPIRE_KEYS_PER_LOOP);
+            let mut expired = 0;
+            for _ in 0..samples {
+                if self.volatile.is_empty() {
+                    break;
+                }
+                let key = self.volatile.random();
+                if self.expire_if_needed_at(&key, now) {
+                    expired += 1;
+                }
+            }
+            total_sampled += samples;
+            total_expired += expired;
+
+            if rounds % ACTIVE_EXPIRE_ROUNDS_PER_TIME_CHECK == 0 && start.elapsed() >= budget {
+                self.stats.expired_time_cap_reached_count += 1;
+                break;
+            }
+            if expired * 100 <= samples * ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                break;
+            }
+        }
+
+        if total_sampled > 0 {
+            // smoothed like redis does, a single cycle says little on its own
+            let current = total_expired as f64 * 100.0 / total_sampled as f64;
+            self.stats.expired_stale_perc = current * 0.05 + self.stats.expired_stale_perc * 0.95;
+        }
+        self.stats.expire_cycle_time += start.elapsed();
+        total_expired
+    }
+
+    /// Removes expired hash fields nobody looks up, the same way as
+    /// [`Db::active_expire_cycle`] does for keys: hashes with fields that have a deadline
+    /// are sampled for as long as enough of them have expired fields.
+    ///
+    /// Fields are considered expired as of `now`. Returns the number of fields removed.
+    pub fn active_expire_fields(&mut self, now: Instant, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut rounds = 0;
+        let mut total_expired = 0;
+        while !self.volatile_hashes.is_empty() {
+            rounds += 1;
+            let samples = self.volatile_hashes.len().min(ACTIVE_EXPIRE_KEYS_PER_LOOP);
+            let mut stale = 0;
+            for _ in 0..samples {
+                if self.volatile_hashes.is_empty() {
+                    break;
+                }
+                let key = self.volatile_hashes.random();
+                let expired = self.expire_fields_at(&key, now);
+                // hashes whose fields no longer have a deadline are left behind by writes
+                let volatile = matches!(
+                    self.entries.get(&key).map(|x| x.value()),
+                    Some(RedisDataType::Hash(hash)) if hash.next_deadline().is_some()
+                );
+                if !volatile {
+                    self.volatile_hashes.remove(&key);
+                }
+                if expired > 0 {
+                    stale += 1;
+                }
+                total_expired += expired;
+            }
+
+            if rounds % ACTIVE_EXPIRE_ROUNDS_PER_TIME_CHECK == 0 && start.elapsed() >= budget {
+                self.stats.expired_time_cap_reached_count += 1;
+                break;
+            }
+            if stale * 100 <= samples * ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                break;
+            }
+        }
+        self.stats.expire_cycle_time += start.elapsed();
+        total_expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Db;
+    use crate::data_type::{Hash, Key, RedisDataType, RedisDataTypeWithTTL};
+    use bytes::Bytes;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn insert(db: &mut Db, key: usize, deadline: Option<Instant>) {
+        db.insert(
+            Bytes::from(format!("key:{}", key)).into(),
+            RedisDataTypeWithTTL::new(RedisDataType::Integers(key as i64), deadline),
+        );
+    }
+
+    #[test]
+    fn test_volatile_keys_are_tracked() {
+        let mut db = Db::new();
+        let later = Instant::now() + Duration::from_secs(100);
+        insert(&mut db, 1, Some(later));
+        insert(&mut db, 2, Some(later));
+        insert(&mut db, 3, None);
+        assert_eq!(db.volatile_len(), 2);
+
+        // overwriting without a deadline, persisting and deleting all untrack the key
+        insert(&mut db, 1, None);
+        assert_eq!(db.volatile_len(), 1);
+        assert!(db.set_deadline(&"key:3".into(), Some(later)));
+        assert_eq!(db.volatile_len(), 2);
+        assert!(db.set_deadline(&"key:3".into(), None));
+        assert!(db.remove(&"key:2".into()).is_some());
+        assert_eq!(db.volatile_len(), 0);
+        assert!(!db.set_deadline(&"key:2".into(), Some(later)));
+    }
+
+    #[test]
+    fn test_active_expire_cycle_removes_expired_keys() {
+        let mut db = Db::new();
+        let now = Instant::now();
+        for key in 0..1000 {
+            insert(&mut db, key, Some(now + Duration::from_secs(10)));
+        }
+        for key in 1000..1100 {
+            insert(&mut db, key, Some(now + Duration::from_secs(1000)));
+        }
+        for key in 1100..1200 {
+            insert(&mut db, key, None);
+        }
+
+        // nothing is expired yet, a single round of sampling shows it
+        assert_eq!(db.active_expire_cycle(now, Duration::from_secs(10)), 0);
+        assert_eq!(db.expiry_stats().expired_keys, 0);
+
+        let later = now + Duration::from_secs(100);
+        // once most expired keys are gone a cycle may sample none of the rest, it takes
+        // a few cycles to get to all of them
+        let mut expired = 0;
+        for _ in 0..1000 {
+            expired += db.active_expire_cycle(later, Duration::from_secs(10));
+        }
+        assert_eq!(expired, 1000);
+        assert_eq!(db.volatile_len(), 100);
+        assert_eq!(db.expiry_stats().expired_keys, 1000);
+        assert!(db.expiry_stats().expired_stale_perc > 0.0);
+        assert_eq!(db.active_expire_cycle(later, Duration::from_secs(10)), 0);
+        assert_eq!(db.entries.len(), 200);
+    }
+
+    #[test]
+    fn test_active_expire_cycle_time_budget() {
+        let mut db = Db::new();
+        let now = Instant::now();
+        for key in 0..10_000 {
+            insert(&mut db, key, Some(now));
+        }
+
+        // without any budget the cycle stops at the first check of the clock
+        let later = now + Duration::from_secs(1);
+        let removed = db.active_expire_cycle(later, Duration::from_secs(0));
+        assert!(removed > 0 && removed <= 16 * 20);
+        assert_eq!(db.expiry_stats().expired_time_cap_reached_count, 1);
+        assert_eq!(db.volatile_len(), 10_000 - removed);
+    }
+
+    #[test]
+    fn test_hash_fields_expire() {
+        let mut db = Db::new();
+        let now = Instant::now();
+        for key in 0..100 {
+            let mut hash = Hash::new();
+            hash.insert("kept".into(), "v".into());
+            hash.insert("soon".into(), "v".into());
+            hash.set_deadline(b"soon", Some(now + Duration::from_secs(10)));
+            db.insert(
+                Bytes::from(format!("hash:{}", key)).into(),
+                RedisDataTypeWithTTL::Infinite(RedisDataType::Hash(hash)),
+            );
+        }
+        let key: Key = "hash:0".into();
+        assert!(db.set_field_deadline(&key, b"kept", Some(now + Duration::from_secs(10))));
+        assert!(!db.set_field_deadline(&key, b"missing", Some(now)));
+
+        let later = now + Duration::from_secs(100);
+        assert_eq!(db.active_expire_fields(now, Duration::from_secs(10)), 0);
+        let mut expired = 0;
+        for _ in 0..1000 {
+            expired += db.active_expire_fields(later, Duration::from_secs(10));
+        }
+        // the hash left without fields is removed along with them
+        assert_eq!(expired, 101);
+        assert_eq!(db.expiry_stats().expired_subkeys, 101);
+        assert_eq!(db.entries.len(), 99);
+        assert!(db.volatile_hashes.is_empty());
+        match db.get(&"hash:1".into()).map(|x| x.value()) {
+            Some(RedisDataType::Hash(hash)) => {
+                assert_eq!(hash.len(), 1);
+                assert!(hash.contains_key(b"kept"));
+            }
+            other => panic!("expected a hash, got {:?}", other),
+        }
+    }
+}
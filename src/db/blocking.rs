@@ -0,0 +1,212 @@
+use super::Db;
+use crate::{
+    data_type::{Key, RespDataType},
+    util,
+};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    ops::Bound,
+};
+use tokio::sync::oneshot;
+
+/// Tries to serve a blocked client, `Ok(None)` while there is nothing for it yet.
+pub type Serve = Box<dyn FnMut(&mut Db) -> util::Result<Option<RespDataType>> + Send + Sync>;
+
+/// The receiving end of a blocked client, getting the reply once it is served.
+pub type Reply = oneshot::Receiver<util::Result<RespDataType>>;
+
+struct Waiter {
+    keys: Vec<Key>,
+    serve: Serve,
+    /// Whether the clients waiting in line with this one are all after the same thing,
+    /// so that when it cannot be served, neither can they
+    in_line: bool,
+    reply: oneshot::Sender<util::Result<RespDataType>>,
+}
+
+/// Clients waiting for keys to be pushed to, served in the order they blocked.
+#[derive(Default)]
+pub(super) struct Blocked {
+    next_id: u64,
+    waiters: HashMap<u64, Waiter>,
+    /// The clients waiting on each key, by id and so oldest first
+    queues: HashMap<Key, BTreeSet<u64>>,
+    /// Keys written to since blocked clients were last served, in signalling order
+    ready: VecDeque<Key>,
+    ready_set: HashSet<Key>,
+}
+
+impl fmt::Debug for Blocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blocked")
+            .field("waiters", &self.waiters.len())
+            .field("queues", &self.queues)
+            .field("ready", &self.ready)
+            .finish()
+    }
+}
+
+impl Blocked {
+    fn remove(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        self.dequeue(id, &waiter.keys);
+        Some(waiter)
+    }
+
+    /// The first client waiting on `key` after `after`.
+    fn next_in_queue(&self, key: &Key, after: Bound<u64>) -> Option<u64> {
+        let queue = self.queues.get(key)?;
+        queue.range((after, Bound::Unbounded)).next().copied()
+    }
+
+    /// Takes a client out of the queues of its keys.
+    fn dequeue(&mut self, id: u64, keys: &[Key]) {
+        for key in keys {
+            if let Some(queue) = self.queues.get_mut(key) {
+                queue.remove(&id);
+                if queue.is_empty() {
+                    self.queues.remove(key);
+                }
+            }
+        }
+    }
+}
+
+impl Db {
+    /// Parks a client until one of `keys` can serve it. The client waits on the returned
+    /// receiver, without holding on to the keyspace, and calls [`Db::unblock`] with the
+    /// returned id if it gives up.
+    ///
+    /// Clients `in_line` wait for the same thing as the others in line with them, such as
+    /// a `BLPOP` for any element, so that no client after one that could not be served is
+    /// tried. Others, such as an `XREAD` after its own ID, are all tried each time.
+    pub fn block(&mut self, keys: Vec<Key>, serve: Serve, in_line: bool) -> (u64, Reply) {
+        let blocked = &mut self.blocked;
+        let id = blocked.next_id;
+        blocked.next_id += 1;
+        for key in &keys {
+            blocked.queues.entry(key.clone()).or_default().insert(id);
+        }
+        let (sender, receiver) = oneshot::channel();
+        blocked.waiters.insert(
+            id,
+            Waiter {
+                keys,
+                serve,
+                in_line,
+                reply: sender,
+            },
+        );
+        (id, receiver)
+    }
+
+    /// Stops waiting, returning whether the client was still blocked. When it was not,
+    /// its reply is already waiting in its receiver.
+    pub fn unblock(&mut self, id: u64) -> bool {
+        self.blocked.remove(id).is_some()
+    }
+
+    /// The number of blocked clients.
+    pub fn blocked_len(&self) -> usize {
+        self.blocked.waiters.len()
+    }
+
+    /// Notes that `key` was written to in a way that may serve clients blocked on it.
+    pub fn signal_ready(&mut self, key: &Key) {
+        let blocked = &mut self.blocked;
+        if blocked.queues.contains_key(key) && blocked.ready_set.insert(key.clone()) {
+            blocked.ready.push_back(key.clone());
+        }
+    }
+
+    /// Serves the clients blocked on the keys signalled since the last call, oldest
+    /// client first. Serving a client may in turn signal more keys, such as the
+    /// destination of a `BLMOVE`, which are served as well.
+    ///
+    /// A client only leaves the queues once served, and the walk down a queue stops at
+    /// the first client in line that cannot be, so that a push tries a single client
+    /// more than it serves however many are waiting.
+    pub fn serve_blocked(&mut self) {
+        while let Some(key) = self.blocked.ready.pop_front() {
+            self.blocked.ready_set.remove(&key);
+            let mut after = Bound::Unbounded;
+            while let Some(id) = self.blocked.next_in_queue(&key, after) {
+                after = Bound::Excluded(id);
+                // taken out while it runs, as serving it needs the whole keyspace
+                let mut waiter = self.blocked.waiters.remove(&id).expect("a waiter");
+                let reply = match (waiter.serve)(self).transpose() {
+                    Some(reply) => reply,
+                    None => {
+                        let in_line = waiter.in_line;
+                        self.blocked.waiters.insert(id, waiter);
+                        if in_line {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                self.blocked.dequeue(id, &waiter.keys);
+                let _ = waiter.reply.send(reply);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        command::{test_util::run_ok, RespCommand},
+        data_type::RespDataType,
+        db::Db,
+    };
+    use std::{
+        convert::TryFrom,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_push_tries_one_client_more_than_it_serves() {
+        let mut db = Db::new();
+        let tries = Arc::new(AtomicUsize::new(0));
+        let mut replies = vec![];
+        for _ in 0..1000 {
+            let args = vec!["BLPOP", "queue", "0"]
+                .into_iter()
+                .map(RespDataType::bulk_strings)
+                .collect();
+            let command = RespCommand::try_from(RespDataType::arrays(args)).unwrap();
+            let tries = tries.clone();
+            let (_, reply) = db.block(
+                vec!["queue".into()],
+                Box::new(move |db| {
+                    tries.fetch_add(1, Ordering::Relaxed);
+                    command.as_blocking_command().unwrap().try_serve(db)
+                }),
+                true,
+            );
+            replies.push(reply);
+        }
+
+        run_ok(&mut db, "RPUSH queue a b c").await;
+        db.serve_blocked();
+        assert_eq!(tries.load(Ordering::Relaxed), 4);
+        run_ok(&mut db, "RPUSH queue d").await;
+        db.serve_blocked();
+        assert_eq!(tries.load(Ordering::Relaxed), 6);
+
+        assert_eq!(db.blocked_len(), 996);
+        for (reply, element) in replies.iter_mut().zip(&["a", "b", "c", "d"]) {
+            assert_eq!(
+                reply.try_recv().unwrap().unwrap(),
+                RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("queue"),
+                    RespDataType::bulk_strings(*element),
+                ])
+            );
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::Db;
+use crate::data_type::Key;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Default)]
+struct Watcher {
+    keys: HashSet<Key>,
+    /// Whether one of the keys changed since it was watched
+    touched: bool,
+}
+
+/// Keys watched by clients with `WATCH`, for their transactions to fail if the keys
+/// change before `EXEC`.
+#[derive(Debug, Default)]
+pub(super) struct Watched {
+    /// The clients watching each key
+    keys: HashMap<Key, HashSet<u64>>,
+    watchers: HashMap<u64, Watcher>,
+}
+
+impl Watched {
+    pub(super) fn touch(&mut self, key: &Key) {
+        if let Some(clients) = self.keys.get(key) {
+            for client in clients {
+                if let Some(watcher) = self.watchers.get_mut(client) {
+                    watcher.touched = true;
+                }
+            }
+        }
+    }
+
+    /// The watched keys, to touch the ones that exist when the keyspace is emptied.
+    pub(super) fn keys(&self) -> Vec<Key> {
+        self.keys.keys().cloned().collect()
+    }
+}
+
+impl Db {
+    /// Watches `key` for `client`, until [`Db::unwatch`].
+    pub fn watch(&mut self, client: u64, key: &Key) {
+        // a key that expired already is not a change to come
+        self.expire_if_needed(key);
+        let watched = &mut self.watched;
+        watched.keys.entry(key.clone()).or_default().insert(client);
+        watched
+            .watchers
+            .entry(client)
+            .or_default()
+            .keys
+            .insert(key.clone());
+    }
+
+    /// Notes that the entry at `key` was changed in place, through [`Db::get_mut`], which
+    /// aborts the transactions watching it.
+    pub fn signal_modified(&mut self, key: &Key) {
+        self.watched.touch(key);
+    }
+
+    /// Stops watching every key of `client`, returning whether any of them changed since
+    /// it was watched.
+    pub fn unwatch(&mut self, client: u64) -> bool {
+        let keys = match self.watched.watchers.get(&client) {
+            Some(watcher) => watcher.keys.iter().cloned().collect::<Vec<_>>(),
+            None => return false,
+        };
+        // keys that expired without anyone looking them up changed all the same
+        for key in &keys {
+            self.expire_if_needed(key);
+        }
+        let watched = &mut self.watched;
+        let watcher = watched.watchers.remove(&client).expect("a watcher");
+        for key in &watcher.keys {
+            if let Some(clients) = watched.keys.get_mut(key) {
+                clients.remove(&client);
+                if clients.is_empty() {
+                    watched.keys.remove(key);
+                }
+            }
+        }
+        watcher.touched
+    }
+}
@@ -7,7 +7,9 @@ async fn main() -> util::Result<()> {
     server.serve("127.0.0.1:6379").await
 }
 
+mod codec;
 mod command;
 mod data_type;
+mod db;
 mod server;
 mod util;
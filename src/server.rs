@@ -1,60 +1,367 @@
 use crate::{
+    codec::{Decoder, Encoder, ProtocolLimits, RespCodec},
     command::{Command, RespCommand},
-    data_type::{RedisDataTypeWithTTL, RespDataType},
+    data_type::{ProtocolVersion, RespDataType},
+    db::Db,
+};
+use bytes::{Bytes, BytesMut};
+use std::{
+    convert::TryInto,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use std::{collections::HashMap, convert::TryInto, sync::Arc};
 use tokio::{
-    io::{AsyncWriteExt, BufStream},
-    net::{TcpListener, TcpStream, ToSocketAddrs},
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, ToSocketAddrs},
     stream::StreamExt,
     sync::Mutex,
+    time::{self, Instant},
 };
 
+/// Replies are written out early once this much is pending, so that a long pipeline of
+/// large replies does not pile up in memory.
+const MAX_PENDING_REPLIES_LEN: usize = 64 * 1024;
+
+/// How often the active expiry cycle runs, 10 times a second like redis at its default
+/// `hz`.
+const ACTIVE_EXPIRE_PERIOD: Duration = Duration::from_millis(100);
+/// How long an active expiry cycle may hold the keyspace, a quarter of its period.
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
+/// Per-connection state, owned by the task serving the connection.
+#[derive(Debug, Default)]
+pub struct Connection {
+    pub id: u64,
+    pub protocol: ProtocolVersion,
+    pub name: Option<Bytes>,
+    /// The commands queued since `MULTI`, `None` outside of a transaction
+    pub transaction: Option<Transaction>,
+}
+
+/// The state of a transaction between `MULTI` and `EXEC`.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    pub commands: Vec<RespCommand>,
+    /// Whether a command failed to be queued, so that `EXEC` discards the transaction
+    pub aborted: bool,
+}
+
+/// The socket of a connection, lent to a blocking command while it waits.
+struct Peer<'a, S> {
+    stream: &'a mut S,
+    read_buf: &'a mut BytesMut,
+    write_buf: &'a mut BytesMut,
+    /// Whether the client went away while its command was waiting
+    closed: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Peer<'_, S> {
+    /// Sends out the replies to the commands pipelined before the one about to wait, as
+    /// they must not wait along with it.
+    async fn flush(&mut self) -> std::io::Result<()> {
+        if !self.write_buf.is_empty() {
+            self.stream.write_all(self.write_buf).await?;
+            self.stream.flush().await?;
+            self.write_buf.clear();
+        }
+        Ok(())
+    }
+
+    /// Resolves once the client disconnects. Whatever it sends meanwhile is buffered, to
+    /// be run after the command it waits on, just like redis does.
+    async fn closed(&mut self) {
+        loop {
+            match self.stream.read_buf(self.read_buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+        self.closed = true;
+    }
+}
+
 pub struct RedisServer {
-    db: Arc<Mutex<HashMap<String, RedisDataTypeWithTTL>>>,
+    db: Arc<Mutex<Db>>,
+    next_client_id: AtomicU64,
+    limits: ProtocolLimits,
 }
 
 impl RedisServer {
     pub fn new() -> RedisServer {
+        Self::with_limits(ProtocolLimits::default())
+    }
+
+    pub fn with_limits(limits: ProtocolLimits) -> RedisServer {
         RedisServer {
-            db: Arc::new(Mutex::new(HashMap::new())),
+            db: Arc::new(Mutex::new(Db::new())),
+            next_client_id: AtomicU64::new(1),
+            limits,
+        }
+    }
+
+    async fn execute<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        connection: &mut Connection,
+        frame: RespDataType,
+        peer: &mut Peer<'_, S>,
+    ) -> crate::util::Result<RespDataType> {
+        let command: crate::util::Result<RespCommand> = frame.try_into();
+        if let Some(transaction) = &mut connection.transaction {
+            match command {
+                Ok(RespCommand::Exec) => return self.exec(connection).await,
+                Ok(RespCommand::Discard) => {
+                    connection.transaction = None;
+                    self.db.lock().await.unwatch(connection.id);
+                    return Ok(RespDataType::simple_strings("OK"));
+                }
+                Ok(RespCommand::Multi) => return Err("ERR MULTI calls can not be nested".into()),
+                Ok(RespCommand::Watch(_)) => {
+                    return Err("ERR WATCH inside MULTI is not allowed".into())
+                }
+                Ok(command) => {
+                    transaction.commands.push(command);
+                    return Ok(RespDataType::simple_strings("QUEUED"));
+                }
+                Err(e) => {
+                    transaction.aborted = true;
+                    return Err(e);
+                }
+            }
+        }
+
+        let command = command?;
+        if command.as_blocking_command().is_some() {
+            return self.block(Arc::new(command), peer).await;
+        }
+        if let Some(command) = command.as_keyspace_command() {
+            let mut db = self.db.lock().await;
+            let reply = command.execute(&mut db).await;
+            db.serve_blocked();
+            return reply;
         }
+        match command {
+            RespCommand::Multi => {
+                connection.transaction = Some(Transaction::default());
+                Ok(RespDataType::simple_strings("OK"))
+            }
+            RespCommand::Exec => Err("ERR EXEC without MULTI".into()),
+            RespCommand::Discard => Err("ERR DISCARD without MULTI".into()),
+            RespCommand::Watch(watch) => {
+                let mut db = self.db.lock().await;
+                for key in &watch.keys {
+                    db.watch(connection.id, key);
+                }
+                Ok(RespDataType::simple_strings("OK"))
+            }
+            RespCommand::Unwatch => {
+                self.db.lock().await.unwatch(connection.id);
+                Ok(RespDataType::simple_strings("OK"))
+            }
+            command => Self::execute_on_connection(connection, &command).await,
+        }
+    }
+
+    /// Runs one of the commands that do not touch the keyspace.
+    async fn execute_on_connection(
+        connection: &mut Connection,
+        command: &RespCommand,
+    ) -> crate::util::Result<RespDataType> {
+        match command {
+            RespCommand::Ping(ping) => ping.execute(&mut ()).await,
+            RespCommand::Echo(echo) => echo.execute(&mut ()).await,
+            RespCommand::Hello(hello) => hello.execute(connection).await,
+            _ => unreachable!("keyspace and transaction commands are executed elsewhere"),
+        }
+    }
+
+    /// Runs the queued commands of a transaction one after the other, holding the
+    /// keyspace throughout so that no other client sees it halfway. Blocking commands
+    /// do not wait, replying as if they timed out when they cannot be served.
+    ///
+    /// Nothing runs if a watched key changed since `WATCH`, the reply being nil.
+    async fn exec(&self, connection: &mut Connection) -> crate::util::Result<RespDataType> {
+        let transaction = connection.transaction.take().expect("a transaction");
+        let mut db = self.db.lock().await;
+        let touched = db.unwatch(connection.id);
+        if transaction.aborted {
+            return Err("EXECABORT Transaction discarded because of previous errors.".into());
+        }
+        if touched {
+            return Ok(RespDataType::empty_arrays());
+        }
+        let mut replies = Vec::with_capacity(transaction.commands.len());
+        for command in &transaction.commands {
+            let reply = match command.as_keyspace_command() {
+                Some(command) => command.execute(&mut db).await,
+                // the keys were unwatched already
+                None if matches!(command, RespCommand::Unwatch) => {
+                    Ok(RespDataType::simple_strings("OK"))
+                }
+                None => Self::execute_on_connection(connection, command).await,
+            };
+            replies.push(reply.unwrap_or_else(|e| RespDataType::errors(e.to_string())));
+        }
+        db.serve_blocked();
+        Ok(RespDataType::arrays(replies))
+    }
+
+    /// Runs a blocking command, waiting for one of its keys when it cannot be served
+    /// right away. The keyspace is only locked to register the wait and to give up on it.
+    ///
+    /// The wait is given up on as well when the client disconnects, so that what would
+    /// have been served to it is left for the next one.
+    async fn block<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        command: Arc<RespCommand>,
+        peer: &mut Peer<'_, S>,
+    ) -> crate::util::Result<RespDataType> {
+        let mut db = self.db.lock().await;
+        let command = match command
+            .as_blocking_command()
+            .expect("a blocking command")
+            .resolve(&mut db)?
+        {
+            Some(resolved) => Arc::new(resolved),
+            None => command,
+        };
+        let blocking = command.as_blocking_command().expect("a blocking command");
+        if let Some(reply) = blocking.try_serve(&mut db)? {
+            db.serve_blocked();
+            return Ok(reply);
+        }
+        let serve_command = command.clone();
+        let (id, mut reply) = db.block(
+            blocking.keys(),
+            Box::new(move |db| {
+                serve_command
+                    .as_blocking_command()
+                    .expect("a blocking command")
+                    .try_serve(db)
+            }),
+            blocking.in_line(),
+        );
+        drop(db);
+
+        let served = match peer.flush().await {
+            Ok(()) => {
+                let wait = async {
+                    match blocking.timeout() {
+                        Some(timeout) => time::timeout(timeout, &mut reply).await.ok(),
+                        None => Some((&mut reply).await),
+                    }
+                };
+                tokio::select! {
+                    served = wait => served,
+                    _ = peer.closed() => None,
+                }
+            }
+            Err(_) => {
+                peer.closed = true;
+                None
+            }
+        };
+        if let Some(Ok(reply)) = served {
+            return reply;
+        }
+        let mut db = self.db.lock().await;
+        if db.unblock(id) {
+            return Ok(blocking.timeout_reply());
+        }
+        // served while giving up, the reply was sent already
+        reply
+            .try_recv()
+            .unwrap_or_else(|_| Ok(blocking.timeout_reply()))
     }
 
-    async fn process(&self, stream: TcpStream) -> crate::util::Result<()> {
-        let mut stream = BufStream::new(stream);
+    async fn process<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+    ) -> crate::util::Result<()> {
+        let mut connection = Connection {
+            id: self.next_client_id.fetch_add(1, Ordering::Relaxed),
+            ..Default::default()
+        };
+        let result = self.serve_connection(stream, &mut connection).await;
+        self.db.lock().await.unwatch(connection.id);
+        result
+    }
+
+    async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut stream: S,
+        connection: &mut Connection,
+    ) -> crate::util::Result<()> {
+        let mut codec = RespCodec::with_limits(self.limits);
+        let mut read_buf = BytesMut::with_capacity(4096);
+        let mut write_buf = BytesMut::with_capacity(4096);
         loop {
-            match RespDataType::deserialize(&mut stream)
-                .await
-                .and_then(|data| data.try_into() as Result<RespCommand, _>)
-            {
-                Err(e) => Err(e),
-                Ok(cmd) => {
-                    match cmd {
-                        RespCommand::Ping(ping) => ping.execute(&mut ()).await,
-                        RespCommand::Echo(echo) => echo.execute(&mut ()).await,
-                        RespCommand::Set(set) => {
-                            let db = self.db.clone();
-                            let mut db = db.lock().await;
-                            set.execute(&mut db).await
-                        }
-                        RespCommand::Get(get) => {
-                            let db = self.db.clone();
-                            let mut db = db.lock().await;
-                            get.execute(&mut db).await
-                        } // _ => RespDataType::errors("unimplemented"),
+            // run every command that is already buffered before writing anything back, so
+            // that a pipeline costs one write instead of one per reply
+            loop {
+                let frame = match codec.decode(&mut read_buf) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => break,
+                    Err(e) => {
+                        // the stream cannot be resynchronized after a malformed frame
+                        codec.encode(RespDataType::errors(e.to_string()), &mut write_buf)?;
+                        stream.write_all(&write_buf).await?;
+                        stream.flush().await?;
+                        return Err(e);
                     }
+                };
+
+                let mut peer = Peer {
+                    stream: &mut stream,
+                    read_buf: &mut read_buf,
+                    write_buf: &mut write_buf,
+                    closed: false,
+                };
+                let reply = self.execute(connection, frame, &mut peer).await;
+                if peer.closed {
+                    return Ok(());
+                }
+                let reply = reply
+                    .unwrap_or_else(|e| RespDataType::errors(e.to_string()))
+                    .into_protocol(connection.protocol);
+                codec.encode(reply, &mut write_buf)?;
+
+                if write_buf.len() >= MAX_PENDING_REPLIES_LEN {
+                    stream.write_all(&write_buf).await?;
+                    write_buf.clear();
                 }
             }
-            .unwrap_or_else(|e| RespDataType::errors(e.to_string()))
-            .serialize(&mut stream)
-            .await?;
-            stream.flush().await?;
+
+            if !write_buf.is_empty() {
+                stream.write_all(&write_buf).await?;
+                stream.flush().await?;
+                write_buf.clear();
+            }
+
+            if stream.read_buf(&mut read_buf).await? == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Removes expired keys and hash fields in the background, for the ones no client
+    /// looks up again.
+    async fn active_expire(self: Arc<Self>) {
+        let mut interval = time::interval(ACTIVE_EXPIRE_PERIOD);
+        loop {
+            interval.tick().await;
+            let mut db = self.db.lock().await;
+            let now = Instant::now();
+            db.active_expire_cycle(now, ACTIVE_EXPIRE_BUDGET);
+            db.active_expire_fields(now, ACTIVE_EXPIRE_BUDGET);
         }
     }
 
     pub async fn serve<A: ToSocketAddrs>(self: Arc<Self>, addr: A) -> crate::util::Result<()> {
         let mut listener = TcpListener::bind(addr).await?;
+        tokio::spawn(self.clone().active_expire());
         while let Some(stream) = listener.incoming().filter_map(|x| x.ok()).next().await {
             let this = self.clone();
             println!(
@@ -67,3 +374,790 @@ impl RedisServer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::RedisServer;
+    use crate::{
+        codec::{Decoder, RespCodec},
+        data_type::RespDataType,
+        util::Result,
+    };
+    use bytes::BytesMut;
+    use std::{
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+    };
+    use tokio::{
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream},
+        time::{delay_for, timeout, Duration, Instant},
+    };
+
+    /// Counts the flushes done by the server on its end of the connection.
+    struct CountingStream {
+        inner: DuplexStream,
+        flushes: Arc<AtomicUsize>,
+    }
+
+    impl AsyncRead for CountingStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for CountingStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    struct Client {
+        stream: DuplexStream,
+        buf: BytesMut,
+        codec: RespCodec,
+    }
+
+    impl Client {
+        fn connect(server: &Arc<RedisServer>) -> Client {
+            Self::connect_counting(server, Arc::new(AtomicUsize::new(0)))
+        }
+
+        fn connect_counting(server: &Arc<RedisServer>, flushes: Arc<AtomicUsize>) -> Client {
+            let (client, stream) = tokio::io::duplex(1 << 20);
+            let server = server.clone();
+            tokio::spawn(async move {
+                server
+                    .process(CountingStream {
+                        inner: stream,
+                        flushes,
+                    })
+                    .await
+            });
+            Client {
+                stream: client,
+                buf: BytesMut::new(),
+                codec: RespCodec::default(),
+            }
+        }
+
+        async fn send<S: AsRef<[u8]>>(&mut self, commands: S) -> Result<()> {
+            self.stream.write_all(commands.as_ref()).await?;
+            Ok(())
+        }
+
+        async fn receive(&mut self) -> Result<RespDataType> {
+            loop {
+                if let Some(reply) = self.codec.decode(&mut self.buf)? {
+                    return Ok(reply);
+                }
+                let read = timeout(Duration::from_secs(5), self.stream.read_buf(&mut self.buf));
+                if read.await?? == 0 {
+                    return Err("connection closed".into());
+                }
+            }
+        }
+    }
+
+    /// Waits for `n` clients to be blocked, so that what follows happens once they are.
+    async fn wait_blocked(server: &RedisServer, n: usize) {
+        while server.db.lock().await.blocked_len() != n {
+            delay_for(Duration::from_millis(1)).await;
+        }
+    }
+
+    fn bulks(values: &[&'static str]) -> RespDataType {
+        RespDataType::arrays(
+            values
+                .iter()
+                .map(|x| RespDataType::bulk_strings(*x))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_replies_are_batched() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let mut client = Client::connect_counting(&server, flushes.clone());
+
+        let n = 1000;
+        let pipeline = (0..n)
+            .map(|i| format!("*2\r\n$4\r\nECHO\r\n${}\r\n{}\r\n", i.to_string().len(), i))
+            .collect::<String>();
+        client.send(&pipeline).await?;
+        for i in 0..n {
+            assert_eq!(
+                client.receive().await?,
+                RespDataType::bulk_strings(i.to_string())
+            );
+        }
+        assert!(flushes.load(Ordering::SeqCst) < n / 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_closes_connection() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+
+        client.send("PING\r\n*1\r\n$x\r\nPING\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::simple_strings("PONG")
+        );
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR Protocol error: invalid bulk length")
+        );
+        assert!(client.receive().await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_binary_keys() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+
+        client
+            .send(&b"*3\r\n$3\r\nSET\r\n$4\r\n\xff\x00\xfe\n\r\n$5\r\nvalue\r\n"[..])
+            .await?;
+        assert_eq!(client.receive().await?, RespDataType::simple_strings("OK"));
+        client
+            .send(&b"*2\r\n$3\r\nGET\r\n$4\r\n\xff\x00\xfe\n\r\n"[..])
+            .await?;
+        assert_eq!(client.receive().await?, RespDataType::bulk_strings("value"));
+        client
+            .send(&b"*2\r\n$3\r\nGET\r\n$3\r\n\xff\x00\xfe\r\n"[..])
+            .await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_bulk_strings());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blpop_is_served_by_a_push() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        worker.send("BLPOP jobs:high jobs:low 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+
+        // the keyspace is not held by the blocked client
+        producer.send("SET other value\r\nGET other\r\n").await?;
+        assert_eq!(
+            producer.receive().await?,
+            RespDataType::simple_strings("OK")
+        );
+        assert_eq!(
+            producer.receive().await?,
+            RespDataType::bulk_strings("value")
+        );
+
+        producer.send("RPUSH jobs:low job\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(1));
+        assert_eq!(worker.receive().await?, bulks(&["jobs:low", "job"]));
+        assert_eq!(server.db.lock().await.blocked_len(), 0);
+
+        // served right away when there is something to pop
+        producer.send("RPUSH jobs:high a\r\n").await?;
+        producer.receive().await?;
+        worker.send("BRPOP jobs:low jobs:high 0\r\n").await?;
+        assert_eq!(worker.receive().await?, bulks(&["jobs:high", "a"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replies_are_sent_before_blocking() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        worker.send("SET k v\r\nBLPOP q 0\r\n").await?;
+        assert_eq!(worker.receive().await?, RespDataType::simple_strings("OK"));
+        producer.send("RPUSH q a\r\n").await?;
+        producer.receive().await?;
+        assert_eq!(worker.receive().await?, bulks(&["q", "a"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_clients_stop_waiting() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        worker.send("BLPOP q 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        drop(worker);
+        wait_blocked(&server, 0).await;
+
+        producer.send("RPUSH q a\r\nLLEN q\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(1));
+        assert_eq!(producer.receive().await?, RespDataType::integers(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blpop_is_served_by_rename_and_copy() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        producer.send("RPUSH src a\r\nRPUSH other b\r\n").await?;
+        producer.receive().await?;
+        producer.receive().await?;
+
+        worker.send("BLPOP dst 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        producer.send("RENAME src dst\r\n").await?;
+        producer.receive().await?;
+        assert_eq!(worker.receive().await?, bulks(&["dst", "a"]));
+
+        worker.send("BLPOP dst 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        producer.send("COPY other dst\r\n").await?;
+        producer.receive().await?;
+        assert_eq!(worker.receive().await?, bulks(&["dst", "b"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blocked_clients_are_served_in_order() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut first = Client::connect(&server);
+        let mut second = Client::connect(&server);
+        let mut third = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        first.send("BLPOP q 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        second.send("BLPOP q 0\r\n").await?;
+        wait_blocked(&server, 2).await;
+        third.send("BLPOP q 0\r\n").await?;
+        wait_blocked(&server, 3).await;
+
+        producer.send("RPUSH q a b\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(2));
+        assert_eq!(first.receive().await?, bulks(&["q", "a"]));
+        assert_eq!(second.receive().await?, bulks(&["q", "b"]));
+        assert_eq!(server.db.lock().await.blocked_len(), 1);
+
+        producer.send("LPUSH q c\r\n").await?;
+        assert_eq!(third.receive().await?, bulks(&["q", "c"]));
+        producer.send("LLEN q\r\n").await?;
+        producer.receive().await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blocking_timeouts() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+
+        let start = Instant::now();
+        client.send("BLPOP q 0.05\r\n").await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        client.send("BLMOVE q d LEFT LEFT 0.01\r\n").await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_bulk_strings());
+        client.send("BLMPOP 0.01 2 q r LEFT COUNT 2\r\n").await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        assert_eq!(server.db.lock().await.blocked_len(), 0);
+
+        client.send("BLPOP q -1\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR timeout is negative")
+        );
+        client.send("BLPOP q soon\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR timeout is not a float or out of range")
+        );
+        client.send("BLMPOP 0 0 q LEFT\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR numkeys should be greater than 0")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blmove_chains_to_other_blocked_clients() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut mover = Client::connect(&server);
+        let mut consumer = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        mover
+            .send("BLMOVE pending processing RIGHT LEFT 0\r\n")
+            .await?;
+        wait_blocked(&server, 1).await;
+        consumer
+            .send("BLMPOP 0 1 processing LEFT COUNT 5\r\n")
+            .await?;
+        wait_blocked(&server, 2).await;
+
+        producer.send("LPUSH pending job\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(1));
+        assert_eq!(mover.receive().await?, RespDataType::bulk_strings("job"));
+        assert_eq!(
+            consumer.receive().await?,
+            RespDataType::arrays(vec![
+                RespDataType::bulk_strings("processing"),
+                bulks(&["job"]),
+            ])
+        );
+        producer.send("EXISTS pending processing\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bzpopmin_is_served_by_zadd() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        worker.send("BZPOPMIN tasks 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        producer.send("ZADD tasks 2 later 1 sooner\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::integers(2));
+        assert_eq!(worker.receive().await?, bulks(&["tasks", "sooner", "1"]));
+
+        worker
+            .send("BZPOPMAX tasks 0\r\nBZPOPMAX tasks 0.01\r\n")
+            .await?;
+        assert_eq!(worker.receive().await?, bulks(&["tasks", "later", "2"]));
+        assert_eq!(worker.receive().await?, RespDataType::empty_arrays());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xread_block_is_served_by_xadd() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut first = Client::connect(&server);
+        let mut second = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        producer.send("XADD events 1 type old\r\n").await?;
+        producer.receive().await?;
+        // `$` is the last ID when the reader blocks, so the old entry is not read
+        first.send("XREAD BLOCK 0 STREAMS events $\r\n").await?;
+        wait_blocked(&server, 1).await;
+        second
+            .send("XREAD BLOCK 0 STREAMS other events 0 5\r\n")
+            .await?;
+        wait_blocked(&server, 2).await;
+
+        producer.send("XADD events 2 type new\r\n").await?;
+        assert_eq!(producer.receive().await?, RespDataType::bulk_strings("2-0"));
+        let entries = RespDataType::arrays(vec![
+            RespDataType::bulk_strings("events"),
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("2-0"),
+                bulks(&["type", "new"]),
+            ])]),
+        ]);
+        assert_eq!(first.receive().await?, RespDataType::arrays(vec![entries]));
+        // the reader after a later ID keeps waiting
+        assert_eq!(server.db.lock().await.blocked_len(), 1);
+        producer.send("XADD events 6 type newer\r\n").await?;
+        producer.receive().await?;
+        let reply = second.receive().await?;
+        assert_eq!(
+            reply,
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("events"),
+                RespDataType::arrays(vec![RespDataType::arrays(vec![
+                    RespDataType::bulk_strings("6-0"),
+                    bulks(&["type", "newer"]),
+                ])]),
+            ])])
+        );
+
+        first.send("XREAD BLOCK 10 STREAMS events $\r\n").await?;
+        assert_eq!(first.receive().await?, RespDataType::empty_arrays());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_xreadgroup_block_is_served_by_xadd() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut alice = Client::connect(&server);
+        let mut bob = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        producer
+            .send("XGROUP CREATE jobs workers $ MKSTREAM\r\n")
+            .await?;
+        producer.receive().await?;
+        alice
+            .send("XREADGROUP GROUP workers alice BLOCK 0 STREAMS jobs >\r\n")
+            .await?;
+        wait_blocked(&server, 1).await;
+        bob.send("XREADGROUP GROUP workers bob BLOCK 0 STREAMS jobs >\r\n")
+            .await?;
+        wait_blocked(&server, 2).await;
+
+        // each entry goes to one consumer of the group only
+        producer.send("XADD jobs 1 job a\r\n").await?;
+        producer.receive().await?;
+        let delivered = |id: &str, job: &'static str| {
+            RespDataType::arrays(vec![RespDataType::arrays(vec![
+                RespDataType::bulk_strings("jobs"),
+                RespDataType::arrays(vec![RespDataType::arrays(vec![
+                    RespDataType::bulk_strings(id.to_owned()),
+                    bulks(&["job", job]),
+                ])]),
+            ])])
+        };
+        assert_eq!(alice.receive().await?, delivered("1-0", "a"));
+        assert_eq!(server.db.lock().await.blocked_len(), 1);
+        producer.send("XADD jobs 2 job b\r\n").await?;
+        producer.receive().await?;
+        assert_eq!(bob.receive().await?, delivered("2-0", "b"));
+
+        alice
+            .send("XREADGROUP GROUP workers alice BLOCK 10 STREAMS jobs >\r\n")
+            .await?;
+        assert_eq!(alice.receive().await?, RespDataType::empty_arrays());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multi_exec() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+        let mut other = Client::connect(&server);
+
+        client
+            .send("MULTI\r\nSET n 1\r\nINCR n\r\nLPUSH n a\r\nBLPOP empty 0\r\n")
+            .await?;
+        assert_eq!(client.receive().await?, RespDataType::simple_strings("OK"));
+        for _ in 0..4 {
+            assert_eq!(
+                client.receive().await?,
+                RespDataType::simple_strings("QUEUED")
+            );
+        }
+        // nothing runs until EXEC
+        other.send("GET n\r\n").await?;
+        assert_eq!(other.receive().await?, RespDataType::empty_bulk_strings());
+
+        client.send("MULTI\r\nEXEC\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR MULTI calls can not be nested")
+        );
+        // errors at run time do not stop the other commands, and blocking commands
+        // do not wait
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::arrays(vec![
+                RespDataType::simple_strings("OK"),
+                RespDataType::integers(2),
+                RespDataType::errors(
+                    "WRONGTYPE Operation against a key holding the wrong kind of value"
+                ),
+                RespDataType::empty_arrays(),
+            ])
+        );
+
+        client
+            .send("MULTI\r\nSET n 3\r\nDISCARD\r\nGET n\r\n")
+            .await?;
+        for reply in &["OK", "QUEUED", "OK"] {
+            assert_eq!(
+                client.receive().await?,
+                RespDataType::simple_strings(*reply)
+            );
+        }
+        assert_eq!(client.receive().await?, RespDataType::bulk_strings("2"));
+
+        client.send("EXEC\r\nDISCARD\r\n").await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR EXEC without MULTI")
+        );
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR DISCARD without MULTI")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queued_expire_counts_from_exec() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+
+        client.send("SET k v\r\nHSET h f v\r\n").await?;
+        assert_eq!(client.receive().await?, RespDataType::simple_strings("OK"));
+        assert_eq!(client.receive().await?, RespDataType::integers(1));
+        client
+            .send("MULTI\r\nPEXPIRE k 300\r\nHPEXPIRE h 300 FIELDS 1 f\r\n")
+            .await?;
+        for reply in &["OK", "QUEUED", "QUEUED"] {
+            assert_eq!(
+                client.receive().await?,
+                RespDataType::simple_strings(*reply)
+            );
+        }
+        delay_for(Duration::from_millis(400)).await;
+        client
+            .send("EXEC\r\nPTTL k\r\nHPTTL h FIELDS 1 f\r\n")
+            .await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::arrays(vec![
+                RespDataType::integers(1),
+                RespDataType::arrays(vec![RespDataType::integers(1)]),
+            ])
+        );
+        match client.receive().await? {
+            RespDataType::Integers(ttl) => assert!(200 < ttl && ttl <= 300, "{}", ttl),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        match client.receive().await? {
+            RespDataType::Arrays(Some(ttls)) => match ttls[..] {
+                [RespDataType::Integers(ttl)] => assert!(200 < ttl && ttl <= 300, "{}", ttl),
+                _ => panic!("unexpected reply {:?}", ttls),
+            },
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exec_aborts_after_queuing_errors() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+
+        client
+            .send("MULTI\r\nSET n 1\r\nSET n\r\nEXEC\r\nGET n\r\n")
+            .await?;
+        assert_eq!(client.receive().await?, RespDataType::simple_strings("OK"));
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::simple_strings("QUEUED")
+        );
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR wrong number of arguments for 'set' command")
+        );
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("EXECABORT Transaction discarded because of previous errors.")
+        );
+        assert_eq!(client.receive().await?, RespDataType::empty_bulk_strings());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exec_is_atomic_for_blocked_clients() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut worker = Client::connect(&server);
+        let mut producer = Client::connect(&server);
+
+        worker.send("BLPOP jobs 0\r\n").await?;
+        wait_blocked(&server, 1).await;
+        producer
+            .send("MULTI\r\nRPUSH jobs a\r\nLPOP jobs\r\nRPUSH jobs b\r\nEXEC\r\n")
+            .await?;
+        for _ in 0..4 {
+            producer.receive().await?;
+        }
+        assert_eq!(
+            producer.receive().await?,
+            RespDataType::arrays(vec![
+                RespDataType::integers(1),
+                RespDataType::bulk_strings("a"),
+                RespDataType::integers(1),
+            ])
+        );
+        // the blocked client only sees the keyspace once the transaction is over
+        assert_eq!(worker.receive().await?, bulks(&["jobs", "b"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_aborts_exec_after_a_write() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+        let mut other = Client::connect(&server);
+
+        client.send("SET stock 10\r\nWATCH stock\r\n").await?;
+        client.receive().await?;
+        assert_eq!(client.receive().await?, RespDataType::simple_strings("OK"));
+        other.send("DECRBY stock 3\r\n").await?;
+        assert_eq!(other.receive().await?, RespDataType::integers(7));
+
+        client
+            .send("MULTI\r\nWATCH stock\r\nSET stock 9\r\nEXEC\r\nGET stock\r\n")
+            .await?;
+        client.receive().await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::errors("ERR WATCH inside MULTI is not allowed")
+        );
+        client.receive().await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        assert_eq!(client.receive().await?, RespDataType::bulk_strings("7"));
+
+        // EXEC forgets the watched keys, whatever comes of it
+        other.send("SET stock 5\r\n").await?;
+        other.receive().await?;
+        client
+            .send("WATCH stock missing\r\nMULTI\r\nDECRBY stock 1\r\nEXEC\r\n")
+            .await?;
+        for _ in 0..3 {
+            client.receive().await?;
+        }
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::arrays(vec![RespDataType::integers(4)])
+        );
+
+        // so does UNWATCH, and writes by the watching client count too
+        client
+            .send("WATCH stock\r\nUNWATCH\r\nSET stock 1\r\nMULTI\r\nEXEC\r\n")
+            .await?;
+        for _ in 0..4 {
+            client.receive().await?;
+        }
+        assert_eq!(client.receive().await?, RespDataType::arrays(vec![]));
+        client
+            .send("WATCH stock\r\nSET stock 2\r\nMULTI\r\nEXEC\r\n")
+            .await?;
+        for _ in 0..3 {
+            client.receive().await?;
+        }
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_ignores_writes_that_change_nothing() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+        let mut other = Client::connect(&server);
+
+        client
+            .send("SADD s a\r\nHSET h f v\r\nRPUSH l a\r\nWATCH s h l\r\n")
+            .await?;
+        for _ in 0..4 {
+            client.receive().await?;
+        }
+        other
+            .send("SREM s missing\r\nHDEL h missing\r\nLREM l 0 missing\r\nSADD s a\r\n")
+            .await?;
+        for _ in 0..4 {
+            other.receive().await?;
+        }
+        client.send("MULTI\r\nSCARD s\r\nEXEC\r\n").await?;
+        client.receive().await?;
+        client.receive().await?;
+        assert_eq!(
+            client.receive().await?,
+            RespDataType::arrays(vec![RespDataType::integers(1)])
+        );
+
+        client.send("WATCH h\r\n").await?;
+        client.receive().await?;
+        other.send("HDEL h f\r\n").await?;
+        other.receive().await?;
+        client.send("MULTI\r\nSCARD s\r\nEXEC\r\n").await?;
+        client.receive().await?;
+        client.receive().await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watch_sees_expiry_and_flush() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        let mut client = Client::connect(&server);
+        let mut other = Client::connect(&server);
+
+        client
+            .send("SET session x PX 20\r\nWATCH session\r\n")
+            .await?;
+        client.receive().await?;
+        client.receive().await?;
+        delay_for(Duration::from_millis(40)).await;
+        client.send("MULTI\r\nGET session\r\nEXEC\r\n").await?;
+        client.receive().await?;
+        client.receive().await?;
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+
+        // only the watched keys that existed change when flushed
+        client.send("SET a 1\r\nWATCH a\r\n").await?;
+        client.receive().await?;
+        client.receive().await?;
+        other.send("WATCH b\r\n").await?;
+        other.receive().await?;
+        client.send("FLUSHALL\r\n").await?;
+        client.receive().await?;
+        for watching in [&mut client, &mut other].iter_mut() {
+            watching.send("MULTI\r\nSET c 1\r\nEXEC\r\n").await?;
+            watching.receive().await?;
+            watching.receive().await?;
+        }
+        assert_eq!(client.receive().await?, RespDataType::empty_arrays());
+        assert_eq!(
+            other.receive().await?,
+            RespDataType::arrays(vec![RespDataType::simple_strings("OK")])
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_active_expire_reclaims_keys_nobody_looks_up() -> Result<()> {
+        let server = Arc::new(RedisServer::new());
+        tokio::spawn(server.clone().active_expire());
+        let mut client = Client::connect(&server);
+
+        for i in 0..100 {
+            client.send(format!("SET key:{} x PX 10\r\n", i)).await?;
+            client.receive().await?;
+        }
+        client.send("SET kept x\r\n").await?;
+        client.receive().await?;
+        assert_eq!(server.db.lock().await.volatile_len(), 100);
+
+        // a few cycles of the task, without a single command touching the keys again
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.db.lock().await.volatile_len() > 0 {
+            assert!(Instant::now() < deadline, "expired keys were not reclaimed");
+            delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.db.lock().await.len(), 1);
+        Ok(())
+    }
+}
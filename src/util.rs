@@ -1,12 +1,345 @@
-use std::{error, future::Future, pin::Pin};
+use std::{
+    cell::Cell,
+    collections::hash_map::RandomState,
+    error,
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    pin::Pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::Instant;
 
-pub fn strip_trailing_newline(input: &str) -> &str {
-    input
-        .strip_suffix("\r\n")
-        .or(input.strip_suffix("\n"))
-        .unwrap_or(input)
+/// Formats a double the way redis replies with it: `inf`/`-inf`/`nan` for the special
+/// values, the shortest representation that round-trips otherwise.
+pub fn format_double(num: f64) -> String {
+    if num.is_nan() {
+        "nan".to_owned()
+    } else if num.is_infinite() {
+        if num > 0.0 { "inf" } else { "-inf" }.to_owned()
+    } else if num != 0.0 && (num.abs() >= 1e17 || num.abs() < 1e-5) {
+        let str = format!("{:e}", num);
+        match str.find("e") {
+            Some(i) if !str[i + 1..].starts_with('-') => {
+                format!("{}e+{}", &str[..i], &str[i + 1..])
+            }
+            _ => str,
+        }
+    } else {
+        num.to_string()
+    }
+}
+
+/// Parses a base 10 signed 64 bit integer the way redis does: no leading `+`, no
+/// whitespace and no leading zeroes.
+pub fn parse_integer(input: &[u8]) -> Option<i64> {
+    let digits = input.strip_prefix(b"-").unwrap_or(input);
+    match digits {
+        [] => None,
+        [b'0', _, ..] => None,
+        _ if input == b"-0" => None,
+        _ if !digits.iter().all(u8::is_ascii_digit) => None,
+        _ => std::str::from_utf8(input).ok()?.parse().ok(),
+    }
+}
+
+/// Parses a float the way redis does for `INCRBYFLOAT` and friends: no surrounding
+/// whitespace, and never NaN.
+pub fn parse_float(input: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(input).ok()?;
+    if s.is_empty() || s.starts_with(char::is_whitespace) || s.ends_with(char::is_whitespace) {
+        return None;
+    }
+    let value = match s.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" | "infinity" | "+infinity" => f64::INFINITY,
+        "-inf" | "-infinity" => f64::NEG_INFINITY,
+        _ => s.parse::<f64>().ok()?,
+    };
+    Some(value).filter(|x| !x.is_nan())
+}
+
+/// Converts a unix timestamp in milliseconds into a deadline on the monotonic clock.
+pub fn instant_from_unix_millis(unix_millis: u64) -> Instant {
+    let target = UNIX_EPOCH + Duration::from_millis(unix_millis);
+    let now = Instant::now();
+    match target.duration_since(SystemTime::now()) {
+        Ok(ahead) => now + ahead,
+        Err(behind) => now.checked_sub(behind.duration()).unwrap_or(now),
+    }
+}
+
+thread_local! {
+    static RANDOM_STATE: Cell<u64> = Cell::new({
+        // seeded from the per-process random keys of the std hasher
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        hasher.finish() | 1
+    });
+}
+
+/// A fast, non-cryptographic random number (xorshift64*), for sampling keys and members.
+pub fn random() -> u64 {
+    RANDOM_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// The current time as a unix timestamp in milliseconds.
+pub fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_millis() as i64)
+}
+
+/// Converts a deadline on the monotonic clock into a unix timestamp in milliseconds.
+///
+/// Rounded to the nearest millisecond, so that converting a timestamp back and forth
+/// gives the same timestamp.
+pub fn unix_millis_from_instant(instant: Instant) -> i64 {
+    let now = Instant::now();
+    let unix_micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_micros() as i64);
+    let micros = if instant >= now {
+        unix_micros + (instant - now).as_micros() as i64
+    } else {
+        unix_micros - (now - instant).as_micros() as i64
+    };
+    (micros + 500).div_euclid(1000)
+}
+
+/// Matches a string against a glob-style pattern, following `stringmatchlen` in redis:
+/// `*`, `?`, `[...]` with ranges and `^` negation, and `\` escaping.
+///
+/// Every other token matches a single character, so when what follows a `*` does not
+/// match, only the last `*` needs to take one more character and try again, which keeps
+/// this in O(pattern × string) however many `*` there are.
+pub fn glob_match(pattern: &[u8], string: &[u8], nocase: bool) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // where to go back to, just past the last `*` with one more character taken by it
+    let mut star = None;
+    while p < pattern.len() || s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            while pattern.get(p + 1) == Some(&b'*') {
+                p += 1;
+            }
+            if p + 1 == pattern.len() {
+                return true;
+            }
+            p += 1;
+            star = Some((p, s + 1));
+            continue;
+        }
+        if let Some(next) = string
+            .get(s)
+            .and_then(|c| glob_token(pattern, p, *c, nocase))
+        {
+            p = next;
+            s += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_s)) if star_s <= string.len() => {
+                p = star_p;
+                s = star_s;
+                star = Some((star_p, star_s + 1));
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Matches a character against the token of a glob-style pattern at `p`, other than `*`,
+/// returning where the next token starts if it does.
+fn glob_token(pattern: &[u8], mut p: usize, c: u8, nocase: bool) -> Option<usize> {
+    let fold = |c: u8| if nocase { c.to_ascii_lowercase() } else { c };
+    let c = fold(c);
+    match *pattern.get(p)? {
+        b'?' => {}
+        b'[' => {
+            p += 1;
+            let negate = pattern.get(p) == Some(&b'^');
+            if negate {
+                p += 1;
+            }
+            let mut matched = false;
+            loop {
+                match pattern.get(p) {
+                    // an unterminated class ends with the pattern
+                    None => {
+                        p -= 1;
+                        break;
+                    }
+                    Some(b']') => break,
+                    Some(b'\\') if p + 1 < pattern.len() => {
+                        p += 1;
+                        matched |= fold(pattern[p]) == c;
+                    }
+                    Some(&start) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                        let (start, end) = (fold(start), fold(pattern[p + 2]));
+                        let (start, end) = (start.min(end), start.max(end));
+                        matched |= start <= c && c <= end;
+                        p += 2;
+                    }
+                    Some(&other) => matched |= fold(other) == c,
+                }
+                p += 1;
+            }
+            if matched == negate {
+                return None;
+            }
+        }
+        token => {
+            let token = if token == b'\\' && p + 1 < pattern.len() {
+                p += 1;
+                pattern[p]
+            } else {
+                token
+            };
+            if fold(token) != c {
+                return None;
+            }
+        }
+    }
+    Some(p + 1)
 }
 
 pub(crate) type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a + Send + Sync>>;
 pub(crate) type GenericError = Box<dyn error::Error + Send + Sync>;
 pub(crate) type Result<T> = std::result::Result<T, GenericError>;
+
+/// Splits an inline command line into arguments, following the quoting rules of
+/// `sdssplitargs` in Redis: tokens are separated by whitespace, `"..."` supports
+/// the usual backslash escapes (including `\xHH`), and `'...'` only escapes `\'`.
+pub fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>> {
+    fn hex_digit(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|x| x as u8)
+    }
+
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double_quotes = false;
+        let mut in_single_quotes = false;
+        loop {
+            let c = line.get(i).copied();
+            if in_double_quotes {
+                match c {
+                    None => return Err("unbalanced quotes in request".into()),
+                    Some(b'\\') if i + 3 < line.len() && line[i + 1] == b'x' => {
+                        match (hex_digit(line[i + 2]), hex_digit(line[i + 3])) {
+                            (Some(hi), Some(lo)) => {
+                                current.push(hi * 16 + lo);
+                                i += 3;
+                            }
+                            _ => current.push(b'\\'),
+                        }
+                    }
+                    Some(b'\\') if i + 1 < line.len() => {
+                        i += 1;
+                        current.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    Some(b'"') => {
+                        // the closing quote must be followed by a space or nothing at all
+                        if matches!(line.get(i + 1), Some(x) if !x.is_ascii_whitespace()) {
+                            return Err("unbalanced quotes in request".into());
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => current.push(c),
+                }
+            } else if in_single_quotes {
+                match c {
+                    None => return Err("unbalanced quotes in request".into()),
+                    Some(b'\\') if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        current.push(b'\'');
+                    }
+                    Some(b'\'') => {
+                        if matches!(line.get(i + 1), Some(x) if !x.is_ascii_whitespace()) {
+                            return Err("unbalanced quotes in request".into());
+                        }
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => current.push(c),
+                }
+            } else {
+                match c {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double_quotes = true,
+                    Some(b'\'') => in_single_quotes = true,
+                    Some(c) => current.push(c),
+                }
+            }
+            i += 1;
+        }
+        args.push(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_glob_match() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*", "", true),
+            ("h?llo", "hello", true),
+            ("h*llo", "heeeello", true),
+            ("h*llo", "hellos", false),
+            ("*llo*", "hello world", true),
+            ("h[ae]llo", "hallo", true),
+            ("h[^e]llo", "hello", false),
+            ("h[a-b]llo", "hbllo", true),
+            ("h\\*llo", "h*llo", true),
+            ("h\\*llo", "hello", false),
+            ("a*b*c", "aXbYbZc", true),
+            ("a*b*c", "aXbYbZ", false),
+            ("h[ab", "hb", true),
+        ];
+        for (pattern, string, matches) in cases {
+            assert_eq!(
+                glob_match(pattern.as_bytes(), string.as_bytes(), false),
+                *matches,
+                "{} against {}",
+                pattern,
+                string
+            );
+        }
+        assert!(glob_match(b"H[A-B]LLO", b"hbllo", true));
+    }
+
+    #[test]
+    fn test_glob_match_is_not_exponential() {
+        let pattern = "*a".repeat(100) + "b";
+        let string = "a".repeat(1000);
+        let start = Instant::now();
+        assert!(!glob_match(pattern.as_bytes(), string.as_bytes(), false));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}